@@ -9,7 +9,9 @@ use crate::Error;
 use crate::sample::Sample;
 use crate::sample::SampleBuffer;
 use crate::types::SignalStrength;
+use crate::window_kind::Symmetry;
 use crate::window_kind::WindowKind;
+use crate::window_metrics::WindowMetrics;
 
 #[derive(Clone)]
 pub struct Analyzer {
@@ -17,41 +19,92 @@ pub struct Analyzer {
     fft: Arc<dyn FFT<Sample>>,
 
     // FFT window to use for smoothing.
+    window_kind: WindowKind,
+    symmetry: Symmetry,
     window: Vec<f32>,
 
     // Intermediate FFT buffers.
     input_l: Vec<Complex<Sample>>,
     input_r: Vec<Complex<Sample>>,
-    output: Vec<Complex<Sample>>,
+    output_l: Vec<Complex<Sample>>,
+    output_r: Vec<Complex<Sample>>,
 
     spectrum_l: Vec<SignalStrength>,
     spectrum_r: Vec<SignalStrength>,
+
+    // Applied to each bin of the spectrum, to correct for the window.
+    amplitude_correction: bool,
+    scale: SignalStrength,
 }
 
 impl Analyzer {
+    /// Creates an analyzer using the periodic form of the given window.
     pub fn new(len: usize, window_kind: WindowKind) -> Self {
         let fft = FFTplanner::new(false).plan_fft(len);
 
-        let window = window_kind.generate(len).into_iter().map(|w| w as f32).collect();
+        let symmetry = Symmetry::Periodic;
+        let window = Self::generate_window(len, window_kind, symmetry);
 
         let input_l = vec![Complex::zero(); len];
         let input_r = vec![Complex::zero(); len];
-        let output = vec![Complex::zero(); len];
+        let output_l = vec![Complex::zero(); len];
+        let output_r = vec![Complex::zero(); len];
 
         let spectrum_l = vec![0.0; len];
         let spectrum_r = vec![0.0; len];
 
         Analyzer {
             fft,
+            window_kind,
+            symmetry,
             window,
             input_l,
             input_r,
-            output,
+            output_l,
+            output_r,
             spectrum_l,
             spectrum_r,
+            amplitude_correction: false,
+            scale: 1.0,
         }
     }
 
+    fn generate_window(len: usize, window_kind: WindowKind, symmetry: Symmetry) -> Vec<f32> {
+        window_kind.generate_with_symmetry(len, symmetry).into_iter().map(|w| w as f32).collect()
+    }
+
+    // Regenerates the window and the scale applied to the spectrum after a setting changes.
+    fn update_window(&mut self) {
+        self.window = Self::generate_window(self.len(), self.window_kind, self.symmetry);
+
+        self.scale =
+            if self.amplitude_correction {
+                let window: Vec<f64> = self.window.iter().map(|&w| w as f64).collect();
+                // An empty window has nothing to correct.
+                let coherent_gain = WindowMetrics::from_window(&window).map_or(1.0, |m| m.coherent_gain);
+
+                // The spectrum holds powers, so the gain is squared.
+                (1.0 / (coherent_gain * coherent_gain)) as SignalStrength
+            }
+            else { 1.0 }
+        ;
+    }
+
+    /// Uses the symmetric or periodic form of the window.
+    pub fn with_symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self.update_window();
+        self
+    }
+
+    /// Scales the spectrum to make up for the coherent gain of the window,
+    /// so that a tone reads at the same level whichever window is used.
+    pub fn with_amplitude_correction(mut self) -> Self {
+        self.amplitude_correction = true;
+        self.update_window();
+        self
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.fft.len()
@@ -67,20 +120,25 @@ impl Analyzer {
             **ir = Complex::new(xr * w, 0.0);
         }
 
-        self.fft.process(&mut self.input_l, &mut self.output);
+        self.fft.process(&mut self.input_l, &mut self.output_l);
 
-        for (s, o) in self.spectrum_l.iter_mut().zip(&self.output) {
-            *s = o.norm_sqr();
+        for (s, o) in self.spectrum_l.iter_mut().zip(&self.output_l) {
+            *s = o.norm_sqr() * self.scale;
         }
 
-        self.fft.process(&mut self.input_r, &mut self.output);
+        self.fft.process(&mut self.input_r, &mut self.output_r);
 
-        for (s, o) in self.spectrum_r.iter_mut().zip(&self.output) {
-            *s = o.norm_sqr();
+        for (s, o) in self.spectrum_r.iter_mut().zip(&self.output_r) {
+            *s = o.norm_sqr() * self.scale;
         }
 
         Ok((&self.spectrum_l, &self.spectrum_r))
     }
+
+    /// The left and right complex spectra from the last call to `analyze`, without any amplitude correction.
+    pub fn complex_spectra(&self) -> (&[Complex<Sample>], &[Complex<Sample>]) {
+        (&self.output_l, &self.output_r)
+    }
 }
 
 #[cfg(test)]
@@ -170,4 +228,62 @@ mod tests {
         //     println!("{}: {} ({} Hz)", n, ss, n as f32 * fft_bin_size);
         // }
     }
+
+    #[test]
+    fn test_amplitude_correction() {
+        const FFT_LEN: usize = 1024;
+        const BIN: usize = 10;
+
+        // A sine wave of amplitude A centred on a bin reads as (A N / 2)^2 with a rectangular window,
+        // and the generated test waves have an amplitude of 0.25.
+        let expected = (0.25 * FFT_LEN as SignalStrength / 2.0).powi(2);
+
+        let inputs = vec![
+            WindowKind::Rectangular,
+            WindowKind::Hanning,
+            WindowKind::Hamming,
+            WindowKind::BlackmanHarris,
+            WindowKind::FlatTop,
+            WindowKind::Kaiser(5.0),
+        ];
+
+        for window_kind in inputs {
+            let mut analyzer = Analyzer::new(FFT_LEN, window_kind).with_amplitude_correction();
+
+            // Use a sampling rate that puts the tone right in the middle of a bin.
+            let samples = SampleBuffer::from(TestUtil::generate_wave_samples(FFT_LEN * 43, (BIN * 43) as Frequency, FFT_LEN));
+            let (spectrum, _) = analyzer.analyze(&samples).unwrap();
+
+            let produced = spectrum[BIN];
+            println!("{:?}: {}, {}", window_kind, expected, produced);
+            assert!((produced / expected - 1.0).abs() < 0.01);
+        }
+
+        // A flat-top window barely loses any level even when the tone falls halfway between bins.
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::FlatTop).with_amplitude_correction();
+        let samples = SampleBuffer::from(TestUtil::generate_wave_samples(FFT_LEN * 2, (BIN * 2 + 1) as Frequency, FFT_LEN));
+        let (spectrum, _) = analyzer.analyze(&samples).unwrap();
+
+        let produced = spectrum[BIN].max(spectrum[BIN + 1]);
+        assert!((produced / expected - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_symmetry() {
+        const FFT_LEN: usize = 256;
+        const BIN: usize = 10;
+
+        let samples = SampleBuffer::from(TestUtil::generate_wave_samples(FFT_LEN * 43, (BIN * 43) as Frequency, FFT_LEN));
+
+        // A periodic Hann window spreads a tone centred on a bin over just that bin and its two neighbours.
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let (spectrum, _) = analyzer.analyze(&samples).unwrap();
+        assert!((spectrum[BIN + 1] / spectrum[BIN] - 0.25).abs() < 1e-3);
+        assert!(spectrum[BIN + 2] / spectrum[BIN] < 1e-6);
+
+        // The symmetric window leaks further.
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning).with_symmetry(Symmetry::Symmetric);
+        let (spectrum, _) = analyzer.analyze(&samples).unwrap();
+        assert!(spectrum[BIN + 2] / spectrum[BIN] > 1e-6);
+    }
 }
@@ -0,0 +1,200 @@
+//! Reads audio files from disk as a stream of stereo sample frames.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::path::Path;
+
+use hound::SampleFormat as WavSampleFormat;
+use hound::WavReader;
+use hound::WavIntoSamples;
+
+use crate::Error;
+use crate::sample::Sample;
+#[cfg(feature = "decode")] use crate::decode::Decoder;
+
+type WavFileReader = BufReader<File>;
+
+enum Samples {
+    Int(WavIntoSamples<WavFileReader, i32>, Sample),
+    Float(WavIntoSamples<WavFileReader, f32>),
+    #[cfg(feature = "decode")] Decoded(Decoder),
+}
+
+impl Iterator for Samples {
+    type Item = Result<Sample, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Samples::Int(samples, scale) => samples.next().map(|r| r.map(|s| s as Sample / *scale).map_err(Error::from)),
+            Samples::Float(samples) => samples.next().map(|r| r.map_err(Error::from)),
+            #[cfg(feature = "decode")] Samples::Decoded(decoder) => decoder.next(),
+        }
+    }
+}
+
+/// An audio file opened for reading.
+/// Iterating over it yields stereo sample frames; mono files have their single channel duplicated,
+/// and files with more than two channels only have their first two channels read.
+pub struct AudioFile {
+    sample_rate: usize,
+    num_channels: usize,
+    len: Option<usize>,
+    samples: Samples,
+    error: Option<Error>,
+}
+
+impl AudioFile {
+    /// Opens an audio file for reading.
+    /// WAV files are always supported, while FLAC, Ogg Vorbis and MP3 files require the `decode` feature.
+    /// WAV files are recognized by their header, whatever their extension.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        if Self::is_wav(path)? { Self::open_wav(path) }
+        else { Self::open_compressed(path) }
+    }
+
+    fn is_wav(path: &Path) -> Result<bool, Error> {
+        let mut header = [0u8; 4];
+
+        // Files too short to hold a header are left for the decoder to reject.
+        match File::open(path)?.read_exact(&mut header) {
+            Ok(()) => Ok(&header == b"RIFF"),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn open_wav(path: &Path) -> Result<Self, Error> {
+        let reader = WavReader::open(path)?;
+        let spec = reader.spec();
+
+        let num_channels = spec.channels as usize;
+        if !(num_channels > 0) { Err(Error::NumChannels(num_channels))? }
+
+        let sample_rate = spec.sample_rate as usize;
+        let len = Some(reader.duration() as usize);
+
+        let samples = match spec.sample_format {
+            WavSampleFormat::Int => {
+                let scale = (1u64 << (spec.bits_per_sample - 1)) as Sample;
+                Samples::Int(reader.into_samples(), scale)
+            },
+            WavSampleFormat::Float => Samples::Float(reader.into_samples()),
+        };
+
+        Ok(Self {
+            sample_rate,
+            num_channels,
+            len,
+            samples,
+            error: None,
+        })
+    }
+
+    #[cfg(feature = "decode")]
+    fn open_compressed(path: &Path) -> Result<Self, Error> {
+        let decoder = Decoder::open(path)?;
+
+        let num_channels = decoder.num_channels();
+        if !(num_channels > 0) { Err(Error::NumChannels(num_channels))? }
+
+        Ok(Self {
+            sample_rate: decoder.sample_rate(),
+            num_channels,
+            len: decoder.len(),
+            samples: Samples::Decoded(decoder),
+            error: None,
+        })
+    }
+
+    #[cfg(not(feature = "decode"))]
+    fn open_compressed(_path: &Path) -> Result<Self, Error> {
+        Err(Error::Format("unsupported file type, compressed formats require the `decode` feature"))
+    }
+
+    /// The sampling rate of the file, in Hz.
+    #[inline]
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// The number of channels stored in the file.
+    #[inline]
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// The total number of frames in the file, if known ahead of time.
+    #[inline]
+    pub fn len(&self) -> Option<usize> {
+        self.len
+    }
+
+    /// The error that ended iteration early, if any.
+    #[inline]
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+
+    /// Reads the next frame, returning `None` once the end of the file is reached.
+    pub fn read_frame(&mut self) -> Option<Result<(Sample, Sample), Error>> {
+        let l = match self.samples.next()? {
+            Ok(s) => s,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let r = if self.num_channels > 1 {
+            match self.samples.next()? {
+                Ok(s) => s,
+                Err(e) => return Some(Err(e)),
+            }
+        } else { l };
+
+        // Skip over any remaining channels.
+        for _ in 2..self.num_channels {
+            if let Err(e) = self.samples.next()? { return Some(Err(e)) }
+        }
+
+        Some(Ok((l, r)))
+    }
+}
+
+impl Iterator for AudioFile {
+    type Item = (Sample, Sample);
+
+    /// Yields frames until the end of the file, or until a read error occurs.
+    /// In the latter case, the error can be retrieved with `error`.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_frame()? {
+            Ok(sample_pair) => Some(sample_pair),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_by_header() {
+        // A WAV file opens the same way without its extension.
+        let path = std::env::temp_dir().join(format!("voxane_{}_wav_without_extension.dat", std::process::id()));
+        std::fs::copy("wav/sin_440hz_44100hz_samp.wav", &path).unwrap();
+
+        let expected: Vec<_> = AudioFile::open("wav/sin_440hz_44100hz_samp.wav").unwrap().collect();
+        let audio_file = AudioFile::open(&path).unwrap();
+        assert_eq!(44100, audio_file.sample_rate());
+        assert_eq!(expected, audio_file.collect::<Vec<_>>());
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(Some(Error::Io(ErrorKind::NotFound)), AudioFile::open("wav/missing.wav").err());
+    }
+}
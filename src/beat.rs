@@ -5,6 +5,38 @@ use crate::sample::SampleBuffer;
 use crate::analyzer::Analyzer;
 use crate::window_kind::WindowKind;
 
+// Estimated tempos are folded by octaves into this range, in beats per minute.
+const MIN_TEMPO: f64 = 80.0;
+const MAX_TEMPO: f64 = MIN_TEMPO * 2.0;
+
+/// Estimates the tempo in beats per minute, given the timestamps of detected beats in seconds.
+/// The median interval between beats is used, so that the occasional missed or spurious beat is ignored.
+pub fn estimate_tempo(beat_timestamps: &[f64]) -> Option<f64> {
+    let mut intervals: Vec<f64> =
+        beat_timestamps
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .filter(|i| *i > 0.0)
+        .collect()
+    ;
+
+    if intervals.is_empty() { return None }
+
+    intervals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mid = intervals.len() / 2;
+    let median =
+        if intervals.len() % 2 == 0 { (intervals[mid - 1] + intervals[mid]) / 2.0 }
+        else { intervals[mid] }
+    ;
+
+    let mut tempo = 60.0 / median;
+    while tempo < MIN_TEMPO { tempo *= 2.0 }
+    while tempo >= MAX_TEMPO { tempo /= 2.0 }
+
+    Some(tempo)
+}
+
 pub struct Detector {
     decay: SignalStrength,
     trigger: SignalStrength,
@@ -68,8 +100,109 @@ impl Detector {
         self.volume
     }
 
-    pub fn is_beat(&mut self, samples: &SampleBuffer) -> bool {
-        // self.analyzer.analyze(samples);
-        false
+    /// Processes the latest window of samples, and returns true if it contains the onset of a beat.
+    /// The number of samples must match the FFT length this detector was created with.
+    pub fn is_beat(&mut self, samples: &SampleBuffer, sampling_rate: usize) -> Result<bool, Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let (lower_cutoff, upper_cutoff) = (self.lower_cutoff, self.upper_cutoff);
+        let (spectrum_l, spectrum_r) = self.analyzer.analyze(samples)?;
+
+        // Only the bins that fall within the cutoffs contribute to the volume.
+        let fft_bin_size = sampling_rate as Frequency / spectrum_l.len() as Frequency;
+        let volume: SignalStrength =
+            spectrum_l
+            .iter()
+            .zip(spectrum_r)
+            .enumerate()
+            .take(spectrum_l.len() / 2 + 1)
+            .filter(|(i, _)| {
+                let freq_bin = *i as Frequency * fft_bin_size;
+                lower_cutoff <= freq_bin && freq_bin < upper_cutoff
+            })
+            .map(|(_, (l, r))| (l + r) / 2.0)
+            .sum()
+        ;
+
+        let prev_volume = self.volume;
+        self.delta = (volume - prev_volume).max(0.0);
+        self.volume = volume;
+
+        // A beat is a sudden rise in volume that is large compared to recent rises,
+        // and loud compared to the recent floor.
+        let is_beat =
+            self.delta > 0.0
+            && self.volume > self.trigger * prev_volume
+            && self.delta > self.trigger * self.beat_delta
+            && self.volume > self.trigger * self.valley
+        ;
+
+        // The peak decays down towards the volume, and the valley creeps up towards the peak.
+        self.peak = self.volume.max(self.peak * self.decay);
+        self.valley = self.volume.min(self.valley + (self.peak - self.valley) * (1.0 - self.decay));
+        self.beat_delta = self.beat_delta * self.decay + self.delta * (1.0 - self.decay);
+
+        Ok(is_beat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::TestUtil;
+
+    const SAMPLES_PER_PERIOD: usize = 44100;
+    const FFT_LEN: usize = 1024;
+
+    #[test]
+    fn test_is_beat() {
+        let mut detector = Detector::new(10, 3, 2, 20, 200, FFT_LEN).unwrap();
+
+        let mut signal = vec![0.0; FFT_LEN * 4];
+        signal.extend(TestUtil::generate_wave_samples(SAMPLES_PER_PERIOD, 60.0, FFT_LEN * 8));
+
+        let produced: Vec<bool> =
+            signal
+            .chunks(FFT_LEN / 2)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|w| {
+                let samples = SampleBuffer::from([w[0], w[1]].concat());
+                detector.is_beat(&samples, SAMPLES_PER_PERIOD).unwrap()
+            })
+            .collect()
+        ;
+
+        // Silence never triggers, the onset of the tone does, and the steady tone afterwards does not.
+        let onset = produced.iter().position(|&b| b).unwrap();
+        assert!((6..=8).contains(&onset));
+        assert!(produced[onset + 2..].iter().all(|&b| !b));
+
+        assert!(detector.volume() > 0.0);
+        assert_eq!(Err(Error::SamplingRate(0)), detector.is_beat(&SampleBuffer::new(FFT_LEN), 0));
+    }
+
+    #[test]
+    fn test_estimate_tempo() {
+        let inputs_and_expected = vec![
+            (vec![], None),
+            (vec![1.0], None),
+            (vec![0.0, 0.5, 1.0, 1.5, 2.0], Some(120.0)),
+            // A missed beat does not throw off the estimate.
+            (vec![0.0, 0.5, 1.0, 2.0, 2.5, 3.0], Some(120.0)),
+            // Tempos outside of the range are folded back into it.
+            (vec![0.0, 1.0, 2.0, 3.0], Some(120.0)),
+            (vec![0.0, 0.2, 0.4, 0.6], Some(150.0)),
+            (vec![0.0, 0.6, 1.2, 1.8, 2.4], Some(100.0)),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let produced = estimate_tempo(&input);
+            assert_eq!(expected.is_some(), produced.is_some());
+            if let (Some(e), Some(p)) = (expected, produced) {
+                assert_approx_eq!(e, p);
+            }
+        }
     }
 }
@@ -0,0 +1,344 @@
+//! Terminal spectrum visualizer, drawing live band levels as a bar graph.
+
+use std::io::Write;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use voxane::analyzer::Analyzer;
+use voxane::audio_file::AudioFile;
+use voxane::buckets::Buckets;
+use voxane::listener::Listener;
+use voxane::offline::OfflineAnalyzer;
+use voxane::source::Pacing;
+use voxane::source::Source;
+use voxane::source::StreamSource;
+use voxane::types::Frequency;
+use voxane::types::SignalStrength;
+use voxane::visualizer::BarGraph;
+use voxane::window_kind::WindowKind;
+
+const USAGE: &str = "\
+Draws a live spectrum of an audio file, or of the default audio device, as a bar graph.
+
+USAGE:
+    voxane-viz [OPTIONS] [FILE]
+
+OPTIONS:
+    --sample-rate <HZ>    Sampling rate when listening to the audio device [default: 44100]
+    --fft-len <N>         FFT length, in samples [default: 2048]
+    --window <KIND>       Window function: blackman, blackman-harris, flat-top, gaussian[:SIGMA], hamming,
+                          hanning, kaiser[:BETA], nuttall, rectangular, sine, triangular, tukey[:ALPHA]
+                          [default: hanning]
+    --lower <HZ>          Lower cutoff frequency [default: 20]
+    --upper <HZ>          Upper cutoff frequency [default: 20000]
+    --fps <N>             Frames drawn per second [default: 30]
+    --bar-width <N>       Columns per bar [default: 1]
+    --range <DB>          Decibel range shown, below full scale [default: 60]
+    --width <N>           Width of the graph [default: terminal width]
+    --height <N>          Height of the graph [default: terminal height]
+    --no-peaks            Do not draw peak markers
+    --no-color            Do not use ANSI colours
+    --snapshot            Analyze FILE as fast as possible and print only the final frame
+    -h, --help            Print this message
+";
+
+// Size of the chunks pushed by the audio source.
+const READ_SIZE: usize = 512;
+
+// Size used for snapshots, or when the terminal size is unknown.
+const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    path: Option<String>,
+    sample_rate: usize,
+    fft_len: usize,
+    window_kind: WindowKind,
+    lower_cutoff: Frequency,
+    upper_cutoff: Frequency,
+    fps: usize,
+    bar_width: usize,
+    range: SignalStrength,
+    width: Option<usize>,
+    height: Option<usize>,
+    peaks: bool,
+    color: bool,
+    snapshot: bool,
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for `{}`", name))?;
+    value.parse().map_err(|_| format!("invalid value for `{}`: {}", name, value))
+}
+
+/// Parses the command line arguments, not including the program name.
+/// Returns `Ok(None)` if help was requested.
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Options>, String> {
+    let mut args = args.into_iter();
+
+    let mut options = Options {
+        path: None,
+        sample_rate: 44100,
+        fft_len: 2048,
+        window_kind: WindowKind::Hanning,
+        lower_cutoff: 20.0,
+        upper_cutoff: 20000.0,
+        fps: 30,
+        bar_width: 1,
+        range: 60.0,
+        width: None,
+        height: None,
+        peaks: true,
+        color: true,
+        snapshot: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sample-rate" => options.sample_rate = parse_value(&arg, args.next())?,
+            "--fft-len" => options.fft_len = parse_value(&arg, args.next())?,
+            "--window" => options.window_kind = parse_value(&arg, args.next())?,
+            "--lower" => options.lower_cutoff = parse_value(&arg, args.next())?,
+            "--upper" => options.upper_cutoff = parse_value(&arg, args.next())?,
+            "--fps" => options.fps = parse_value(&arg, args.next())?,
+            "--bar-width" => options.bar_width = parse_value(&arg, args.next())?,
+            "--range" => options.range = parse_value(&arg, args.next())?,
+            "--width" => options.width = Some(parse_value(&arg, args.next())?),
+            "--height" => options.height = Some(parse_value(&arg, args.next())?),
+            "--no-peaks" => options.peaks = false,
+            "--no-color" => options.color = false,
+            "--snapshot" => options.snapshot = true,
+            "-h" | "--help" => return Ok(None),
+            _ if arg.starts_with('-') => Err(format!("unknown option: {}", arg))?,
+            _ if options.path.is_none() => options.path = Some(arg),
+            _ => Err(format!("unexpected argument: {}", arg))?,
+        }
+    }
+
+    if !(options.fft_len > 0) { Err("FFT length must be greater than zero")? }
+    if !(options.fps > 0) { Err("frame rate must be greater than zero")? }
+    if options.snapshot && options.path.is_none() { Err("`--snapshot` requires an input file")? }
+
+    Ok(Some(options))
+}
+
+// Averages the left and right channels of a spectrum.
+fn mix(l: &[SignalStrength], r: &[SignalStrength]) -> Vec<SignalStrength> {
+    l.iter().zip(r).map(|(l, r)| (l + r) / 2.0).collect()
+}
+
+/// Pairs the bands being measured with the graph that draws them.
+struct Layout {
+    size: (usize, usize),
+    buckets: Buckets,
+    graph: BarGraph,
+}
+
+impl Layout {
+    /// Fits as many bands as will fit across a graph of the given size.
+    fn new(options: &Options, size: (usize, usize), sampling_rate: usize) -> Result<Self, String> {
+        let (width, height) = size;
+        let num_bands = BarGraph::bands_for_width(width, options.bar_width).max(1);
+
+        let nyquist = sampling_rate as Frequency / 2.0;
+        let buckets = Buckets::new(options.lower_cutoff, options.upper_cutoff.min(nyquist), num_bands).map_err(|e| e.to_string())?;
+
+        let mut graph =
+            BarGraph::new(num_bands, height.max(1)).map_err(|e| e.to_string())?
+            .with_range(-options.range, 0.0).map_err(|e| e.to_string())?
+            .with_bar_width(options.bar_width)
+            .with_color(options.color)
+        ;
+
+        // Hold peaks for half a second, then let them fall the full height in a second.
+        if options.peaks {
+            graph = graph.with_peak_hold(options.fps / 2, 1.0 / options.fps as SignalStrength);
+        }
+
+        Ok(Self { size, buckets, graph })
+    }
+
+    /// Feeds the spectra of a frame into the graph.
+    fn update(&mut self, spectrum_l: &[SignalStrength], spectrum_r: &[SignalStrength], sampling_rate: usize) -> Result<(), String> {
+        // A full scale sine wave in a single bin has this power, which is drawn as 0 dB.
+        let full_scale = (spectrum_l.len() as SignalStrength / 2.0).powi(2);
+
+        let levels: Vec<_> =
+            self.buckets.bucketize(&mix(spectrum_l, spectrum_r), sampling_rate).map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|p| p / full_scale)
+            .collect()
+        ;
+
+        self.graph.update(&levels).map_err(|e| e.to_string())
+    }
+}
+
+/// Analyzes an entire file as fast as possible, and writes out the graph of its final frame.
+/// Frames are spaced out as they would be when drawn live, so that peaks behave the same way.
+fn run_snapshot<W: Write>(options: &Options, mut writer: W) -> Result<(), String> {
+    let path = options.path.as_ref().ok_or("missing input file")?;
+    let mut audio_file = AudioFile::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let sampling_rate = audio_file.sample_rate();
+
+    let size = (options.width.unwrap_or(DEFAULT_SIZE.0), options.height.unwrap_or(DEFAULT_SIZE.1));
+    let mut layout = Layout::new(options, size, sampling_rate)?;
+
+    let hop = sampling_rate / options.fps;
+    let mut offline = OfflineAnalyzer::new(options.fft_len, hop, options.window_kind).map_err(|e| e.to_string())?;
+
+    for frame in offline.stream(&mut audio_file, sampling_rate) {
+        let frame = frame.map_err(|e| e.to_string())?;
+        layout.update(&frame.spectrum.0, &frame.spectrum.1, sampling_rate)?;
+    }
+
+    if let Some(e) = audio_file.error() { Err(format!("{}: {}", path, e))? }
+
+    writer.write_all(layout.graph.render().as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Draws the graph in place at a fixed frame rate, until the source runs out of samples.
+fn run_live(options: &Options) -> Result<(), String> {
+    let mut source: Box<dyn Source> = match &options.path {
+        Some(path) => Box::new(
+            StreamSource::file(path, options.fft_len, READ_SIZE, Pacing::RealTime).map_err(|e| format!("{}: {}", path, e))?
+        ),
+        None => Box::new(Listener::new(options.sample_rate, options.fft_len, READ_SIZE)),
+    };
+
+    let sampling_rate = source.sample_rate();
+    let mut analyzer = Analyzer::new(options.fft_len, options.window_kind);
+
+    let frame_duration = Duration::from_secs_f64(1.0 / options.fps as f64);
+    let mut layout: Option<Layout> = None;
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+
+    source.play();
+    let mut deadline = Instant::now();
+
+    while source.is_running() {
+        // Leave the last line free, so that the graph does not scroll the terminal.
+        let size = match term_size::dimensions_stdout() {
+            Some((w, h)) => (options.width.unwrap_or(w), options.height.unwrap_or(h.saturating_sub(1))),
+            None => (options.width.unwrap_or(DEFAULT_SIZE.0), options.height.unwrap_or(DEFAULT_SIZE.1)),
+        };
+
+        // Rebuild the bands whenever the terminal is resized.
+        let layout = match &mut layout {
+            Some(l) if l.size == size => l,
+            _ => {
+                write!(out, "\x1b[2J").map_err(|e| e.to_string())?;
+                layout.insert(Layout::new(options, size, sampling_rate)?)
+            },
+        };
+
+        let (spectrum_l, spectrum_r) = analyzer.analyze(source.sample_buffer()).map_err(|e| e.to_string())?;
+        layout.update(spectrum_l, spectrum_r, sampling_rate)?;
+
+        write!(out, "\x1b[H{}", layout.graph.render()).map_err(|e| e.to_string())?;
+        out.flush().map_err(|e| e.to_string())?;
+
+        deadline += frame_duration;
+        let now = Instant::now();
+        if deadline > now { thread::sleep(deadline - now) }
+        else { deadline = now }
+    }
+
+    source.stop();
+
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let result =
+        if options.snapshot { run_snapshot(&options, std::io::stdout().lock()) }
+        else { run_live(&options) }
+    ;
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn snapshot(line: &str) -> String {
+        let options = parse_args(args(line)).unwrap().unwrap();
+        let mut produced = Vec::new();
+        run_snapshot(&options, &mut produced).unwrap();
+        String::from_utf8(produced).unwrap()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args("song.wav --fft-len 1024 --fps 60 --bar-width 2 --no-color --width 40")).unwrap().unwrap();
+        assert_eq!(Some("song.wav".to_string()), options.path);
+        assert_eq!(1024, options.fft_len);
+        assert_eq!(60, options.fps);
+        assert_eq!(2, options.bar_width);
+        assert_eq!(Some(40), options.width);
+        assert_eq!(None, options.height);
+        assert!(options.peaks);
+        assert!(!options.color);
+        assert!(!options.snapshot);
+
+        let options = parse_args(args("")).unwrap().unwrap();
+        assert_eq!(None, options.path);
+        assert_eq!(44100, options.sample_rate);
+
+        assert_eq!(None, parse_args(args("--help")).unwrap());
+        assert!(parse_args(args("--snapshot")).is_err());
+        assert!(parse_args(args("--fps 0")).is_err());
+        assert!(parse_args(args("--bogus")).is_err());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let produced = snapshot("wav/sin_440hz_44100hz_samp.wav --snapshot --no-color --width 19 --height 4 --lower 100 --upper 1600");
+
+        // Ten bands of 0.4 octaves each, with the band holding the tone standing out from its neighbours.
+        let expected = concat!(
+            "          ▄        \n",
+            "          █        \n",
+            "        ▃ █        \n",
+            "        █ █ ▂      \n",
+        );
+        assert_eq!(expected, produced);
+    }
+
+    #[test]
+    fn test_snapshot_color() {
+        let produced = snapshot("wav/sin_440hz_44100hz_samp.wav --snapshot --width 7 --height 3 --lower 220 --upper 1760");
+
+        let lines: Vec<_> = produced.lines().collect();
+        assert_eq!(3, lines.len());
+
+        // Rows are coloured from the top down and reset at the end of each line.
+        assert!(lines[0].starts_with("\x1b[31m"));
+        assert!(lines[2].starts_with("\x1b[32m"));
+        assert!(lines.iter().all(|l| l.ends_with("\x1b[0m")));
+    }
+}
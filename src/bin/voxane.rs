@@ -0,0 +1,580 @@
+//! Command-line tool for analyzing audio files.
+
+use std::io::Write;
+use std::process;
+
+use voxane::Error;
+use voxane::audio_file::AudioFile;
+use voxane::beat;
+use voxane::beat::Detector;
+use voxane::buckets::Buckets;
+use voxane::offline::Frame;
+use voxane::offline::OfflineAnalyzer;
+use voxane::spectrogram::Colormap;
+use voxane::spectrogram::FrequencyScale;
+use voxane::spectrogram::Spectrogram;
+use voxane::types::Frequency;
+use voxane::types::SignalStrength;
+use voxane::window_kind::WindowKind;
+
+const USAGE: &str = "\
+Analyzes audio files.
+
+USAGE:
+    voxane <COMMAND> [OPTIONS] <FILE>
+
+COMMANDS:
+    analyze        Print the band levels of each frame
+    beats          Print the timestamps of detected beats
+    tempo          Estimate the tempo in beats per minute
+    spectrogram    Print the power spectrum of each frame, in decibels
+
+OPTIONS:
+    --fft-len <N>      FFT length, in samples [default: 2048]
+    --hop <N>          Distance between frames, in samples [default: half the FFT length]
+    --window <KIND>    Window function: blackman, blackman-harris, classic-blackman, flat-top, gaussian[:SIGMA],
+                       hamming, hanning, kaiser[:BETA], nuttall, rectangular, sine, triangular, tukey[:ALPHA]
+                       [default: hanning]
+    --bands <N>        Number of bands for `analyze` [default: 16]
+    --lower <HZ>       Lower cutoff frequency [default: 20]
+    --upper <HZ>       Upper cutoff frequency [default: 20000, or 200 for beat detection]
+    --format <FMT>     Output format: table, csv, jsonl [default: table]
+
+SPECTROGRAM IMAGE OPTIONS:
+    --png <PATH>       Save the spectrogram as a PNG image instead of printing it
+    --scale <SCALE>    Frequency axis: linear, log, bands [default: linear]
+    --colormap <MAP>   Colormap: viridis, magma, grayscale [default: viridis]
+    --range <DB>       Decibel range shown, below full scale [default: 100]
+    --height <N>       Height of the image, in pixels [default: 256]
+    --beats            Mark the frames where beats are detected
+    -h, --help         Print this message
+";
+
+// The decay and trigger values used for beat detection.
+const BEAT_DECAY_FACTOR: usize = 10;
+const BEAT_TRIGGER: usize = 3;
+const BEAT_TRIGGER_FACTOR: usize = 2;
+
+// The frequencies listened to for beats, unless overridden.
+const BEAT_LOWER_CUTOFF: Frequency = 20.0;
+const BEAT_UPPER_CUTOFF: Frequency = 200.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    Analyze,
+    Beats,
+    Tempo,
+    Spectrogram,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Table,
+    Csv,
+    JsonLines,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scale {
+    Linear,
+    Log,
+    Bands,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct ImageOptions {
+    path: String,
+    scale: Scale,
+    colormap: Colormap,
+    range: SignalStrength,
+    height: usize,
+    beat_markers: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    command: Command,
+    path: String,
+    fft_len: usize,
+    hop: usize,
+    window_kind: WindowKind,
+    num_bands: usize,
+    lower_cutoff: Frequency,
+    upper_cutoff: Frequency,
+    beat_cutoffs: (Frequency, Frequency),
+    format: Format,
+    image: Option<ImageOptions>,
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for `{}`", name))?;
+    value.parse().map_err(|_| format!("invalid value for `{}`: {}", name, value))
+}
+
+/// Parses the command line arguments, not including the program name.
+/// Returns `Ok(None)` if help was requested.
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Options>, String> {
+    let mut args = args.into_iter();
+
+    let command = match args.next().as_deref() {
+        Some("analyze") => Command::Analyze,
+        Some("beats") => Command::Beats,
+        Some("tempo") => Command::Tempo,
+        Some("spectrogram") => Command::Spectrogram,
+        Some("-h") | Some("--help") | None => return Ok(None),
+        Some(other) => Err(format!("unknown command: {}", other))?,
+    };
+
+    let mut path = None;
+    let mut fft_len = 2048;
+    let mut hop = None;
+    let mut window_kind = WindowKind::Hanning;
+    let mut num_bands = 16;
+    let mut lower_cutoff = None;
+    let mut upper_cutoff = None;
+    let mut format = Format::Table;
+    let mut png_path = None;
+    let mut scale = Scale::Linear;
+    let mut colormap = Colormap::Viridis;
+    let mut range = 100.0;
+    let mut height = 256;
+    let mut beat_markers = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fft-len" => fft_len = parse_value(&arg, args.next())?,
+            "--hop" => hop = Some(parse_value(&arg, args.next())?),
+            "--window" => window_kind = parse_value(&arg, args.next())?,
+            "--bands" => num_bands = parse_value(&arg, args.next())?,
+            "--lower" => lower_cutoff = Some(parse_value(&arg, args.next())?),
+            "--upper" => upper_cutoff = Some(parse_value(&arg, args.next())?),
+            "--format" => format = match args.next().as_deref() {
+                Some("table") => Format::Table,
+                Some("csv") => Format::Csv,
+                Some("jsonl") => Format::JsonLines,
+                Some(other) => Err(format!("invalid value for `--format`: {}", other))?,
+                None => Err("missing value for `--format`".to_string())?,
+            },
+            "--png" => png_path = Some(parse_value::<String>(&arg, args.next())?),
+            "--scale" => scale = match args.next().as_deref() {
+                Some("linear") => Scale::Linear,
+                Some("log") => Scale::Log,
+                Some("bands") => Scale::Bands,
+                Some(other) => Err(format!("invalid value for `--scale`: {}", other))?,
+                None => Err("missing value for `--scale`".to_string())?,
+            },
+            "--colormap" => colormap = match args.next().as_deref() {
+                Some("viridis") => Colormap::Viridis,
+                Some("magma") => Colormap::Magma,
+                Some("grayscale") => Colormap::Grayscale,
+                Some(other) => Err(format!("invalid value for `--colormap`: {}", other))?,
+                None => Err("missing value for `--colormap`".to_string())?,
+            },
+            "--range" => range = parse_value(&arg, args.next())?,
+            "--height" => height = parse_value(&arg, args.next())?,
+            "--beats" => beat_markers = true,
+            "-h" | "--help" => return Ok(None),
+            _ if arg.starts_with('-') => Err(format!("unknown option: {}", arg))?,
+            _ if path.is_none() => path = Some(arg),
+            _ => Err(format!("unexpected argument: {}", arg))?,
+        }
+    }
+
+    let path = path.ok_or("missing input file")?;
+
+    if !(fft_len > 0) { Err("FFT length must be greater than zero")? }
+
+    let is_beats = command == Command::Beats || command == Command::Tempo;
+
+    let image = match png_path {
+        Some(_) if command != Command::Spectrogram => Err("`--png` can only be used with `spectrogram`")?,
+        Some(path) => Some(ImageOptions { path, scale, colormap, range, height, beat_markers }),
+        None => None,
+    };
+
+    Ok(Some(Options {
+        command,
+        path,
+        fft_len,
+        hop: hop.unwrap_or((fft_len / 2).max(1)),
+        window_kind,
+        num_bands,
+        lower_cutoff: lower_cutoff.unwrap_or(20.0),
+        upper_cutoff: upper_cutoff.unwrap_or(if is_beats { BEAT_UPPER_CUTOFF } else { 20000.0 }),
+        beat_cutoffs: (lower_cutoff.unwrap_or(BEAT_LOWER_CUTOFF), upper_cutoff.unwrap_or(BEAT_UPPER_CUTOFF)),
+        format,
+        image,
+    }))
+}
+
+/// Writes rows of values in the requested format.
+struct Output<W: Write> {
+    writer: W,
+    format: Format,
+}
+
+impl<W: Write> Output<W> {
+    fn header(&mut self, columns: &[String]) -> std::io::Result<()> {
+        match self.format {
+            Format::Table => writeln!(self.writer, "{}", columns.iter().map(|c| format!("{:>12}", c)).collect::<Vec<_>>().join(" ")),
+            Format::Csv => writeln!(self.writer, "{}", columns.join(",")),
+            Format::JsonLines => Ok(()),
+        }
+    }
+
+    fn row(&mut self, time: f64, key: &str, values: &[SignalStrength]) -> std::io::Result<()> {
+        match self.format {
+            Format::Table => {
+                write!(self.writer, "{:>12.3}", time)?;
+                for v in values { write!(self.writer, " {:>12.4}", v)? }
+                writeln!(self.writer)
+            },
+            Format::Csv => {
+                write!(self.writer, "{}", time)?;
+                for v in values { write!(self.writer, ",{}", v)? }
+                writeln!(self.writer)
+            },
+            Format::JsonLines => {
+                let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+                writeln!(self.writer, "{{\"time\":{},\"{}\":[{}]}}", time, key, values)
+            },
+        }
+    }
+
+    fn value(&mut self, key: &str, value: f64) -> std::io::Result<()> {
+        match self.format {
+            Format::Table => writeln!(self.writer, "{:>12.3}", value),
+            Format::Csv => writeln!(self.writer, "{}", value),
+            Format::JsonLines => writeln!(self.writer, "{{\"{}\":{}}}", key, value),
+        }
+    }
+}
+
+// Averages the left and right channels of a frame.
+fn mix(l: &[SignalStrength], r: &[SignalStrength]) -> Vec<SignalStrength> {
+    l.iter().zip(r).map(|(l, r)| (l + r) / 2.0).collect()
+}
+
+fn to_db(power: SignalStrength) -> SignalStrength {
+    10.0 * power.max(1e-20).log10()
+}
+
+/// Creates the beat detector used by `beats`, `tempo` and the markers on spectrograms,
+/// listening between the given cutoffs, or the low frequencies if none were given.
+fn beat_detector(options: &Options, nyquist: Frequency) -> Result<Detector, String> {
+    let (lower_cutoff, upper_cutoff) = options.beat_cutoffs;
+
+    Detector::new(
+        BEAT_DECAY_FACTOR,
+        BEAT_TRIGGER,
+        BEAT_TRIGGER_FACTOR,
+        lower_cutoff as usize,
+        upper_cutoff.min(nyquist) as usize,
+        options.fft_len,
+    ).map_err(|e| e.to_string())
+}
+
+/// Renders the spectrogram of the whole file, and saves it as a PNG image.
+fn save_spectrogram(options: &Options, image: &ImageOptions) -> Result<(), String> {
+    let sampling_rate = AudioFile::open(&options.path).map_err(|e| format!("{}: {}", options.path, e))?.sample_rate();
+    let nyquist = sampling_rate as Frequency / 2.0;
+
+    let scale = match image.scale {
+        Scale::Linear => FrequencyScale::Linear,
+        Scale::Log => FrequencyScale::Log(options.lower_cutoff),
+        Scale::Bands => FrequencyScale::Buckets(
+            Buckets::new(options.lower_cutoff, options.upper_cutoff.min(nyquist), options.num_bands).map_err(|e| e.to_string())?
+        ),
+    };
+
+    let spectrogram =
+        Spectrogram::new(image.height).map_err(|e| e.to_string())?
+        .with_scale(scale).map_err(|e| e.to_string())?
+        .with_colormap(image.colormap)
+        .with_range(-image.range, 0.0).map_err(|e| e.to_string())?
+        .with_beat_markers(image.beat_markers)
+    ;
+
+    let mut offline = OfflineAnalyzer::new(options.fft_len, options.hop, options.window_kind).map_err(|e| e.to_string())?;
+
+    if image.beat_markers {
+        offline = offline.with_detector(beat_detector(options, nyquist)?);
+    }
+
+    let rendered = spectrogram.render_file(&mut offline, &options.path).map_err(|e| format!("{}: {}", options.path, e))?;
+    rendered.save(&image.path).map_err(|e| format!("{}: {}", image.path, e))
+}
+
+fn run<W: Write>(options: &Options, writer: W) -> Result<(), String> {
+    if let Some(image) = &options.image { return save_spectrogram(options, image) }
+
+    let mut audio_file = AudioFile::open(&options.path).map_err(|e| format!("{}: {}", options.path, e))?;
+    let sampling_rate = audio_file.sample_rate();
+
+    // Keep the cutoffs below the Nyquist frequency of the file.
+    let nyquist = sampling_rate as Frequency / 2.0;
+    let upper_cutoff = options.upper_cutoff.min(nyquist);
+    let lower_cutoff = options.lower_cutoff;
+
+    let mut offline = OfflineAnalyzer::new(options.fft_len, options.hop, options.window_kind).map_err(|e| e.to_string())?;
+
+    if !(options.num_bands > 0) { Err(Error::NumBands.to_string())? }
+    let buckets = Buckets::new(lower_cutoff, upper_cutoff, options.num_bands).map_err(|e| e.to_string())?;
+
+    let is_beats = options.command == Command::Beats || options.command == Command::Tempo;
+
+    if options.command == Command::Analyze {
+        offline = offline.with_buckets(buckets.clone());
+    }
+
+    if is_beats {
+        offline = offline.with_detector(beat_detector(options, nyquist)?);
+    }
+
+    let mut output = Output { writer, format: options.format };
+    let io_err = |e: std::io::Error| e.to_string();
+
+    match options.command {
+        Command::Analyze => {
+            let mut columns = vec!["time".to_string()];
+            columns.extend(buckets.bands().iter().map(|(lo, hi)| format!("{:.0}-{:.0}", lo, hi)));
+            output.header(&columns).map_err(io_err)?;
+        },
+        Command::Beats => output.header(&["time".to_string()]).map_err(io_err)?,
+        Command::Tempo => output.header(&["bpm".to_string()]).map_err(io_err)?,
+        Command::Spectrogram => {
+            let fft_bin_size = sampling_rate as Frequency / options.fft_len as Frequency;
+            let mut columns = vec!["time".to_string()];
+            columns.extend((0..=options.fft_len / 2).map(|i| format!("{:.1}", i as Frequency * fft_bin_size)));
+            output.header(&columns).map_err(io_err)?;
+        },
+    }
+
+    let mut beat_timestamps = Vec::new();
+    let mut was_beat = false;
+
+    for frame in offline.stream(&mut audio_file, sampling_rate) {
+        let Frame { timestamp, spectrum, bands, is_beat, .. } = frame.map_err(|e| e.to_string())?;
+
+        // Overlapping windows can all catch the same onset, so only the first of a run of beats counts.
+        let is_onset = is_beat == Some(true) && !was_beat;
+        was_beat = is_beat == Some(true);
+
+        match options.command {
+            Command::Analyze => {
+                let (bands_l, bands_r) = bands.unwrap_or_default();
+                output.row(timestamp, "bands", &mix(&bands_l, &bands_r)).map_err(io_err)?;
+            },
+            Command::Beats => {
+                if is_onset { output.value("time", timestamp).map_err(io_err)? }
+            },
+            Command::Tempo => {
+                if is_onset { beat_timestamps.push(timestamp) }
+            },
+            Command::Spectrogram => {
+                let (spectrum_l, spectrum_r) = spectrum;
+                let powers: Vec<_> = mix(&spectrum_l, &spectrum_r).into_iter().take(options.fft_len / 2 + 1).map(to_db).collect();
+                output.row(timestamp, "spectrum", &powers).map_err(io_err)?;
+            },
+        }
+    }
+
+    if let Some(e) = audio_file.error() { Err(format!("{}: {}", options.path, e))? }
+
+    if options.command == Command::Tempo {
+        let tempo = beat::estimate_tempo(&beat_timestamps).ok_or("too few beats to estimate a tempo")?;
+        output.value("bpm", tempo).map_err(io_err)?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let stdout = std::io::stdout();
+
+    if let Err(e) = run(&options, stdout.lock()) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let produced = parse_args(args("analyze --fft-len 1024 --window blackman --bands 8 --format csv song.wav")).unwrap().unwrap();
+        let expected = Options {
+            command: Command::Analyze,
+            path: "song.wav".to_string(),
+            fft_len: 1024,
+            hop: 512,
+            window_kind: WindowKind::Blackman,
+            num_bands: 8,
+            lower_cutoff: 20.0,
+            upper_cutoff: 20000.0,
+            beat_cutoffs: (20.0, 200.0),
+            format: Format::Csv,
+            image: None,
+        };
+        assert_eq!(expected, produced);
+
+        let produced = parse_args(args("beats song.wav --hop 256 --format jsonl")).unwrap().unwrap();
+        assert_eq!(Command::Beats, produced.command);
+        assert_eq!(256, produced.hop);
+        assert_eq!(200.0, produced.upper_cutoff);
+        assert_eq!(Format::JsonLines, produced.format);
+
+        // Beat markers on a spectrogram listen to the same frequencies as `beats`.
+        let produced = parse_args(args("spectrogram song.wav --png out.png --beats --lower 40 --upper 120")).unwrap().unwrap();
+        assert_eq!((40.0, 120.0), produced.beat_cutoffs);
+        let produced = parse_args(args("spectrogram song.wav --png out.png --beats")).unwrap().unwrap();
+        assert_eq!((BEAT_LOWER_CUTOFF, BEAT_UPPER_CUTOFF), produced.beat_cutoffs);
+
+        assert_eq!(None, parse_args(args("")).unwrap());
+        assert_eq!(None, parse_args(args("tempo --help")).unwrap());
+
+        assert!(parse_args(args("dance song.wav")).is_err());
+        assert!(parse_args(args("analyze")).is_err());
+        assert!(parse_args(args("analyze song.wav --window lumpy")).is_err());
+        assert!(parse_args(args("analyze song.wav --fft-len")).is_err());
+        assert!(parse_args(args("analyze song.wav --fft-len 0")).is_err());
+        assert!(parse_args(args("analyze song.wav --format xml")).is_err());
+        assert!(parse_args(args("analyze song.wav other.wav")).is_err());
+        assert!(parse_args(args("analyze song.wav --png out.png")).is_err());
+        assert!(parse_args(args("spectrogram song.wav --png out.png --colormap jet")).is_err());
+    }
+
+    fn run_to_string(command: &str) -> String {
+        let options = parse_args(args(command)).unwrap().unwrap();
+        let mut produced = Vec::new();
+        run(&options, &mut produced).unwrap();
+        String::from_utf8(produced).unwrap()
+    }
+
+    #[test]
+    fn test_analyze() {
+        let produced = run_to_string("analyze wav/sin_440hz_44100hz_samp.wav --fft-len 4096 --hop 4096 --bands 4 --upper 442 --format csv");
+        let lines: Vec<_> = produced.lines().collect();
+
+        assert_eq!("time,20-43,43-94,94-204,204-442", lines[0]);
+
+        // Three seconds of audio split into hops of 4096 samples.
+        assert_eq!(1 + 32, lines.len());
+
+        for line in &lines[1..] {
+            let values: Vec<f32> = line.split(',').map(|v| v.parse().unwrap()).collect();
+            assert_eq!(5, values.len());
+
+            // The 440 Hz tone lands in the highest band.
+            let loudest = values[1..].iter().enumerate().max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap()).unwrap().0;
+            assert_eq!(3, loudest);
+        }
+
+        let produced = run_to_string("analyze wav/sin_440hz_44100hz_samp.wav --fft-len 4096 --hop 4096 --bands 4 --upper 442 --format jsonl");
+        let first = produced.lines().next().unwrap();
+        assert!(first.starts_with("{\"time\":0,\"bands\":["));
+        assert!(first.ends_with("]}"));
+
+        let values = &first[first.find('[').unwrap() + 1..first.find(']').unwrap()];
+        assert_eq!(4, values.split(',').count());
+    }
+
+    #[test]
+    fn test_beats_and_tempo() {
+        const SAMPLE_RATE: u32 = 44100;
+
+        // Short bursts of a low tone every half second, for a tempo of 120 beats per minute.
+        let path = std::env::temp_dir().join(format!("voxane_{}_beats_and_tempo.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 1, sample_rate: SAMPLE_RATE, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..SAMPLE_RATE * 4 {
+            let t = (i % (SAMPLE_RATE / 2)) as f32 / SAMPLE_RATE as f32;
+            let s = if t < 0.1 { (2.0 * std::f32::consts::PI * 60.0 * t).sin() * 0.5 } else { 0.0 };
+            writer.write_sample((s * 32767.0) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let path_str = path.to_str().unwrap();
+
+        let produced = run_to_string(&format!("beats {} --fft-len 1024 --hop 512 --format csv", path_str));
+        let beats: Vec<f64> = produced.lines().skip(1).map(|l| l.parse().unwrap()).collect();
+        assert_eq!(8, beats.len());
+        for (i, b) in beats.iter().enumerate() {
+            assert!((b - i as f64 * 0.5).abs() < 0.05);
+        }
+
+        let produced = run_to_string(&format!("tempo {} --fft-len 1024 --hop 512 --format jsonl", path_str));
+        let bpm: f64 = produced.trim().trim_start_matches("{\"bpm\":").trim_end_matches('}').parse().unwrap();
+        assert!((bpm - 120.0).abs() < 5.0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_spectrogram() {
+        let produced = run_to_string("spectrogram wav/sin_1234hz_32000hz_samp.wav --fft-len 256 --hop 256 --window hann");
+        let lines: Vec<_> = produced.lines().collect();
+
+        // A time column, and one column per bin up to and including the Nyquist frequency.
+        assert_eq!(1 + 129, lines[0].split_whitespace().count());
+        assert_eq!(1 + 96000 / 256, lines.len());
+    }
+
+    #[test]
+    fn test_spectrogram_png() {
+        let path = std::env::temp_dir().join(format!("voxane_{}_spectrogram_png.png", std::process::id()));
+        let line = format!(
+            "spectrogram wav/sin_440hz_44100hz_samp.wav --fft-len 1024 --hop 1024 --png {} --scale log --colormap magma --range 80 --height 64 --beats",
+            path.display(),
+        );
+
+        let options = parse_args(args(&line)).unwrap().unwrap();
+        let image = options.image.clone().unwrap();
+        assert_eq!(Scale::Log, image.scale);
+        assert_eq!(Colormap::Magma, image.colormap);
+        assert_eq!(80.0, image.range);
+        assert_eq!(64, image.height);
+        assert!(image.beat_markers);
+
+        // Nothing is printed, the image is written instead.
+        let mut produced = Vec::new();
+        run(&options, &mut produced).unwrap();
+        assert!(produced.is_empty());
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&[0x89, b'P', b'N', b'G'], &bytes[..4]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let options = parse_args(args("tempo wav/missing.wav")).unwrap().unwrap();
+        assert!(run(&options, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_hop_size() {
+        // A hop of zero is reported, rather than quietly replaced.
+        let options = parse_args(args("analyze wav/sin_440hz_44100hz_samp.wav --hop 0")).unwrap().unwrap();
+        assert_eq!(Err(Error::HopSize.to_string()), run(&options, Vec::new()));
+    }
+}
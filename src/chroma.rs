@@ -0,0 +1,293 @@
+//! Folds the power spectra produced by `Analyzer` into the twelve pitch classes of the chromatic scale.
+
+use crate::Error;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+use crate::util::MIN_POWER;
+use crate::util::interpolate_peak;
+
+pub const NUM_PITCH_CLASSES: usize = 12;
+
+/// The names of the pitch classes, starting from C.
+pub const PITCH_CLASS_NAMES: [&str; NUM_PITCH_CLASSES] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+// How many semitones A is above C.
+const A_PITCH_CLASS: f64 = 9.0;
+
+// Peaks quieter than this fraction of the loudest bin are left out of the tuning estimate.
+const TUNING_PEAK_THRESHOLD: SignalStrength = 0.01;
+
+/// How the energy of the pitch classes is scaled in each frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChromaNormalization {
+    None,
+
+    /// The loudest pitch class is one.
+    #[default]
+    Max,
+
+    /// The pitch classes sum to one.
+    Sum,
+
+    /// The pitch classes have a Euclidean length of one.
+    Euclidean,
+}
+
+/// Computes the energy in each pitch class, from C to B, of a power spectrum.
+#[derive(Clone, Debug)]
+pub struct Chroma {
+    reference: Frequency,
+    lower_cutoff: Frequency,
+    upper_cutoff: Frequency,
+    normalization: ChromaNormalization,
+    estimate_tuning: bool,
+}
+
+impl Chroma {
+    /// Creates a chroma with A4 at 440 Hz, counting frequencies from C2 to around C8.
+    pub fn new() -> Self {
+        Self {
+            reference: 440.0,
+            lower_cutoff: 65.0,
+            upper_cutoff: 4200.0,
+            normalization: ChromaNormalization::default(),
+            estimate_tuning: false,
+        }
+    }
+
+    /// Sets the frequency of A4 that the pitch classes are tuned to.
+    pub fn with_reference(mut self, reference: Frequency) -> Result<Self, Error> {
+        if !(reference > 0.0) { Err(Error::TuningReference)? }
+
+        self.reference = reference;
+        Ok(self)
+    }
+
+    /// Sets the range of frequencies that are counted.
+    pub fn with_range(mut self, lower_cutoff: Frequency, upper_cutoff: Frequency) -> Result<Self, Error> {
+        if !(lower_cutoff > 0.0) { Err(Error::LowerCutoff)? }
+        if !(upper_cutoff > 0.0) { Err(Error::UpperCutoff)? }
+        if !(lower_cutoff < upper_cutoff) { Err(Error::CutoffOrder)? }
+
+        self.lower_cutoff = lower_cutoff;
+        self.upper_cutoff = upper_cutoff;
+        Ok(self)
+    }
+
+    pub fn with_normalization(mut self, normalization: ChromaNormalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Estimates how far each frame is tuned from the reference, and shifts the pitch classes to match.
+    pub fn with_tuning_estimation(mut self) -> Self {
+        self.estimate_tuning = true;
+        self
+    }
+
+    /// Computes the pitch classes of a power spectrum, as produced by `Analyzer`.
+    pub fn compute(&self, spectrum: &[SignalStrength], sampling_rate: usize) -> Result<[SignalStrength; NUM_PITCH_CLASSES], Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let tuning =
+            if self.estimate_tuning { estimate_tuning(spectrum, sampling_rate, self.reference).unwrap_or(0.0) }
+            else { 0.0 }
+        ;
+        let reference = self.reference as f64 * 2.0f64.powf(tuning as f64 / 12.0);
+
+        let num_bins = spectrum.len() / 2 + 1;
+        let bin_size = sampling_rate as f64 / spectrum.len() as f64;
+        let mut chroma = [0.0; NUM_PITCH_CLASSES];
+
+        for (k, &p) in spectrum.iter().enumerate().take(num_bins).skip(1) {
+            let f = k as f64 * bin_size;
+            if f < self.lower_cutoff as f64 || f > self.upper_cutoff as f64 { continue }
+
+            let pitch = 12.0 * (f / reference).log2() + A_PITCH_CLASS;
+            chroma[pitch.round().rem_euclid(NUM_PITCH_CLASSES as f64) as usize] += p;
+        }
+
+        let scale = match self.normalization {
+            ChromaNormalization::None => 1.0,
+            ChromaNormalization::Max => chroma.iter().cloned().fold(0.0, SignalStrength::max),
+            ChromaNormalization::Sum => chroma.iter().sum(),
+            ChromaNormalization::Euclidean => chroma.iter().map(|c| c * c).sum::<SignalStrength>().sqrt(),
+        };
+
+        // Silence stays at zero rather than dividing by it.
+        if scale > 0.0 { chroma.iter_mut().for_each(|c| *c /= scale) }
+
+        Ok(chroma)
+    }
+}
+
+impl Default for Chroma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Estimates how far the peaks of a power spectrum are from the equal tempered pitches around the reference,
+/// in semitones from -0.5 to 0.5, or `None` if there are no peaks.
+/// Each peak's frequency is refined by fitting a parabola to the log power around it,
+/// and the deviations are averaged around a circle, weighted by power, so that -0.5 and 0.5 agree.
+pub fn estimate_tuning(spectrum: &[SignalStrength], sampling_rate: usize, reference: Frequency) -> Option<SignalStrength> {
+    let num_bins = spectrum.len() / 2 + 1;
+    if num_bins < 3 || !(sampling_rate > 0 && reference > 0.0) { return None }
+
+    let powers = &spectrum[..num_bins];
+    let threshold = TUNING_PEAK_THRESHOLD * powers.iter().cloned().fold(0.0, SignalStrength::max);
+    let bin_size = sampling_rate as f64 / spectrum.len() as f64;
+    let (mut x, mut y) = (0.0, 0.0);
+
+    for k in 1..num_bins - 1 {
+        let p = powers[k];
+        if !(p > threshold && p > powers[k - 1] && p >= powers[k + 1]) { continue }
+
+        let (offset, _) = interpolate_peak(ln(powers[k - 1]), ln(p), ln(powers[k + 1]));
+
+        let f = (k as f64 + offset) * bin_size;
+        let pitch = 12.0 * (f / reference as f64).log2();
+        let angle = 2.0 * std::f64::consts::PI * (pitch - pitch.round());
+
+        x += p as f64 * angle.cos();
+        y += p as f64 * angle.sin();
+    }
+
+    if x == 0.0 && y == 0.0 { return None }
+    Some((y.atan2(x) / (2.0 * std::f64::consts::PI)) as SignalStrength)
+}
+
+fn ln(power: SignalStrength) -> f64 {
+    (power as f64).max(MIN_POWER).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analyzer::Analyzer;
+    use crate::sample::Sample;
+    use crate::sample::SampleBuffer;
+    use crate::wave::MultiTone;
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+    use crate::window_kind::WindowKind;
+
+    const SAMPLE_RATE: usize = 44100;
+    const FFT_LEN: usize = 8192;
+
+    fn spectrum_of<I>(signal: I) -> Vec<SignalStrength>
+    where
+        I: Iterator<Item = Sample> + Send + 'static,
+    {
+        let samples: Vec<(Sample, Sample)> = StereoGen::mono(signal).take(FFT_LEN).collect();
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        analyzer.analyze(&SampleBuffer::from(samples)).unwrap().0.to_vec()
+    }
+
+    // Frequency of a note, in semitones from A4 at the given reference.
+    fn note(semitones: f64, reference: f64) -> Frequency {
+        (reference * 2.0f64.powf(semitones / 12.0)) as Frequency
+    }
+
+    // C4, E4 and G4, with A4 at the given reference.
+    fn triad(reference: f64) -> MultiTone {
+        MultiTone::new(SAMPLE_RATE, &[note(-9.0, reference), note(-5.0, reference), note(-2.0, reference)])
+    }
+
+    // Indices of the three loudest pitch classes, in order.
+    fn loudest_three(chroma: &[SignalStrength; NUM_PITCH_CLASSES]) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..NUM_PITCH_CLASSES).collect();
+        indices.sort_by(|&a, &b| chroma[b].partial_cmp(&chroma[a]).unwrap());
+        let mut loudest = indices[..3].to_vec();
+        loudest.sort_unstable();
+        loudest
+    }
+
+    #[test]
+    fn test_c_major_triad() {
+        let produced = Chroma::new().compute(&spectrum_of(triad(440.0)), SAMPLE_RATE).unwrap();
+        println!("{:?}", produced);
+
+        assert_eq!(vec![0, 4, 7], loudest_three(&produced));
+        assert_eq!(vec!["C", "E", "G"], loudest_three(&produced).iter().map(|&i| PITCH_CLASS_NAMES[i]).collect::<Vec<_>>());
+
+        // The other pitch classes only pick up a little leakage.
+        for (i, c) in produced.iter().enumerate() {
+            if ![0, 4, 7].contains(&i) { assert!(*c < 0.2) }
+        }
+    }
+
+    #[test]
+    fn test_reference() {
+        // A tone at 415 Hz is A with baroque tuning, and G# with modern tuning.
+        let spectrum = spectrum_of(WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 415.0));
+
+        let inputs_and_expected = vec![
+            (440.0, 8),
+            (415.0, 9),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let produced = Chroma::new().with_reference(input).unwrap().compute(&spectrum, SAMPLE_RATE).unwrap();
+            assert_eq!(1.0, produced[expected]);
+        }
+    }
+
+    #[test]
+    fn test_tuning_estimation() {
+        // A triad tuned 40 cents sharp falls between pitch classes, unless the tuning is estimated.
+        let reference = 440.0 * 2.0f64.powf(0.4 / 12.0);
+        let spectrum = spectrum_of(triad(reference));
+
+        let produced = estimate_tuning(&spectrum, SAMPLE_RATE, 440.0).unwrap();
+        assert_approx_eq!(0.4, produced, 0.05);
+
+        let produced = Chroma::new().with_tuning_estimation().compute(&spectrum, SAMPLE_RATE).unwrap();
+        assert_eq!(vec![0, 4, 7], loudest_three(&produced));
+
+        // In tune, the estimate is close to zero, and with nothing to go on there is no estimate.
+        assert_approx_eq!(0.0, estimate_tuning(&spectrum_of(triad(440.0)), SAMPLE_RATE, 440.0).unwrap(), 0.05);
+        assert_eq!(None, estimate_tuning(&vec![0.0; FFT_LEN], SAMPLE_RATE, 440.0));
+    }
+
+    #[test]
+    fn test_normalization() {
+        let spectrum = spectrum_of(triad(440.0));
+
+        let inputs = vec![
+            ChromaNormalization::Max,
+            ChromaNormalization::Sum,
+            ChromaNormalization::Euclidean,
+            ChromaNormalization::None,
+        ];
+        let produced: Vec<_> = inputs
+            .into_iter()
+            .map(|n| Chroma::new().with_normalization(n).compute(&spectrum, SAMPLE_RATE).unwrap())
+            .collect();
+
+        assert_approx_eq!(1.0, produced[0].iter().cloned().fold(0.0, SignalStrength::max));
+        assert_approx_eq!(1.0, produced[1].iter().sum::<SignalStrength>());
+        assert_approx_eq!(1.0, produced[2].iter().map(|c| c * c).sum::<SignalStrength>().sqrt());
+        assert!(produced[3][0] > 1.0);
+
+        // Every mode keeps the same shape.
+        for p in &produced {
+            assert_approx_eq!(produced[0][4] / produced[0][0], p[4] / p[0]);
+        }
+
+        let silence = Chroma::new().compute(&vec![0.0; FFT_LEN], SAMPLE_RATE).unwrap();
+        assert_eq!([0.0; NUM_PITCH_CLASSES], silence);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(Error::TuningReference), Chroma::new().with_reference(0.0).err());
+        assert_eq!(Some(Error::LowerCutoff), Chroma::new().with_range(0.0, 1000.0).err());
+        assert_eq!(Some(Error::UpperCutoff), Chroma::new().with_range(100.0, -1.0).err());
+        assert_eq!(Some(Error::CutoffOrder), Chroma::new().with_range(1000.0, 100.0).err());
+        assert_eq!(Some(Error::SamplingRate(0)), Chroma::new().compute(&[0.0; 8], 0).err());
+    }
+}
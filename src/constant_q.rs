@@ -0,0 +1,314 @@
+//! A constant-Q transform, whose bins are spaced logarithmically like musical notes, each with the same number of cycles.
+//! This uses the spectral kernels from Brown and Puckette, "An efficient algorithm for the calculation of a constant Q transform" (1992),
+//! so that each frame only needs a single FFT.
+
+use std::sync::Arc;
+
+use rustfft::FFT;
+use rustfft::FFTplanner;
+use rustfft::num_complex::Complex;
+use rustfft::num_traits::Zero;
+
+use crate::Error;
+use crate::sample::Sample;
+use crate::sample::SampleBuffer;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+use crate::window_kind::WindowKind;
+
+// Kernel values smaller than this fraction of each kernel's peak are dropped, as suggested by Brown and Puckette.
+const SPARSITY_THRESHOLD: f64 = 0.0054;
+
+#[derive(Clone)]
+pub struct ConstantQ {
+    fft: Arc<dyn FFT<Sample>>,
+    sampling_rate: usize,
+    frequencies: Vec<Frequency>,
+    bands: Vec<(Frequency, Frequency)>,
+
+    // The nonzero values of each bin's spectral kernel, already conjugated and scaled.
+    kernels: Vec<Vec<(usize, Complex<Sample>)>>,
+
+    // Intermediate FFT buffers.
+    input: Vec<Complex<Sample>>,
+    output: Vec<Complex<Sample>>,
+}
+
+impl ConstantQ {
+    /// Creates a transform with `bins_per_octave` bins per octave, starting from the lower cutoff
+    /// and going up to the last bin at or below the upper cutoff.
+    /// Each frame uses enough samples to hold the kernel of the lowest bin, rounded up to a power of two.
+    pub fn new(lower_cutoff: Frequency, upper_cutoff: Frequency, bins_per_octave: usize, sampling_rate: usize) -> Result<Self, Error> {
+        if !(bins_per_octave > 0) { Err(Error::BinsPerOctave)? }
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+        if !(lower_cutoff > 0.0) { Err(Error::LowerCutoff)? }
+        if !(upper_cutoff > 0.0 && upper_cutoff <= sampling_rate as Frequency / 2.0) { Err(Error::UpperCutoff)? }
+        if !(lower_cutoff < upper_cutoff) { Err(Error::CutoffOrder)? }
+
+        let b = bins_per_octave as f64;
+        let (lower, upper, sr) = (lower_cutoff as f64, upper_cutoff as f64, sampling_rate as f64);
+
+        // Each bin is as wide as the spacing between bins.
+        let q = 1.0 / (2.0f64.powf(1.0 / b) - 1.0);
+        let num_bins = (b * (upper / lower).log2() + 1e-9).floor() as usize + 1;
+        let frequencies: Vec<f64> = (0..num_bins).map(|k| lower * 2.0f64.powf(k as f64 / b)).collect();
+
+        let len = ((q * sr / lower).ceil() as usize).next_power_of_two();
+        let fft = FFTplanner::new(false).plan_fft(len);
+
+        let kernels = frequencies
+            .iter()
+            .map(|&f| {
+                // A windowed complex sinusoid, centred in the frame and normalized so that a sine reads at half its amplitude.
+                let kernel_len = ((q * sr / f).ceil() as usize).min(len);
+                let window = WindowKind::Hanning.generate(kernel_len);
+                let sum: f64 = window.iter().sum();
+                let offset = (len - kernel_len) / 2;
+
+                let mut input = vec![Complex::zero(); len];
+                for (n, w) in window.iter().enumerate() {
+                    let phase = 2.0 * std::f64::consts::PI * f * n as f64 / sr;
+                    input[offset + n] = Complex::new((w / sum * phase.cos()) as Sample, (w / sum * phase.sin()) as Sample);
+                }
+
+                let mut output = vec![Complex::zero(); len];
+                fft.process(&mut input, &mut output);
+
+                let peak = output.iter().map(|c| c.norm() as f64).fold(0.0, f64::max);
+                output
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.norm() as f64 >= SPARSITY_THRESHOLD * peak)
+                    .map(|(j, c)| (j, c.conj() / len as Sample))
+                    .collect()
+            })
+            .collect();
+
+        let half_step = 2.0f64.powf(0.5 / b);
+        let bands = frequencies.iter().map(|f| ((f / half_step) as Frequency, (f * half_step) as Frequency)).collect();
+
+        Ok(Self {
+            fft,
+            sampling_rate,
+            frequencies: frequencies.into_iter().map(|f| f as Frequency).collect(),
+            bands,
+            kernels,
+            input: vec![Complex::zero(); len],
+            output: vec![Complex::zero(); len],
+        })
+    }
+
+    /// The number of samples each frame uses.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.fft.len()
+    }
+
+    #[inline]
+    pub fn num_bins(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    #[inline]
+    pub fn sampling_rate(&self) -> usize {
+        self.sampling_rate
+    }
+
+    /// The centre frequency of each bin.
+    #[inline]
+    pub fn frequencies(&self) -> &[Frequency] {
+        &self.frequencies
+    }
+
+    /// The range of frequencies each bin covers, halfway to its neighbours on a logarithmic scale, as with `Buckets::bands`.
+    #[inline]
+    pub fn bands(&self) -> &[(Frequency, Frequency)] {
+        &self.bands
+    }
+
+    /// Transforms the last `len` samples of a buffer, giving the power of each bin of the left and right channels.
+    /// Powers are relative to full scale, so that a full scale sine at the centre of a bin reads as one,
+    /// which suits `BarGraph` in the same way as bucketized levels.
+    pub fn transform(&mut self, samples: &SampleBuffer) -> Result<(Vec<SignalStrength>, Vec<SignalStrength>), Error> {
+        let (left, right): (Vec<Sample>, Vec<Sample>) = samples.iter_tail(self.len())?.unzip();
+
+        Ok((self.transform_channel(&left), self.transform_channel(&right)))
+    }
+
+    fn transform_channel(&mut self, samples: &[Sample]) -> Vec<SignalStrength> {
+        for (i, &s) in self.input.iter_mut().zip(samples) {
+            *i = Complex::new(s, 0.0);
+        }
+
+        self.fft.process(&mut self.input, &mut self.output);
+
+        let output = &self.output;
+        self.kernels
+            .iter()
+            .map(|kernel| {
+                let value: Complex<Sample> = kernel.iter().map(|&(j, k)| output[j] * k).sum();
+                4.0 * value.norm_sqr()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::offline::OfflineAnalyzer;
+    use crate::wave::NoiseColor;
+    use crate::wave::NoiseGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    fn transform<I>(constant_q: &mut ConstantQ, signal: I) -> Vec<SignalStrength>
+    where
+        I: Iterator<Item = Sample>,
+    {
+        let samples = SampleBuffer::from(signal.take(constant_q.len()).collect::<Vec<Sample>>());
+        constant_q.transform(&samples).unwrap().0
+    }
+
+    fn db(power: SignalStrength) -> SignalStrength {
+        10.0 * power.log10()
+    }
+
+    #[test]
+    fn test_new() {
+        let produced = ConstantQ::new(55.0, 7040.0, 12, SAMPLE_RATE).unwrap();
+
+        // Seven octaves of semitones, from A1 up to and including A8.
+        assert_eq!(85, produced.num_bins());
+        assert_approx_eq!(440.0, produced.frequencies()[36], 1e-3);
+        assert_approx_eq!(7040.0, produced.frequencies()[84], 1e-2);
+
+        // The lowest kernel is 16.8 cycles of 55 Hz, about 13500 samples.
+        assert_eq!(16384, produced.len());
+
+        for (w, f) in produced.bands().windows(2).zip(produced.frequencies()) {
+            assert_approx_eq!(w[0].1, w[1].0, 1e-3);
+            assert!(w[0].0 < *f && *f < w[0].1);
+        }
+    }
+
+    #[test]
+    fn test_tones() {
+        let mut constant_q = ConstantQ::new(55.0, 7040.0, 12, SAMPLE_RATE).unwrap();
+
+        for bin in [0, 12, 36, 60, 84] {
+            let frequency = constant_q.frequencies()[bin];
+            let produced = transform(&mut constant_q, WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, frequency));
+
+            // A tone at the centre of a bin reads at its own level, and is kept out of the bins a couple of semitones away.
+            assert_approx_eq!(db(0.25 * 0.25), db(produced[bin]), 0.1);
+
+            for (i, p) in produced.iter().enumerate() {
+                let distance = (i as isize - bin as isize).abs();
+                if distance == 1 { assert!(db(*p) < db(produced[bin]) - 5.0) }
+                if distance >= 2 { assert!(db(*p) < db(produced[bin]) - 25.0) }
+            }
+        }
+    }
+
+    #[test]
+    fn test_low_frequency_resolution() {
+        // A1 and A#1 are only 3.3 Hz apart, closer than a bin of a 4096 point FFT.
+        let signal = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 55.0)
+            .zip(WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 58.27))
+            .map(|(a, b)| a + b)
+        ;
+
+        let mut constant_q = ConstantQ::new(50.0, 200.0, 24, SAMPLE_RATE).unwrap();
+        let produced = transform(&mut constant_q, signal);
+
+        // There are two separate peaks, two bins apart, well above the leakage.
+        let loudest = produced.iter().cloned().fold(0.0, SignalStrength::max);
+        let peaks: Vec<usize> = (1..produced.len() - 1)
+            .filter(|&i| produced[i] > produced[i - 1] && produced[i] > produced[i + 1] && produced[i] > loudest / 100.0)
+            .collect();
+        let a1 = constant_q.bands().iter().position(|(lo, hi)| *lo <= 55.0 && 55.0 < *hi).unwrap();
+        assert_eq!(vec![a1, a1 + 2], peaks);
+
+        // Log-spaced buckets over that FFT can't tell them apart at all, since both land in the same bin.
+        let fft_bin_size = SAMPLE_RATE as Frequency / 4096.0;
+        assert_eq!((55.0 / fft_bin_size).round(), (58.27 / fft_bin_size).round());
+    }
+
+    #[test]
+    fn test_direct() {
+        // The sparse kernels give almost the same result as correlating with each temporal kernel directly.
+        let mut constant_q = ConstantQ::new(200.0, 2000.0, 6, SAMPLE_RATE).unwrap();
+        let samples: Vec<Sample> = NoiseGen::new(NoiseColor::White, 11).take(constant_q.len()).collect();
+        let produced = transform(&mut constant_q, samples.clone().into_iter());
+
+        let q = 1.0 / (2.0f64.powf(1.0 / 6.0) - 1.0);
+        for (f, p) in constant_q.frequencies().iter().zip(&produced) {
+            let kernel_len = (q * SAMPLE_RATE as f64 / *f as f64).ceil() as usize;
+            let window = WindowKind::Hanning.generate(kernel_len);
+            let sum: f64 = window.iter().sum();
+            let offset = (constant_q.len() - kernel_len) / 2;
+
+            let (mut re, mut im) = (0.0, 0.0);
+            for (n, w) in window.iter().enumerate() {
+                let phase = 2.0 * std::f64::consts::PI * *f as f64 * n as f64 / SAMPLE_RATE as f64;
+                re += samples[offset + n] as f64 * w / sum * phase.cos();
+                im -= samples[offset + n] as f64 * w / sum * phase.sin();
+            }
+
+            let expected = 4.0 * (re * re + im * im);
+            assert_approx_eq!(db(expected as SignalStrength), db(*p), 0.2);
+        }
+    }
+
+    #[test]
+    fn test_offline() {
+        // A hop much shorter than the frame, as when streaming.
+        let constant_q = ConstantQ::new(110.0, 3520.0, 12, SAMPLE_RATE).unwrap();
+        let len = constant_q.len();
+        let bin = constant_q.frequencies().iter().position(|f| (f - 440.0).abs() < 1.0).unwrap();
+
+        let mut offline = OfflineAnalyzer::new(len, len / 8, WindowKind::Hanning).unwrap().with_constant_q(constant_q).unwrap();
+        let samples = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 440.0).take(len * 2).map(|s| (s, s));
+        let frames = offline.stream(samples, SAMPLE_RATE).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(9, frames.len());
+
+        for frame in frames {
+            let (bands_l, bands_r) = frame.bands.unwrap();
+            assert_eq!(bands_l, bands_r);
+            assert_approx_eq!(db(0.25 * 0.25), db(bands_l[bin]), 0.1);
+        }
+
+        // Streams at any other sampling rate are rejected.
+        let samples = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE / 2, 440.0).take(len * 2).map(|s| (s, s));
+        let produced = offline.stream(samples, SAMPLE_RATE / 2).next();
+        assert_eq!(Some(Err(Error::SamplingRate(SAMPLE_RATE / 2))), produced.map(|r| r.map(|_| ())));
+
+        // The transform needs the analyzer's window to hold a whole frame.
+        let constant_q = ConstantQ::new(110.0, 3520.0, 12, SAMPLE_RATE).unwrap();
+        let offline = OfflineAnalyzer::new(1024, 512, WindowKind::Hanning).unwrap();
+        assert_eq!(Some(Error::TooFewSamples(len, 1024)), offline.with_constant_q(constant_q).err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let inputs_and_expected = vec![
+            ((55.0, 7040.0, 0, SAMPLE_RATE), Error::BinsPerOctave),
+            ((55.0, 7040.0, 12, 0), Error::SamplingRate(0)),
+            ((0.0, 7040.0, 12, SAMPLE_RATE), Error::LowerCutoff),
+            ((55.0, 30000.0, 12, SAMPLE_RATE), Error::UpperCutoff),
+            ((7040.0, 55.0, 12, SAMPLE_RATE), Error::CutoffOrder),
+        ];
+
+        for ((lower, upper, bins_per_octave, sampling_rate), expected) in inputs_and_expected {
+            assert_eq!(Some(expected), ConstantQ::new(lower, upper, bins_per_octave, sampling_rate).err());
+        }
+
+        let mut constant_q = ConstantQ::new(55.0, 7040.0, 12, SAMPLE_RATE).unwrap();
+        let samples = SampleBuffer::new(1024);
+        assert_eq!(Some(Error::TooFewSamples(16384, 1024)), constant_q.transform(&samples).err());
+    }
+}
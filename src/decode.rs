@@ -0,0 +1,218 @@
+//! Decodes compressed audio files (FLAC, Ogg Vorbis and MP3) into interleaved samples.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer as DecodedBuffer;
+use symphonia::core::codecs::CODEC_TYPE_NULL;
+use symphonia::core::codecs::Decoder as CodecDecoder;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::formats::FormatReader;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::Error;
+use crate::sample::Sample;
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::IoError(e) => Error::Io(e.kind()),
+            DecodeError::DecodeError(d) => Error::Format(d),
+            DecodeError::Unsupported(d) => Error::Format(d),
+            DecodeError::LimitError(d) => Error::Format(d),
+            DecodeError::SeekError(_) => Error::Format("unable to seek"),
+            DecodeError::ResetRequired => Error::Format("stream requires a decoder reset"),
+        }
+    }
+}
+
+/// Decodes the first audio track of a compressed file, yielding its interleaved samples.
+pub struct Decoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    track_id: u32,
+    sample_rate: usize,
+    num_channels: usize,
+    len: Option<usize>,
+    pending: VecDeque<Sample>,
+    done: bool,
+}
+
+impl Decoder {
+    /// Opens a compressed audio file, using its extension as a hint for the container format.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let probed = symphonia::default::get_probe().format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())?;
+        let format = probed.format;
+
+        let track =
+            format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(Error::Format("no audio track found"))?
+        ;
+
+        let params = &track.codec_params;
+        let track_id = track.id;
+
+        let sample_rate = params.sample_rate.ok_or(Error::Format("unknown sampling rate"))? as usize;
+        let num_channels = params.channels.ok_or(Error::Format("unknown channel layout"))?.count();
+        let len = params.n_frames.map(|n| n as usize);
+
+        let decoder = symphonia::default::get_codecs().make(params, &DecoderOptions::default())?;
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            sample_rate,
+            num_channels,
+            len,
+            pending: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// The sampling rate of the decoded audio, in Hz.
+    #[inline]
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// The number of channels in the decoded audio.
+    #[inline]
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// The total number of frames in the file, if the container reports it.
+    #[inline]
+    pub fn len(&self) -> Option<usize> {
+        self.len
+    }
+
+    // Decodes packets until some samples are available, or the stream ends.
+    fn refill(&mut self) -> Result<(), Error> {
+        while self.pending.is_empty() && !self.done {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(ref e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    self.done = true;
+                    break;
+                },
+                Err(e) => {
+                    self.done = true;
+                    Err(e)?
+                },
+            };
+
+            if packet.track_id() != self.track_id { continue }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // Corrupt packets are skipped, the same as a player would.
+                Err(DecodeError::DecodeError(_)) => continue,
+                Err(e) => {
+                    self.done = true;
+                    Err(e)?
+                },
+            };
+
+            let mut buffer = DecodedBuffer::<Sample>::new(decoded.capacity() as u64, *decoded.spec());
+            buffer.copy_interleaved_ref(decoded);
+            self.pending.extend(buffer.samples());
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = Result<Sample, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.refill() { return Some(Err(e)) }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::audio_file::AudioFile;
+    use crate::offline::OfflineAnalyzer;
+    use crate::types::Frequency;
+    use crate::types::SignalStrength;
+    use crate::window_kind::WindowKind;
+
+    const FFT_LEN: usize = 4096;
+
+    fn peak_frequency(spectrum: &[SignalStrength], sampling_rate: usize) -> Frequency {
+        let peak_index =
+            spectrum
+            .iter()
+            .take(spectrum.len() / 2 + 1)
+            .enumerate()
+            .max_by(|(_, sa), (_, sb)| sa.partial_cmp(sb).unwrap())
+            .map(|(i, _)| i)
+            .unwrap()
+        ;
+
+        peak_index as Frequency * sampling_rate as Frequency / spectrum.len() as Frequency
+    }
+
+    #[test]
+    fn test_open() {
+        // Path, sampling rate, channels, total frames, and expected left and right frequencies.
+        let inputs_and_expected = vec![
+            ("wav/sin_440hz_1000hz_44100hz_stereo.flac", 44100, 2, Some(8192), (440.0, 1000.0)),
+            ("wav/sin_1896hz_48000hz_mono.mp3", 48000, 1, None, (1895.833, 1895.833)),
+            ("wav/sin_2562hz_32000hz_mono.ogg", 32000, 1, Some(31872), (2562.5, 2562.5)),
+        ];
+
+        for (path, sampling_rate, num_channels, len, (expected_l, expected_r)) in inputs_and_expected {
+            let audio_file = AudioFile::open(path).unwrap();
+            assert_eq!(sampling_rate, audio_file.sample_rate());
+            assert_eq!(num_channels, audio_file.num_channels());
+
+            let decoder = Decoder::open(path).unwrap();
+            // MP3 has no header reporting its length.
+            if len.is_some() { assert_eq!(len, decoder.len()) }
+
+            let mut offline = OfflineAnalyzer::new(FFT_LEN, FFT_LEN, WindowKind::Hanning).unwrap();
+            let frames = offline.analyze_file(path).unwrap();
+            assert!(!frames.is_empty());
+
+            let fft_bin_size = sampling_rate as Frequency / FFT_LEN as Frequency;
+
+            for frame in frames {
+                let produced_l = peak_frequency(&frame.spectrum.0, sampling_rate);
+                let produced_r = peak_frequency(&frame.spectrum.1, sampling_rate);
+                println!("{}: {}, {}, {}, {}", path, expected_l, produced_l, expected_r, produced_r);
+                assert!((expected_l - produced_l).abs() < fft_bin_size);
+                assert!((expected_r - produced_r).abs() < fft_bin_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_open_missing() {
+        assert_eq!(Some(Error::Io(ErrorKind::NotFound)), Decoder::open("wav/missing.flac").err());
+    }
+}
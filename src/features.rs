@@ -0,0 +1,352 @@
+//! Spectral descriptors computed from the power spectra produced by `Analyzer`,
+//! following Peeters, "A large set of audio features for sound description" (2004).
+
+use crate::Error;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+use crate::util::MIN_POWER;
+
+/// Descriptors of the shape of a single spectrum.
+/// Frequencies are in Hz, and the moments are weighted by the magnitude of each bin.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpectralFeatures {
+    /// The centre of mass of the spectrum.
+    pub centroid: Frequency,
+
+    /// The standard deviation of the spectrum around its centroid.
+    pub spread: Frequency,
+
+    /// The asymmetry of the spectrum around its centroid, which is zero for a symmetric spectrum.
+    pub skewness: SignalStrength,
+
+    /// How peaked the spectrum is around its centroid, which is 3 for a normal distribution.
+    pub kurtosis: SignalStrength,
+
+    /// The frequency below which the given fraction of the power lies.
+    pub rolloff: Frequency,
+
+    /// The ratio of the geometric to the arithmetic mean of the power, near 0 for tones and near 1 for noise.
+    pub flatness: SignalStrength,
+
+    /// The ratio of the largest magnitude to the mean magnitude.
+    pub crest: SignalStrength,
+
+    /// The slope of a line fitted to the magnitudes, normalized by their sum, per Hz.
+    pub slope: SignalStrength,
+
+    /// How much the shape of the spectrum changed since the previous one,
+    /// as the distance between the two magnitude spectra once each is normalized to sum to one.
+    pub flux: SignalStrength,
+}
+
+impl SpectralFeatures {
+    /// Computes the features of a power spectrum, as produced by `Analyzer`.
+    /// Only the bins from DC up to the Nyquist frequency are used.
+    /// The previous spectrum is needed for the flux, which is zero without one.
+    pub fn from_spectrum(
+        spectrum: &[SignalStrength],
+        previous: Option<&[SignalStrength]>,
+        sampling_rate: usize,
+        rolloff_fraction: SignalStrength,
+        ) -> Self
+    {
+        let num_bins = spectrum.len() / 2 + 1;
+        let bin_size = sampling_rate as Frequency / spectrum.len() as Frequency;
+
+        let powers = &spectrum[..num_bins.min(spectrum.len())];
+        let magnitudes: Vec<SignalStrength> = powers.iter().map(|p| p.sqrt()).collect();
+        let frequencies: Vec<Frequency> = (0..powers.len()).map(|i| i as Frequency * bin_size).collect();
+
+        let mut features = SpectralFeatures {
+            flatness: flatness(powers),
+            flux: previous.map(|p| flux(&magnitudes, p)).unwrap_or(0.0),
+            ..Default::default()
+        };
+
+        let total: SignalStrength = magnitudes.iter().sum();
+        if !(total > 0.0) { return features }
+
+        let moment = |centre: Frequency, n: i32| -> SignalStrength {
+            frequencies.iter().zip(&magnitudes).map(|(f, m)| (f - centre).powi(n) * m).sum::<SignalStrength>() / total
+        };
+
+        let centroid = moment(0.0, 1);
+        let spread = moment(centroid, 2).sqrt();
+
+        features.centroid = centroid;
+        features.spread = spread;
+
+        if spread > 0.0 {
+            features.skewness = moment(centroid, 3) / spread.powi(3);
+            features.kurtosis = moment(centroid, 4) / spread.powi(4);
+        }
+
+        features.rolloff = rolloff(powers, rolloff_fraction) as Frequency * bin_size;
+        features.crest = magnitudes.iter().cloned().fold(0.0, SignalStrength::max) / (total / magnitudes.len() as SignalStrength);
+        features.slope = slope(&frequencies, &magnitudes) / total;
+
+        features
+    }
+}
+
+// Index of the first bin at which the running total of the power reaches the given fraction of the total.
+fn rolloff(powers: &[SignalStrength], fraction: SignalStrength) -> usize {
+    let threshold = fraction * powers.iter().sum::<SignalStrength>();
+    let mut sum = 0.0;
+
+    powers
+        .iter()
+        .position(|p| {
+            sum += p;
+            sum >= threshold
+        })
+        .unwrap_or(powers.len().saturating_sub(1))
+}
+
+fn flatness(powers: &[SignalStrength]) -> SignalStrength {
+    if powers.is_empty() { return 0.0 }
+
+    // Summing this many logarithms needs the extra precision.
+    let n = powers.len() as f64;
+    let log_mean = powers.iter().map(|&p| (p as f64).max(MIN_POWER).ln()).sum::<f64>() / n;
+    let mean = powers.iter().map(|&p| (p as f64).max(MIN_POWER)).sum::<f64>() / n;
+
+    (log_mean.exp() / mean) as SignalStrength
+}
+
+// Slope of the least squares line through the magnitudes.
+fn slope(frequencies: &[Frequency], magnitudes: &[SignalStrength]) -> SignalStrength {
+    let n = magnitudes.len() as SignalStrength;
+
+    let sum_f: Frequency = frequencies.iter().sum();
+    let sum_m: SignalStrength = magnitudes.iter().sum();
+    let sum_fm: SignalStrength = frequencies.iter().zip(magnitudes).map(|(f, m)| f * m).sum();
+    let sum_ff: Frequency = frequencies.iter().map(|f| f * f).sum();
+
+    let denominator = n * sum_ff - sum_f * sum_f;
+    if !(denominator > 0.0) { return 0.0 }
+
+    (n * sum_fm - sum_f * sum_m) / denominator
+}
+
+fn flux(magnitudes: &[SignalStrength], previous: &[SignalStrength]) -> SignalStrength {
+    let previous: Vec<SignalStrength> = previous.iter().take(magnitudes.len()).map(|p| p.sqrt()).collect();
+
+    let total: SignalStrength = magnitudes.iter().sum();
+    let previous_total: SignalStrength = previous.iter().sum();
+
+    // Silence is treated as having no shape to change from or to.
+    if !(total > 0.0 && previous_total > 0.0) { return 0.0 }
+
+    magnitudes
+        .iter()
+        .zip(&previous)
+        .map(|(m, p)| (m / total - p / previous_total).powi(2))
+        .sum::<SignalStrength>()
+        .sqrt()
+}
+
+/// Computes spectral features frame by frame for both channels,
+/// remembering the previous spectra in order to compute the flux.
+#[derive(Clone, Debug)]
+pub struct FeatureExtractor {
+    rolloff_fraction: SignalStrength,
+    previous: Option<(Vec<SignalStrength>, Vec<SignalStrength>)>,
+}
+
+impl FeatureExtractor {
+    /// Creates an extractor whose rolloff is the frequency below which 85% of the power lies.
+    pub fn new() -> Self {
+        Self {
+            rolloff_fraction: 0.85,
+            previous: None,
+        }
+    }
+
+    /// Sets the fraction of the power that lies below the rolloff frequency.
+    pub fn with_rolloff(mut self, fraction: SignalStrength) -> Result<Self, Error> {
+        if !(fraction > 0.0 && fraction <= 1.0) { Err(Error::RolloffFraction)? }
+
+        self.rolloff_fraction = fraction;
+        Ok(self)
+    }
+
+    /// Computes the features of the left and right spectra of the next frame.
+    pub fn extract(
+        &mut self,
+        (spectrum_l, spectrum_r): (&[SignalStrength], &[SignalStrength]),
+        sampling_rate: usize,
+        ) -> Result<(SpectralFeatures, SpectralFeatures), Error>
+    {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+        if spectrum_l.len() != spectrum_r.len() { Err(Error::InputBuffer(spectrum_l.len(), spectrum_r.len()))? }
+
+        let (previous_l, previous_r) = match &self.previous {
+            Some((l, r)) if l.len() == spectrum_l.len() => (Some(l.as_slice()), Some(r.as_slice())),
+            _ => (None, None),
+        };
+
+        let features_l = SpectralFeatures::from_spectrum(spectrum_l, previous_l, sampling_rate, self.rolloff_fraction);
+        let features_r = SpectralFeatures::from_spectrum(spectrum_r, previous_r, sampling_rate, self.rolloff_fraction);
+
+        self.previous = Some((spectrum_l.to_vec(), spectrum_r.to_vec()));
+
+        Ok((features_l, features_r))
+    }
+
+    /// Forgets the previous frame, so that the flux of the next frame is zero.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+impl Default for FeatureExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analyzer::Analyzer;
+    use crate::sample::Sample;
+    use crate::sample::SampleBuffer;
+    use crate::wave::NoiseColor;
+    use crate::wave::NoiseGen;
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+    use crate::window_kind::WindowKind;
+
+    const SAMPLE_RATE: usize = 44100;
+    const FFT_LEN: usize = 4096;
+
+    // Features of both channels of the first window of a stereo signal.
+    fn features_of<I>(frames: I) -> (SpectralFeatures, SpectralFeatures)
+    where
+        I: Iterator<Item = (Sample, Sample)>,
+    {
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let samples = SampleBuffer::from(frames.take(FFT_LEN).collect::<Vec<_>>());
+        let spectra = analyzer.analyze(&samples).unwrap();
+
+        FeatureExtractor::new().extract(spectra, SAMPLE_RATE).unwrap()
+    }
+
+    fn tone(frequency: Frequency) -> impl Iterator<Item = (Sample, Sample)> {
+        StereoGen::mono(WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, frequency))
+    }
+
+    fn noise(color: NoiseColor) -> impl Iterator<Item = (Sample, Sample)> {
+        StereoGen::mono(NoiseGen::new(color, 3))
+    }
+
+    #[test]
+    fn test_tone() {
+        let inputs = vec![100.0, 440.0, 1000.0, 5000.0, 12345.0];
+
+        for input in inputs {
+            let (produced, _) = features_of(tone(input));
+            println!("{}: {:?}", input, produced);
+
+            // All of the power is right around the tone, with a bit of leakage either side.
+            assert!((produced.centroid / input - 1.0).abs() < 0.01);
+            assert!(produced.spread < 100.0);
+            assert!((produced.rolloff - input).abs() < 2.0 * SAMPLE_RATE as Frequency / FFT_LEN as Frequency);
+            assert!(produced.flatness < 0.01);
+            assert!(produced.crest > 100.0);
+        }
+    }
+
+    #[test]
+    fn test_white_noise() {
+        let (produced, _) = features_of(noise(NoiseColor::White));
+        println!("{:?}", produced);
+
+        // The magnitudes are spread evenly up to the Nyquist frequency, like a uniform distribution,
+        // and the power in each bin is exponentially distributed, with a flatness of e^-γ.
+        let nyquist = SAMPLE_RATE as Frequency / 2.0;
+
+        assert!((produced.centroid / (nyquist / 2.0) - 1.0).abs() < 0.02);
+        assert!((produced.spread / (nyquist / 12.0f32.sqrt()) - 1.0).abs() < 0.02);
+        assert!(produced.skewness.abs() < 0.05);
+        assert!((produced.kurtosis - 1.8).abs() < 0.05);
+        assert!((produced.rolloff / (0.85 * nyquist) - 1.0).abs() < 0.02);
+        assert!((produced.flatness - (-0.5772f32).exp()).abs() < 0.05);
+        assert!(produced.crest < 5.0);
+        assert!(produced.slope.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_slope() {
+        // Pink and brown noise have more power in the low frequencies,
+        // so their spectra are skewed towards the top and slope downwards.
+        let (white, _) = features_of(noise(NoiseColor::White));
+        let (pink, _) = features_of(noise(NoiseColor::Pink));
+        let (brown, _) = features_of(noise(NoiseColor::Brown));
+
+        assert!(brown.slope < pink.slope && pink.slope < white.slope);
+        assert!(brown.centroid < pink.centroid && pink.centroid < white.centroid);
+        assert!(pink.skewness > white.skewness);
+    }
+
+    #[test]
+    fn test_per_channel() {
+        let left = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1000.0);
+        let right = NoiseGen::new(NoiseColor::White, 3);
+        let (produced_l, produced_r) = features_of(StereoGen::stereo(left, right));
+
+        assert!(produced_l.flatness < 0.01);
+        assert!(produced_r.flatness > 0.4);
+        assert!(produced_l.centroid < produced_r.centroid);
+    }
+
+    #[test]
+    fn test_flux() {
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let mut extractor = FeatureExtractor::new();
+
+        let inputs = vec![1000.0, 1000.0, 3000.0, 3000.0];
+        let mut produced = Vec::new();
+
+        for input in inputs {
+            let samples = SampleBuffer::from(tone(input).take(FFT_LEN).collect::<Vec<_>>());
+            let (features, _) = extractor.extract(analyzer.analyze(&samples).unwrap(), SAMPLE_RATE).unwrap();
+            produced.push(features.flux);
+        }
+
+        // There is nothing to compare the first frame to, and the spectrum only changes on the third.
+        assert_eq!(0.0, produced[0]);
+        assert!(produced[1] < 1e-6);
+        assert!(produced[2] > 0.1);
+        assert!(produced[3] < 1e-6);
+
+        extractor.reset();
+        let samples = SampleBuffer::from(tone(1000.0).take(FFT_LEN).collect::<Vec<_>>());
+        let (features, _) = extractor.extract(analyzer.analyze(&samples).unwrap(), SAMPLE_RATE).unwrap();
+        assert_eq!(0.0, features.flux);
+    }
+
+    #[test]
+    fn test_silence() {
+        let spectrum = vec![0.0; FFT_LEN];
+        let produced = SpectralFeatures::from_spectrum(&spectrum, Some(&spectrum), SAMPLE_RATE, 0.85);
+
+        let expected = SpectralFeatures { flatness: 1.0, ..Default::default() };
+        assert_eq!(expected, produced);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(Error::RolloffFraction), FeatureExtractor::new().with_rolloff(0.0).err());
+        assert_eq!(Some(Error::RolloffFraction), FeatureExtractor::new().with_rolloff(1.5).err());
+        assert!(FeatureExtractor::new().with_rolloff(1.0).is_ok());
+
+        let spectrum = vec![0.0; 8];
+        let mut extractor = FeatureExtractor::new();
+        assert_eq!(Err(Error::SamplingRate(0)), extractor.extract((&spectrum, &spectrum), 0));
+        assert_eq!(Err(Error::InputBuffer(8, 4)), extractor.extract((&spectrum, &spectrum[..4]), SAMPLE_RATE));
+    }
+}
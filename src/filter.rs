@@ -0,0 +1,100 @@
+//! Second order IIR filters, for weighting audio before it is measured.
+
+use crate::types::Frequency;
+
+/// A second order IIR filter, in transposed direct form II.
+/// Coefficients and state are kept in double precision, since low frequency sections are sensitive to rounding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    /// Creates a filter from its numerator and denominator coefficients, normalizing them so that `a[0]` is one.
+    pub fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        let a0 = a[0];
+
+        Self {
+            b: [b[0] / a0, b[1] / a0, b[2] / a0],
+            a: [1.0, a[1] / a0, a[2] / a0],
+            state: [0.0; 2],
+        }
+    }
+
+    /// The normalized numerator coefficients.
+    #[inline]
+    pub fn b(&self) -> [f64; 3] {
+        self.b
+    }
+
+    /// The normalized denominator coefficients.
+    #[inline]
+    pub fn a(&self) -> [f64; 3] {
+        self.a
+    }
+
+    /// Filters the next sample.
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[1] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[2] * y;
+        y
+    }
+
+    /// Clears the filter's memory of previous samples.
+    pub fn reset(&mut self) {
+        self.state = [0.0; 2];
+    }
+
+    /// The gain of the filter at the given frequency.
+    pub fn gain(&self, frequency: Frequency, sampling_rate: usize) -> f64 {
+        let w = 2.0 * std::f64::consts::PI * frequency as f64 / sampling_rate as f64;
+
+        // Evaluates a polynomial in z^-1 on the unit circle.
+        let evaluate = |c: &[f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+
+        evaluate(&self.b) / evaluate(&self.a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process() {
+        // A one sample delay, and a two point moving average.
+        let inputs_and_expected = vec![
+            (Biquad::new([0.0, 2.0, 0.0], [2.0, 0.0, 0.0]), vec![0.0, 1.0, 2.0, 3.0]),
+            (Biquad::new([0.5, 0.5, 0.0], [1.0, 0.0, 0.0]), vec![0.5, 1.5, 2.5, 3.5]),
+        ];
+
+        for (mut filter, expected) in inputs_and_expected {
+            let produced: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0].into_iter().map(|x| filter.process(x)).collect();
+            assert_eq!(expected, produced);
+
+            filter.reset();
+            assert_eq!(expected[0], filter.process(1.0));
+        }
+
+        // A leaky integrator feeds back its previous output.
+        let mut filter = Biquad::new([1.0, 0.0, 0.0], [1.0, -0.5, 0.0]);
+        let produced: Vec<f64> = (0..4).map(|_| filter.process(1.0)).collect();
+        assert_eq!(vec![1.0, 1.5, 1.75, 1.875], produced);
+    }
+
+    #[test]
+    fn test_gain() {
+        let filter = Biquad::new([0.5, 0.5, 0.0], [1.0, 0.0, 0.0]);
+
+        assert_approx_eq!(1.0, filter.gain(0.0, 48000));
+        assert_approx_eq!(0.5f64.sqrt(), filter.gain(12000.0, 48000));
+        assert_approx_eq!(0.0, filter.gain(24000.0, 48000));
+    }
+}
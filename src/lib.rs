@@ -4,10 +4,31 @@ pub mod types;
 pub mod buckets;
 pub mod analyzer;
 pub mod window_kind;
+pub mod window_metrics;
 pub mod wave;
+pub mod signal;
 pub mod sample;
 pub mod listener;
 pub mod beat;
+pub mod audio_file;
+pub mod wav_writer;
+pub mod source;
+pub mod offline;
+pub mod visualizer;
+pub mod spectrogram;
+pub mod features;
+pub mod mel;
+pub mod chroma;
+pub mod pitch;
+pub mod peaks;
+pub mod constant_q;
+pub mod filter;
+pub mod loudness;
+pub mod peak_meter;
+pub mod weighting;
+pub mod stereo;
+mod util;
+#[cfg(feature = "decode")] pub mod decode;
 #[cfg(test)] pub mod test_util;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -23,6 +44,31 @@ pub enum Error {
     DecayFactor,
     TriggerFactor,
     TooFewSamples(usize, usize),
+    NumChannels(usize),
+    Io(std::io::ErrorKind),
+    Format(&'static str),
+    HopSize,
+    WindowKind,
+    GraphHeight,
+    DbRange,
+    Encode,
+    SweepRange,
+    RolloffFraction,
+    NumCoefficients(usize),
+    DeltaWidth,
+    TuningReference,
+    ClarityThreshold,
+    PeakDistance,
+    Prominence,
+    ComplexSpectrum,
+    BinsPerOctave,
+    HoldTime,
+    DecayRate,
+    ClipLevel,
+    ClipRun,
+    TimeConstant,
+    CorrelationThreshold,
+    WindowLength,
 }
 
 impl std::fmt::Display for Error {
@@ -39,6 +85,31 @@ impl std::fmt::Display for Error {
             Error::DecayFactor => write!(f, "decay factor must be greater than zero"),
             Error::TriggerFactor => write!(f, "trigger factor must be greater than zero"),
             Error::TooFewSamples(e, p) => write!(f, "too few samples in buffer {{ expected: {}, produced: {} }}", e, p),
+            Error::NumChannels(n) => write!(f, "unsupported number of channels {{ found: {} }}", n),
+            Error::Io(k) => write!(f, "i/o error {{ kind: {:?} }}", k),
+            Error::Format(d) => write!(f, "invalid audio format: {}", d),
+            Error::HopSize => write!(f, "hop size must be greater than zero"),
+            Error::WindowKind => write!(f, "unknown window kind"),
+            Error::GraphHeight => write!(f, "graph height must be greater than zero"),
+            Error::DbRange => write!(f, "minimum decibel level must be less than maximum decibel level"),
+            Error::Encode => write!(f, "unable to encode image"),
+            Error::SweepRange => write!(f, "logarithmic sweep frequencies must be greater than zero"),
+            Error::RolloffFraction => write!(f, "rolloff fraction must be greater than zero and at most one"),
+            Error::NumCoefficients(n) => write!(f, "number of coefficients must be between one and the number of bands {{ found: {} }}", n),
+            Error::DeltaWidth => write!(f, "delta width must be greater than zero"),
+            Error::TuningReference => write!(f, "tuning reference must be greater than zero"),
+            Error::ClarityThreshold => write!(f, "clarity threshold must be greater than zero and at most one"),
+            Error::PeakDistance => write!(f, "minimum peak distance must not be negative"),
+            Error::Prominence => write!(f, "minimum prominence must not be negative"),
+            Error::ComplexSpectrum => write!(f, "interpolation needs a complex spectrum"),
+            Error::BinsPerOctave => write!(f, "bins per octave must be greater than zero"),
+            Error::HoldTime => write!(f, "hold time must not be negative"),
+            Error::DecayRate => write!(f, "decay rate must be greater than zero"),
+            Error::ClipLevel => write!(f, "clip level must be greater than zero and at most one"),
+            Error::ClipRun => write!(f, "clip run length must be greater than zero"),
+            Error::TimeConstant => write!(f, "time constant must be greater than zero"),
+            Error::CorrelationThreshold => write!(f, "correlation threshold must be between minus one and zero"),
+            Error::WindowLength => write!(f, "window length must be greater than zero"),
         }
     }
 }
@@ -50,3 +121,22 @@ impl std::error::Error for Error {
         }
     }
 }
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e.kind())
+    }
+}
+
+impl From<hound::Error> for Error {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => Error::Io(e.kind()),
+            hound::Error::FormatError(d) => Error::Format(d),
+            hound::Error::TooWide => Error::Format("sample is too wide"),
+            hound::Error::UnfinishedSample => Error::Format("unfinished sample"),
+            hound::Error::Unsupported => Error::Format("unsupported format"),
+            hound::Error::InvalidSampleFormat => Error::Format("invalid sample format"),
+        }
+    }
+}
@@ -1,15 +1,24 @@
 //! Reads audio data in-transit and pushes samples into a buffer.
 
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
 use std::thread::Builder as ThreadBuilder;
 use std::thread::JoinHandle;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering as AtomicOrdering;
 
 use cpal::UnknownTypeInputBuffer;
 use cpal::EventLoop;
 use cpal::StreamId;
 use cpal::StreamData;
+use cpal::StreamDataResult;
 use cpal::SampleRate;
 use cpal::SampleFormat;
 use cpal::Format;
@@ -17,14 +26,146 @@ use cpal::traits::HostTrait;
 use cpal::traits::DeviceTrait;
 use cpal::traits::EventLoopTrait;
 
+use crate::Error;
+use crate::sample::Sample;
 use crate::sample::SampleBuffer;
+use crate::source::Source;
+use crate::wav_writer::WavFormat;
+use crate::wav_writer::WavWriter;
 
 const NUM_CHANNELS: u16 = 2;
 
+// How many captured buffers can be waiting to be written to disk before more are dropped.
+const RECORDING_QUEUE_LEN: usize = 64;
+
+// Receives the samples captured by one listener's stream.
+type Handler = Box<dyn FnMut(&[Sample]) + Send>;
+
+// `EventLoop::run` never returns, so rather than each listener running its own event loop on a thread that can't be joined,
+// every listener builds its stream on this one, and its thread only ever hands samples to whichever listeners are still around.
+struct SharedEventLoop {
+    event_loop: EventLoop,
+    handlers: Mutex<HashMap<StreamId, Handler>>,
+}
+
+impl SharedEventLoop {
+    fn dispatch(&self, stream_id: StreamId, stream_result: StreamDataResult) {
+        let mut handlers = self.handlers.lock().unwrap();
+
+        // Streams that have been destroyed, or not yet registered, are ignored.
+        let handler = match handlers.get_mut(&stream_id) {
+            Some(handler) => handler,
+            None => return,
+        };
+
+        // Errors and unrequested formats are dropped, rather than bringing down the loop for every listener.
+        if let Ok(StreamData::Input { buffer: UnknownTypeInputBuffer::F32(buffer) }) = stream_result {
+            handler(&buffer);
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref EVENT_LOOP: &'static SharedEventLoop = {
+        let shared: &'static SharedEventLoop = Box::leak(Box::new(SharedEventLoop {
+            event_loop: cpal::default_host().event_loop(),
+            handlers: Mutex::new(HashMap::new()),
+        }));
+
+        ThreadBuilder::new()
+            .name("voxane-event-loop".into())
+            .spawn(move || shared.event_loop.run(move |stream_id, stream_result| shared.dispatch(stream_id, stream_result)))
+            .expect("failed to spawn event loop thread")
+        ;
+
+        shared
+    };
+}
+
+// What the event loop sends to the thread writing a recording to disk.
+enum RecorderMessage {
+    Samples(Vec<Sample>),
+    Finish,
+}
+
+// The event loop's end of a recording. Nothing here blocks or, once buffers start coming back to be reused, allocates.
+struct Recorder {
+    sender: SyncSender<RecorderMessage>,
+    spares: Receiver<Vec<Sample>>,
+    spare: Option<Vec<Sample>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Recorder {
+    // Queues a copy of the buffer to be written, or counts it as dropped if the disk has fallen behind.
+    // Returns false once the recording has finished.
+    fn record(&mut self, buffer: &[Sample]) -> bool {
+        let mut samples = self.spare.take().or_else(|| self.spares.try_recv().ok()).unwrap_or_default();
+        samples.clear();
+        samples.extend_from_slice(buffer);
+
+        match self.sender.try_send(RecorderMessage::Samples(samples)) {
+            Ok(()) => true,
+            Err(TrySendError::Full(RecorderMessage::Samples(samples))) => {
+                self.dropped.fetch_add(1, AtomicOrdering::SeqCst);
+                self.spare = Some(samples);
+                true
+            },
+            Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+// A recording in progress, written on its own thread so that the event loop never waits on the disk.
+struct Recording {
+    sender: SyncSender<RecorderMessage>,
+    handle: JoinHandle<Result<(), Error>>,
+}
+
+impl Recording {
+    fn start(mut writer: WavWriter, dropped: Arc<AtomicUsize>) -> Result<(Self, Recorder), Error> {
+        let (sender, receiver) = mpsc::sync_channel(RECORDING_QUEUE_LEN);
+        let (spare_sender, spares) = mpsc::channel();
+
+        let handle = ThreadBuilder::new().spawn(move || {
+            // Once a write fails, the rest of the recording is dropped, and the error is reported when it is stopped.
+            for message in receiver {
+                match message {
+                    RecorderMessage::Samples(samples) => {
+                        writer.write_interleaved(&samples)?;
+
+                        // Hand the buffer back to be filled again.
+                        let _ = spare_sender.send(samples);
+                    },
+                    RecorderMessage::Finish => break,
+                }
+            }
+
+            writer.finalize()
+        })?;
+
+        let recorder = Recorder { sender: sender.clone(), spares, spare: None, dropped };
+        Ok((Self { sender, handle }, recorder))
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        // If the thread has already stopped because of an error, the message isn't needed.
+        let _ = self.sender.send(RecorderMessage::Finish);
+        self.handle.join().unwrap_or(Err(Error::Io(std::io::ErrorKind::Other)))
+    }
+}
+
 pub struct Listener {
+    sample_rate: usize,
     sample_buffer: SampleBuffer,
     stream_id: StreamId,
-    event_loop: Arc<EventLoop>,
+    running: bool,
+
+    // Hands the event loop's end of each new recording over to it.
+    recorders: Mutex<Sender<Recorder>>,
+    recording: Mutex<Option<Recording>>,
+    dropped_buffers: Arc<AtomicUsize>,
 }
 
 impl Listener {
@@ -44,99 +185,227 @@ impl Listener {
             data_type: SampleFormat::F32,
         };
 
-        let event_loop = Arc::new(host.event_loop());
-
         let device = host.default_output_device().expect("failed to get default output device");
 
-        let stream_id = event_loop.build_input_stream(&device, &format).expect("failed to build input stream");
+        let stream_id = EVENT_LOOP.event_loop.build_input_stream(&device, &format).expect("failed to build input stream");
+
+        let (recorders, new_recorders): (_, Receiver<Recorder>) = mpsc::channel();
 
-        // Scope for thread spawning.
-        let _handle = {
-            // Since these are both using `Arc` to some degree, the calls to clone are cheap.
+        // Scope for the handler.
+        let handler: Handler = {
+            // Since the buffer is using `Arc`, the call to clone is cheap.
             let mut sample_buffer = sample_buffer.clone();
-            let event_loop = event_loop.clone();
+            let mut recorder: Option<Recorder> = None;
 
             // This is a smaller buffer for shuttling data,
             // in order to keep the sample sink from being locked for too long.
             // let transport_buffer = vec![0.0; read_size * NUM_CHANNELS as usize];
             let transport_size = read_size * NUM_CHANNELS as usize;
 
-            ThreadBuilder::new()
-                .spawn(move || {
-                    // Run the event loop and fill sample sink.
-                    event_loop.run(|_stream_id, stream_result| {
-                        // println!("{:?}", _stream_id);
-                        let stream_data = stream_result.unwrap();
-
-                        match stream_data {
-                            StreamData::Input { buffer: UnknownTypeInputBuffer::F32(buffer) } => {
-                                // println!("CPAL buffer size: {}", buffer.len());
-                                for chunk in buffer.chunks(transport_size) {
-                                    sample_buffer.push_interleaved(&chunk);
-                                }
-                            },
-                            StreamData::Input { .. } => panic!("unrequested format"),
-                            StreamData::Output { .. } => {},
-                        };
-                    });
-                })
-                .unwrap()
+            Box::new(move |buffer| {
+                // println!("CPAL buffer size: {}", buffer.len());
+                for chunk in buffer.chunks(transport_size) {
+                    sample_buffer.push_interleaved(&chunk);
+                }
+
+                // Neither of these channel operations blocks.
+                while let Ok(new_recorder) = new_recorders.try_recv() {
+                    recorder = Some(new_recorder);
+                }
+
+                if let Some(r) = recorder.as_mut() {
+                    if !r.record(buffer) { recorder = None }
+                }
+            })
         };
 
+        EVENT_LOOP.handlers.lock().unwrap().insert(stream_id.clone(), handler);
+
         Self {
+            sample_rate,
             sample_buffer,
             stream_id,
-            event_loop,
+            running: true,
+            recorders: Mutex::new(recorders),
+            recording: Mutex::new(None),
+            dropped_buffers: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Starts writing everything captured from now on to a stereo WAV file,
+    /// finishing off any recording already in progress.
+    pub fn start_recording<P: AsRef<Path>>(&self, path: P, format: WavFormat) -> Result<(), Error> {
+        self.stop_recording()?;
+
+        let writer = WavWriter::create(path, self.sample_rate, NUM_CHANNELS as usize, format)?;
+        self.dropped_buffers.store(0, AtomicOrdering::SeqCst);
+        let (recording, recorder) = Recording::start(writer, self.dropped_buffers.clone())?;
+
+        // The event loop only goes away once the listener does, so this can't fail.
+        let _ = self.recorders.lock().unwrap().send(recorder);
+        *self.recording.lock().unwrap() = Some(recording);
+        Ok(())
+    }
+
+    /// The number of captured buffers left out of the current or most recent recording,
+    /// because they arrived while too many others were still waiting to be written to disk.
+    pub fn dropped_buffers(&self) -> usize {
+        self.dropped_buffers.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Stops recording and finalizes the file, returning any error that occurred while writing it.
+    /// Samples captured before this call are all written, even if they are still on their way to the disk.
+    pub fn stop_recording(&self) -> Result<(), Error> {
+        match self.recording.lock().unwrap().take() {
+            Some(recording) => recording.finish(),
+            None => Ok(()),
         }
     }
 
     pub fn play(&self) {
         // print!("PLAYING... ");
-        self.event_loop.play_stream(self.stream_id.clone()).unwrap();
+        EVENT_LOOP.event_loop.play_stream(self.stream_id.clone()).unwrap();
         // println!("PLAYED");
     }
 
     pub fn pause(&self) {
         // print!("PAUSING... ");
-        self.event_loop.pause_stream(self.stream_id.clone()).unwrap();
+        EVENT_LOOP.event_loop.pause_stream(self.stream_id.clone()).unwrap();
         // println!("PAUSED");
     }
 
+    /// Stops listening and tears down the stream.
+    /// All listeners share one event loop thread, so there is no thread of the listener's own to wait on.
+    /// Calling this more than once has no further effect.
+    pub fn stop(&mut self) {
+        if !self.running { return }
+        self.running = false;
+
+        EVENT_LOOP.event_loop.destroy_stream(self.stream_id.clone());
+
+        // Nothing more is handed to the listener once its handler is gone, even if the stream hasn't been torn down yet.
+        EVENT_LOOP.handlers.lock().unwrap().remove(&self.stream_id);
+    }
+
+    /// Returns true if the listener has not yet been stopped.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
     pub fn sample_buffer<'a>(&'a self) -> &'a SampleBuffer {
         &self.sample_buffer
     }
 }
 
+impl Source for Listener {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn sample_buffer(&self) -> &SampleBuffer {
+        &self.sample_buffer
+    }
+
+    fn play(&self) {
+        Listener::play(self)
+    }
+
+    fn pause(&self) {
+        Listener::pause(self)
+    }
+
+    fn stop(&mut self) {
+        Listener::stop(self)
+    }
+
+    fn is_running(&self) -> bool {
+        Listener::is_running(self)
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        // Any error writing the recording has nowhere to go from here.
+        let _ = self.stop_recording();
+        self.stop();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use std::fs::File;
-    use std::io::Write;
     use std::time::Duration;
 
+    use crate::audio_file::AudioFile;
+
     #[test]
     fn test_listen() {
-        let mut output_file = File::create("wav_data.csv").unwrap();
+        let path = std::env::temp_dir().join(format!("voxane_{}_listen.wav", std::process::id()));
 
         let listener = Listener::new(44100, 44100*3, 256);
 
-        let sample_buffer = listener.sample_buffer().clone();
-
+        listener.start_recording(&path, WavFormat::F32).unwrap();
         listener.play();
 
         std::thread::sleep(Duration::from_secs(5));
 
-        for (l_sample, r_sample) in sample_buffer.iter() {
-            writeln!(output_file, "{},{}", l_sample, r_sample).unwrap();
+        listener.pause();
+        listener.stop_recording().unwrap();
+        assert_eq!(0, listener.dropped_buffers());
+
+        // Everything captured while recording ends up in the file, not just what is left in the buffer.
+        let audio_file = AudioFile::open(&path).unwrap();
+        assert_eq!(44100, audio_file.sample_rate());
+        assert!(audio_file.len().unwrap() > 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recorder() {
+        // A writer that has fallen behind, and so never takes anything off the queue.
+        let (sender, receiver) = mpsc::sync_channel(2);
+        let (_, spares) = mpsc::channel();
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut recorder = Recorder { sender, spares, spare: None, dropped: dropped.clone() };
+
+        for _ in 0..5 {
+            assert!(recorder.record(&[0.25, -0.25]));
         }
+        assert_eq!(3, dropped.load(AtomicOrdering::SeqCst));
 
-        std::thread::sleep(Duration::from_secs(5));
+        // Buffers that were queued are written as they were captured.
+        match receiver.try_recv() {
+            Ok(RecorderMessage::Samples(samples)) => assert_eq!(vec![0.25, -0.25], samples),
+            _ => panic!("expected samples"),
+        }
+
+        // Once the writer has gone, the recorder can be let go.
+        drop(receiver);
+        assert!(!recorder.record(&[0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_stop() {
+        // Listeners should be able to be created and torn down repeatedly.
+        for _ in 0..3 {
+            let mut listener = Listener::new(44100, 4096, 256);
+
+            listener.play();
+            std::thread::sleep(Duration::from_millis(100));
+
+            assert!(listener.is_running());
+            listener.stop();
+            assert!(!listener.is_running());
 
-        for (l_sample, r_sample) in sample_buffer.iter() {
-            writeln!(output_file, "{},{}", l_sample, r_sample).unwrap();
+            // Stopping again is a no-op.
+            listener.stop();
         }
 
+        // Dropping a paused listener should also shut it down cleanly.
+        let listener = Listener::new(44100, 4096, 256);
         listener.pause();
+        drop(listener);
     }
 }
@@ -0,0 +1,381 @@
+//! Measures loudness as specified in ITU-R BS.1770-4 and EBU R 128,
+//! with loudness range as specified in EBU Tech 3342.
+
+use std::collections::VecDeque;
+
+use crate::Error;
+use crate::filter::Biquad;
+use crate::sample::Sample;
+use crate::util::FrameMeter;
+
+// Momentary loudness is measured over 400 ms and short-term loudness over 3 s, both updated every 100 ms.
+const STEPS_PER_SECOND: usize = 10;
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+
+// Blocks quieter than this are ignored entirely.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+// Blocks this far below the loudness of the blocks that passed the absolute gate are ignored.
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+
+// The range is measured between these percentiles of the gated short-term loudness.
+const RANGE_LOWER_PERCENTILE: f64 = 0.10;
+const RANGE_UPPER_PERCENTILE: f64 = 0.95;
+
+// Blocks are counted in bins of 0.1 LU from the absolute gate up to +30 LUFS, as libebur128 does,
+// so that a meter left running indefinitely doesn't keep growing.
+const HISTOGRAM_BIN_WIDTH: f64 = 0.1;
+const HISTOGRAM_BINS: usize = 1000;
+
+/// The two stage K-weighting filter from BS.1770: a high shelf modelling the head, followed by a high pass.
+/// The coefficients are derived from the analog prototypes, so they match the standard's table at 48 kHz
+/// and work at any other sample rate.
+pub fn k_weighting(sampling_rate: usize) -> (Biquad, Biquad) {
+    let sr = sampling_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / sr).tan();
+    let vh = 10.0f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = Biquad::new(
+        [vh + vb * k / q + k * k, 2.0 * (k * k - vh), vh - vb * k / q + k * k],
+        [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / sr).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [a0, -2.0 * a0, a0],
+        [a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    (shelf, high_pass)
+}
+
+// Converts the mean square of the K-weighted channels, summed across channels, to LUFS.
+fn to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+fn from_lufs(loudness: f64) -> f64 {
+    10.0f64.powf((loudness + 0.691) / 10.0)
+}
+
+// How many blocks have fallen in each bin of loudness, leaving out those that don't pass the absolute gate.
+// Each block is taken to be as loud as the centre of its bin.
+#[derive(Clone, Debug)]
+struct Histogram {
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self { counts: vec![0; HISTOGRAM_BINS] }
+    }
+
+    fn add(&mut self, power: f64) {
+        let loudness = to_lufs(power);
+        if !(loudness > ABSOLUTE_GATE) { return }
+
+        let bin = (((loudness - ABSOLUTE_GATE) / HISTOGRAM_BIN_WIDTH) as usize).min(HISTOGRAM_BINS - 1);
+        self.counts[bin] += 1;
+    }
+
+    fn clear(&mut self) {
+        for count in self.counts.iter_mut() { *count = 0 }
+    }
+
+    fn loudness(bin: usize) -> f64 {
+        ABSOLUTE_GATE + (bin as f64 + 0.5) * HISTOGRAM_BIN_WIDTH
+    }
+
+    // The counts of the bins louder than `relative_gate` below the loudness of all the blocks, with the index of the first,
+    // or `None` if there are no such blocks.
+    fn gate(&self, relative_gate: f64) -> Option<(usize, &[u64])> {
+        let total: u64 = self.counts.iter().sum();
+        if total == 0 { return None }
+
+        let energy: f64 = self.counts.iter().enumerate().map(|(i, &c)| c as f64 * from_lufs(Self::loudness(i))).sum();
+        let threshold = to_lufs(energy / total as f64) + relative_gate;
+
+        let first = (0..HISTOGRAM_BINS).find(|&i| Self::loudness(i) > threshold)?;
+        let counts = &self.counts[first..];
+        if counts.iter().all(|&c| c == 0) { return None }
+
+        Some((first, counts))
+    }
+}
+
+/// Measures the loudness of a stereo stream, in LUFS, and its loudness range, in LU.
+/// Both channels are weighted equally, as for the left and right channels in BS.1770.
+#[derive(Clone, Debug)]
+pub struct LoudnessMeter {
+    filters: [(Biquad, Biquad); 2],
+
+    // The sum of the squared weighted samples in the current 100 ms step, and how many frames it holds.
+    step_len: usize,
+    step_energy: f64,
+    step_frames: usize,
+
+    // The power of each of the most recent steps, oldest first.
+    steps: VecDeque<f64>,
+
+    // The loudness of every momentary and short-term block so far, for gating.
+    momentary_blocks: Histogram,
+    short_term_blocks: Histogram,
+}
+
+impl LoudnessMeter {
+    pub fn new(sampling_rate: usize) -> Result<Self, Error> {
+        if !(sampling_rate >= STEPS_PER_SECOND) { Err(Error::SamplingRate(sampling_rate))? }
+
+        Ok(Self {
+            filters: [k_weighting(sampling_rate), k_weighting(sampling_rate)],
+            step_len: (sampling_rate + STEPS_PER_SECOND / 2) / STEPS_PER_SECOND,
+            step_energy: 0.0,
+            step_frames: 0,
+            steps: VecDeque::with_capacity(SHORT_TERM_STEPS),
+            momentary_blocks: Histogram::new(),
+            short_term_blocks: Histogram::new(),
+        })
+    }
+
+    /// Measures a slice of stereo samples, continuing on from the previous ones.
+    pub fn push(&mut self, sample_pairs: &[(Sample, Sample)]) {
+        self.push_pairs(sample_pairs);
+    }
+
+    /// Measures a slice of interleaved stereo samples, as delivered by an audio device.
+    pub fn push_interleaved(&mut self, samples: &[Sample]) {
+        self.push_interleaved_samples(samples);
+    }
+
+
+    // The mean power of the last `num_steps` steps, once there are that many.
+    fn power(&self, num_steps: usize) -> Option<f64> {
+        if self.steps.len() < num_steps { return None }
+
+        Some(self.steps.iter().rev().take(num_steps).sum::<f64>() / num_steps as f64)
+    }
+
+    /// The loudness of the last 400 ms, once that much has been measured.
+    pub fn momentary(&self) -> Option<f64> {
+        self.power(MOMENTARY_STEPS).map(to_lufs)
+    }
+
+    /// The loudness of the last 3 s, once that much has been measured.
+    pub fn short_term(&self) -> Option<f64> {
+        self.power(SHORT_TERM_STEPS).map(to_lufs)
+    }
+
+    /// The gated loudness of everything measured so far, to within 0.05 LU,
+    /// or `None` until at least one 400 ms block is louder than -70 LUFS.
+    pub fn integrated(&self) -> Option<f64> {
+        let (first, counts) = self.momentary_blocks.gate(INTEGRATED_RELATIVE_GATE)?;
+
+        let total: u64 = counts.iter().sum();
+        let energy: f64 = counts.iter().enumerate().map(|(i, &c)| c as f64 * from_lufs(Histogram::loudness(first + i))).sum();
+
+        Some(to_lufs(energy / total as f64))
+    }
+
+    /// How much the short-term loudness varies, from its 10th to its 95th percentile after gating, to within 0.1 LU,
+    /// or `None` until at least one 3 s block is louder than -70 LUFS.
+    pub fn loudness_range(&self) -> Option<f64> {
+        let (first, counts) = self.short_term_blocks.gate(RANGE_RELATIVE_GATE)?;
+
+        // The loudness of the block at the given rank, as if they were all sorted.
+        let total: u64 = counts.iter().sum();
+        let percentile = |p: f64| {
+            let rank = ((total - 1) as f64 * p).round() as u64;
+            let mut seen = 0;
+            let i = counts.iter().position(|&c| { seen += c; seen > rank }).unwrap_or(0);
+            Histogram::loudness(first + i)
+        };
+
+        Some(percentile(RANGE_UPPER_PERCENTILE) - percentile(RANGE_LOWER_PERCENTILE))
+    }
+
+    /// Forgets everything measured so far.
+    pub fn reset(&mut self) {
+        for (shelf, high_pass) in self.filters.iter_mut() {
+            shelf.reset();
+            high_pass.reset();
+        }
+
+        self.step_energy = 0.0;
+        self.step_frames = 0;
+        self.steps.clear();
+        self.momentary_blocks.clear();
+        self.short_term_blocks.clear();
+    }
+}
+
+impl FrameMeter for LoudnessMeter {
+    fn push_frame(&mut self, l: Sample, r: Sample) {
+        for ((shelf, high_pass), x) in self.filters.iter_mut().zip(&[l, r]) {
+            let y = high_pass.process(shelf.process(*x as f64));
+            self.step_energy += y * y;
+        }
+
+        self.step_frames += 1;
+        if self.step_frames < self.step_len { return }
+
+        if self.steps.len() == SHORT_TERM_STEPS { self.steps.pop_front(); }
+        self.steps.push_back(self.step_energy / self.step_len as f64);
+        self.step_energy = 0.0;
+        self.step_frames = 0;
+
+        if let Some(power) = self.power(MOMENTARY_STEPS) { self.momentary_blocks.add(power) }
+        if let Some(power) = self.power(SHORT_TERM_STEPS) { self.short_term_blocks.add(power) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::Frequency;
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+
+    const SAMPLE_RATE: usize = 48000;
+
+    // A 1 kHz sine in both channels, at each level in dBFS for the given number of seconds in turn.
+    fn measure(segments: &[(f64, f64)]) -> LoudnessMeter {
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE).unwrap();
+
+        for &(level, seconds) in segments {
+            let amplitude = 10.0f64.powf(level / 20.0) as Sample;
+            let sine = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1000.0).with_amplitude(amplitude);
+            let frames: Vec<(Sample, Sample)> = StereoGen::mono(sine).take((seconds * SAMPLE_RATE as f64) as usize).collect();
+            meter.push(&frames);
+        }
+
+        meter
+    }
+
+    #[test]
+    fn test_k_weighting() {
+        // The coefficients at 48 kHz, from table 1 and table 2 of BS.1770-4.
+        let (shelf, high_pass) = k_weighting(SAMPLE_RATE);
+
+        let expected = [1.53512485958697, -2.69169618940638, 1.19839281085285];
+        for (e, p) in expected.iter().zip(&shelf.b()) { assert_approx_eq!(e, p, 1e-9) }
+
+        let expected = [1.0, -1.69065929318241, 0.73248077421585];
+        for (e, p) in expected.iter().zip(&shelf.a()) { assert_approx_eq!(e, p, 1e-9) }
+
+        let expected = [1.0, -2.0, 1.0];
+        for (e, p) in expected.iter().zip(&high_pass.b()) { assert_approx_eq!(e, p, 1e-9) }
+
+        let expected = [1.0, -1.99004745483398, 0.99007225036621];
+        for (e, p) in expected.iter().zip(&high_pass.a()) { assert_approx_eq!(e, p, 1e-9) }
+
+        // A 1 kHz tone is boosted by just enough to cancel out the -0.691 dB offset in the loudness.
+        let gain_db = |frequency: Frequency, sampling_rate: usize| {
+            let (shelf, high_pass) = k_weighting(sampling_rate);
+            20.0 * (shelf.gain(frequency, sampling_rate) * high_pass.gain(frequency, sampling_rate)).log10()
+        };
+        assert_approx_eq!(0.691, gain_db(1000.0, SAMPLE_RATE), 0.01);
+
+        // The response is the same at other sample rates.
+        for &frequency in &[20.0, 100.0, 1000.0, 10000.0] {
+            for &sampling_rate in &[44100, 96000] {
+                assert_approx_eq!(gain_db(frequency, SAMPLE_RATE), gain_db(frequency, sampling_rate), 0.1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_integrated() {
+        // The minimum requirements for integrated loudness in EBU Tech 3341, cases 1 to 5, all to within 0.1 LU.
+        let inputs_and_expected = vec![
+            (vec![(-23.0, 20.0)], -23.0),
+            (vec![(-33.0, 20.0)], -33.0),
+            (vec![(-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0)], -23.0),
+            (vec![(-72.0, 10.0), (-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0), (-72.0, 10.0)], -23.0),
+            (vec![(-26.0, 20.0), (-20.0, 20.1), (-26.0, 20.0)], -23.0),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let produced = measure(&input).integrated().unwrap();
+            println!("{:?}: {}", input, produced);
+            assert_approx_eq!(expected, produced, 0.1);
+        }
+    }
+
+    #[test]
+    fn test_momentary_and_short_term() {
+        let mut meter = measure(&[(-23.0, 0.3)]);
+        assert_eq!(None, meter.momentary());
+
+        meter = measure(&[(-23.0, 2.9)]);
+        assert_approx_eq!(-23.0, meter.momentary().unwrap(), 0.1);
+        assert_eq!(None, meter.short_term());
+
+        // The short-term loudness catches up with a change in level more slowly than the momentary loudness.
+        meter = measure(&[(-23.0, 3.0), (-33.0, 1.0)]);
+        assert_approx_eq!(-33.0, meter.momentary().unwrap(), 0.1);
+        assert!(meter.short_term().unwrap() > -26.0);
+
+        meter.reset();
+        assert_eq!(None, meter.momentary());
+        assert_eq!(None, meter.integrated());
+    }
+
+    #[test]
+    fn test_loudness_range() {
+        // The minimum requirements for loudness range in EBU Tech 3342, cases 1 to 4, all to within 1 LU.
+        let inputs_and_expected = vec![
+            (vec![(-20.0, 20.0), (-30.0, 20.0)], 10.0),
+            (vec![(-20.0, 20.0), (-15.0, 20.0)], 5.0),
+            (vec![(-40.0, 20.0), (-20.0, 20.0)], 20.0),
+            (vec![(-50.0, 20.0), (-35.0, 20.0), (-20.0, 20.0), (-35.0, 20.0), (-50.0, 20.0)], 15.0),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let produced = measure(&input).loudness_range().unwrap();
+            println!("{:?}: {}", input, produced);
+            assert_approx_eq!(expected, produced, 1.0);
+        }
+    }
+
+    #[test]
+    fn test_silence() {
+        // Silence never passes the absolute gate.
+        let meter = measure(&[(-100.0, 5.0)]);
+        assert_eq!(None, meter.integrated());
+        assert_eq!(None, meter.loudness_range());
+        assert!(meter.momentary().unwrap() < -90.0);
+
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE).unwrap();
+        meter.push_interleaved(&vec![0.0; SAMPLE_RATE]);
+        assert_eq!(f64::NEG_INFINITY, meter.momentary().unwrap());
+
+        assert_eq!(Some(Error::SamplingRate(0)), LoudnessMeter::new(0).err());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new();
+        let inputs = [-23.0, -23.04, 12.0, 40.0, -70.0, -80.0];
+        for &loudness in inputs.iter() { histogram.add(from_lufs(loudness)) }
+
+        // Blocks at the absolute gate or below aren't counted, and those above +30 LUFS go in the last bin.
+        assert_eq!(HISTOGRAM_BINS, histogram.counts.len());
+        assert_eq!(4, histogram.counts.iter().sum::<u64>());
+        assert_eq!(1, histogram.counts[HISTOGRAM_BINS - 1]);
+
+        let (first, counts) = histogram.gate(-100.0).unwrap();
+        assert_eq!(0, first);
+        assert_approx_eq!(-23.05, Histogram::loudness(counts.iter().position(|&c| c > 0).unwrap()), 0.05 + 1e-9);
+
+        histogram.clear();
+        assert_eq!(None, histogram.gate(-10.0));
+    }
+}
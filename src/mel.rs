@@ -0,0 +1,460 @@
+//! Mel spectra and mel-frequency cepstral coefficients computed from the power spectra produced by `Analyzer`,
+//! following the definitions used by librosa.
+
+use crate::Error;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+
+// Floor applied to powers before converting them to decibels, as in librosa's `power_to_db`.
+const MIN_POWER: f64 = 1e-10;
+
+/// How frequencies are mapped to mels, and how the filters are normalized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MelScale {
+    /// The scale from Slaney's Auditory Toolbox, linear below 1 kHz and logarithmic above,
+    /// with each filter scaled to have the same area. This is librosa's default.
+    #[default]
+    Slaney,
+
+    /// The scale used by HTK and Kaldi, with each filter peaking at one.
+    Htk,
+}
+
+impl MelScale {
+    pub fn hz_to_mel(&self, frequency: f64) -> f64 {
+        match self {
+            &MelScale::Slaney => {
+                if frequency < SLANEY_MIN_LOG_HZ { frequency / SLANEY_HZ_PER_MEL }
+                else { SLANEY_MIN_LOG_HZ / SLANEY_HZ_PER_MEL + (frequency / SLANEY_MIN_LOG_HZ).ln() / slaney_log_step() }
+            },
+            &MelScale::Htk => 2595.0 * (1.0 + frequency / 700.0).log10(),
+        }
+    }
+
+    pub fn mel_to_hz(&self, mel: f64) -> f64 {
+        match self {
+            &MelScale::Slaney => {
+                let min_log_mel = SLANEY_MIN_LOG_HZ / SLANEY_HZ_PER_MEL;
+                if mel < min_log_mel { mel * SLANEY_HZ_PER_MEL }
+                else { SLANEY_MIN_LOG_HZ * (slaney_log_step() * (mel - min_log_mel)).exp() }
+            },
+            &MelScale::Htk => 700.0 * (10.0f64.powf(mel / 2595.0) - 1.0),
+        }
+    }
+}
+
+const SLANEY_HZ_PER_MEL: f64 = 200.0 / 3.0;
+const SLANEY_MIN_LOG_HZ: f64 = 1000.0;
+
+// Each mel above 1 kHz is a step of 6.4^(1/27) in frequency.
+fn slaney_log_step() -> f64 {
+    6.4f64.ln() / 27.0
+}
+
+/// Triangular filters spaced evenly on the mel scale, which sum the power spectrum into mel bands.
+#[derive(Clone, Debug)]
+pub struct MelFilterbank {
+    // The weight of each bin from DC up to the Nyquist frequency, for each filter.
+    weights: Vec<Vec<SignalStrength>>,
+    fft_len: usize,
+}
+
+impl MelFilterbank {
+    /// Creates a filterbank for spectra of the given length,
+    /// with filters whose edges are spread evenly in mels from the lower to the upper cutoff.
+    pub fn new(
+        num_mels: usize,
+        fft_len: usize,
+        sampling_rate: usize,
+        lower_cutoff: Frequency,
+        upper_cutoff: Frequency,
+        scale: MelScale,
+        ) -> Result<Self, Error>
+    {
+        if !(num_mels > 0) { Err(Error::NumBands)? }
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+        if !(lower_cutoff >= 0.0) { Err(Error::LowerCutoff)? }
+        if !(upper_cutoff > 0.0) { Err(Error::UpperCutoff)? }
+        if !(lower_cutoff < upper_cutoff) { Err(Error::CutoffOrder)? }
+
+        let lower_mel = scale.hz_to_mel(lower_cutoff as f64);
+        let upper_mel = scale.hz_to_mel(upper_cutoff as f64);
+
+        // The edges of the filters, each sharing its outer edges with the centres of its neighbours.
+        let edges: Vec<f64> = (0..num_mels + 2)
+            .map(|i| scale.mel_to_hz(lower_mel + (upper_mel - lower_mel) * i as f64 / (num_mels + 1) as f64))
+            .collect();
+
+        let bin_size = sampling_rate as f64 / fft_len as f64;
+
+        let weights = edges
+            .windows(3)
+            .map(|e| {
+                let area = match scale {
+                    MelScale::Slaney => 2.0 / (e[2] - e[0]),
+                    MelScale::Htk => 1.0,
+                };
+
+                (0..fft_len / 2 + 1)
+                    .map(|k| {
+                        let f = k as f64 * bin_size;
+                        let rising = (f - e[0]) / (e[1] - e[0]);
+                        let falling = (e[2] - f) / (e[2] - e[1]);
+                        (rising.min(falling).max(0.0) * area) as SignalStrength
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            weights,
+            fft_len,
+        })
+    }
+
+    #[inline]
+    pub fn num_mels(&self) -> usize {
+        self.weights.len()
+    }
+
+    #[inline]
+    pub fn fft_len(&self) -> usize {
+        self.fft_len
+    }
+
+    /// The weight of each bin from DC up to the Nyquist frequency, for each filter.
+    pub fn weights(&self) -> &[Vec<SignalStrength>] {
+        &self.weights
+    }
+
+    /// Sums a power spectrum, as produced by `Analyzer`, into mel bands.
+    pub fn apply(&self, spectrum: &[SignalStrength]) -> Result<Vec<SignalStrength>, Error> {
+        if spectrum.len() != self.fft_len { Err(Error::InputBuffer(self.fft_len, spectrum.len()))? }
+
+        Ok(self.weights.iter().map(|w| w.iter().zip(spectrum).map(|(w, p)| w * p).sum()).collect())
+    }
+}
+
+/// Converts powers to decibels, with silence floored at -100 dB.
+/// If given, anything more than `top_db` below the loudest power is raised to that level.
+pub fn power_to_db(powers: &[SignalStrength], top_db: Option<SignalStrength>) -> Vec<SignalStrength> {
+    let db: Vec<SignalStrength> = powers.iter().map(|&p| (10.0 * (p as f64).max(MIN_POWER).log10()) as SignalStrength).collect();
+
+    match top_db {
+        Some(top_db) => {
+            let floor = db.iter().cloned().fold(SignalStrength::NEG_INFINITY, SignalStrength::max) - top_db;
+            db.into_iter().map(|d| d.max(floor)).collect()
+        },
+        None => db,
+    }
+}
+
+/// Computes mel-frequency cepstral coefficients from power spectra,
+/// as the orthonormal type 2 DCT of the mel spectrum in decibels.
+#[derive(Clone, Debug)]
+pub struct Mfcc {
+    filterbank: MelFilterbank,
+    top_db: Option<SignalStrength>,
+
+    // The first rows of the DCT matrix, one per coefficient.
+    dct: Vec<Vec<SignalStrength>>,
+}
+
+impl Mfcc {
+    /// Creates an extractor for the first `num_coefficients` coefficients,
+    /// which limits the mel spectrum to 80 dB below its loudest band.
+    pub fn new(filterbank: MelFilterbank, num_coefficients: usize) -> Result<Self, Error> {
+        let num_mels = filterbank.num_mels();
+        if !(num_coefficients > 0 && num_coefficients <= num_mels) { Err(Error::NumCoefficients(num_coefficients))? }
+
+        let n = num_mels as f64;
+        let dct = (0..num_coefficients)
+            .map(|k| {
+                let scale = if k == 0 { (1.0 / n).sqrt() } else { (2.0 / n).sqrt() };
+                (0..num_mels)
+                    .map(|i| (scale * (std::f64::consts::PI * k as f64 * (2 * i + 1) as f64 / (2.0 * n)).cos()) as SignalStrength)
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            filterbank,
+            top_db: Some(80.0),
+            dct,
+        })
+    }
+
+    /// Sets how far below its loudest band the mel spectrum is limited to, if at all.
+    /// Unlike librosa, which limits a whole spectrogram at once, each frame is limited on its own.
+    pub fn with_top_db(mut self, top_db: Option<SignalStrength>) -> Self {
+        self.top_db = top_db;
+        self
+    }
+
+    #[inline]
+    pub fn num_coefficients(&self) -> usize {
+        self.dct.len()
+    }
+
+    #[inline]
+    pub fn filterbank(&self) -> &MelFilterbank {
+        &self.filterbank
+    }
+
+    /// Computes the mel spectrum of a power spectrum, in decibels.
+    pub fn log_mel(&self, spectrum: &[SignalStrength]) -> Result<Vec<SignalStrength>, Error> {
+        Ok(power_to_db(&self.filterbank.apply(spectrum)?, self.top_db))
+    }
+
+    /// Computes the coefficients of a power spectrum, as produced by `Analyzer`.
+    pub fn compute(&self, spectrum: &[SignalStrength]) -> Result<Vec<SignalStrength>, Error> {
+        self.cepstrum(&self.log_mel(spectrum)?)
+    }
+
+    /// Computes the coefficients of a mel spectrum already in decibels,
+    /// such as one limited along with the rest of a spectrogram, as librosa does.
+    pub fn cepstrum(&self, log_mel: &[SignalStrength]) -> Result<Vec<SignalStrength>, Error> {
+        let num_mels = self.filterbank.num_mels();
+        if log_mel.len() != num_mels { Err(Error::InputBuffer(num_mels, log_mel.len()))? }
+
+        Ok(self.dct.iter().map(|row| row.iter().zip(log_mel).map(|(c, m)| c * m).sum()).collect())
+    }
+}
+
+/// Estimates how each coefficient changes from frame to frame,
+/// by fitting a line through the `width` frames either side of each frame, as HTK does.
+/// The first and last frames are repeated to fill in past the ends.
+/// Applying this to its own output gives the delta-deltas.
+pub fn deltas(frames: &[Vec<SignalStrength>], width: usize) -> Result<Vec<Vec<SignalStrength>>, Error> {
+    if !(width > 0) { Err(Error::DeltaWidth)? }
+
+    let last = frames.len().saturating_sub(1) as isize;
+    let at = |i: isize| &frames[i.clamp(0, last) as usize];
+    let denominator = 2.0 * (1..=width).map(|n| (n * n) as SignalStrength).sum::<SignalStrength>();
+
+    Ok((0..frames.len() as isize)
+        .map(|t| {
+            (0..frames[t as usize].len())
+                .map(|c| {
+                    (1..=width as isize)
+                        .map(|n| n as SignalStrength * (at(t + n)[c] - at(t - n)[c]))
+                        .sum::<SignalStrength>() / denominator
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analyzer::Analyzer;
+    use crate::sample::Sample;
+    use crate::sample::SampleBuffer;
+    use crate::wave::StereoGen;
+    use crate::wave::SweepKind;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+    use crate::window_kind::WindowKind;
+
+    // These match the settings in wav/generate_mfcc_reference.py, which produces the fixtures with librosa.
+    const SAMPLE_RATE: usize = 16000;
+    const FFT_LEN: usize = 1024;
+    const HOP: usize = 512;
+    const NUM_FRAMES: usize = 15;
+    const NUM_MELS: usize = 40;
+    const NUM_MFCC: usize = 13;
+    const DELTA_WIDTH: usize = 2;
+
+    struct Fixture {
+        mel_db: Vec<Vec<SignalStrength>>,
+        mfcc: Vec<Vec<SignalStrength>>,
+        delta: Vec<Vec<SignalStrength>>,
+        delta_delta: Vec<Vec<SignalStrength>>,
+    }
+
+    fn parse_fixture(text: &str) -> Fixture {
+        let mut sections: Vec<(&str, Vec<Vec<SignalStrength>>)> = Vec::new();
+
+        for line in text.lines() {
+            match line.strip_prefix("# ") {
+                Some(name) => sections.push((name, Vec::new())),
+                None => sections.last_mut().unwrap().1.push(line.split_whitespace().map(|v| v.parse().unwrap()).collect()),
+            }
+        }
+
+        let section = |name: &str| sections.iter().find(|(n, _)| *n == name).unwrap().1.clone();
+
+        Fixture {
+            mel_db: section("mel_db"),
+            mfcc: section("mfcc"),
+            delta: section("delta"),
+            delta_delta: section("delta_delta"),
+        }
+    }
+
+    // The power spectrum of each frame of a chirp from 200 to 4000 Hz.
+    fn chirp_spectra() -> Vec<Vec<SignalStrength>> {
+        let len = FFT_LEN + HOP * (NUM_FRAMES - 1);
+        let chirp = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 200.0)
+            .with_sweep(4000.0, len, SweepKind::Linear)
+            .unwrap()
+        ;
+        let samples: Vec<(Sample, Sample)> = StereoGen::mono(chirp).take(len).collect();
+
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        samples
+            .windows(FFT_LEN)
+            .step_by(HOP)
+            .map(|w| analyzer.analyze(&SampleBuffer::from(w.to_vec())).unwrap().0.to_vec())
+            .collect()
+    }
+
+    // Limits a whole spectrogram to `top_db` below its loudest band, as librosa does.
+    fn limit_globally(frames: &[Vec<SignalStrength>], top_db: SignalStrength) -> Vec<Vec<SignalStrength>> {
+        let floor = frames.iter().flatten().cloned().fold(SignalStrength::NEG_INFINITY, SignalStrength::max) - top_db;
+        frames.iter().map(|f| f.iter().map(|d| d.max(floor)).collect()).collect()
+    }
+
+    fn assert_frames_eq(expected: &[Vec<SignalStrength>], produced: &[Vec<SignalStrength>], tolerance: SignalStrength) {
+        assert_eq!(expected.len(), produced.len());
+
+        for (e, p) in expected.iter().zip(produced) {
+            assert_eq!(e.len(), p.len());
+            for (e, p) in e.iter().zip(p) {
+                assert_approx_eq!(e, p, tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mel_scale() {
+        // Frequency, and the expected mels on the Slaney and HTK scales.
+        let inputs_and_expected = vec![
+            (0.0, (0.0, 0.0)),
+            (500.0, (7.5, 607.4459)),
+            (1000.0, (15.0, 999.9855)),
+            (6400.0, (42.0, 2610.9860)),
+        ];
+
+        for (input, (slaney, htk)) in inputs_and_expected {
+            assert_approx_eq!(slaney, MelScale::Slaney.hz_to_mel(input), 1e-9);
+            assert_approx_eq!(htk, MelScale::Htk.hz_to_mel(input), 1e-3);
+            assert_approx_eq!(input, MelScale::Slaney.mel_to_hz(slaney), 1e-9);
+            assert_approx_eq!(input, MelScale::Htk.mel_to_hz(MelScale::Htk.hz_to_mel(input)), 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_filterbank() {
+        let slaney = MelFilterbank::new(NUM_MELS, FFT_LEN, SAMPLE_RATE, 0.0, 8000.0, MelScale::Slaney).unwrap();
+        let htk = MelFilterbank::new(NUM_MELS, FFT_LEN, SAMPLE_RATE, 0.0, 8000.0, MelScale::Htk).unwrap();
+        assert_eq!(NUM_MELS, slaney.num_mels());
+
+        // HTK filters peak at one, and Slaney filters have the same area once converted to Hz.
+        let bin_size = SAMPLE_RATE as SignalStrength / FFT_LEN as SignalStrength;
+        for (s, h) in slaney.weights().iter().zip(htk.weights()) {
+            assert!(h.iter().cloned().fold(0.0, SignalStrength::max) <= 1.0);
+            assert!(h.iter().all(|&w| w >= 0.0));
+            assert_approx_eq!(1.0, s.iter().sum::<SignalStrength>() * bin_size, 0.01);
+        }
+    }
+
+    #[test]
+    fn test_reference() {
+        let spectra = chirp_spectra();
+
+        let inputs_and_expected = vec![
+            (MelScale::Slaney, include_str!("../wav/fixtures/mfcc_slaney.txt")),
+            (MelScale::Htk, include_str!("../wav/fixtures/mfcc_htk.txt")),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let expected = parse_fixture(expected);
+
+            let filterbank = MelFilterbank::new(NUM_MELS, FFT_LEN, SAMPLE_RATE, 0.0, 8000.0, input).unwrap();
+            let mfcc = Mfcc::new(filterbank, NUM_MFCC).unwrap().with_top_db(None);
+
+            let mel_db: Vec<_> = spectra.iter().map(|s| mfcc.log_mel(s).unwrap()).collect();
+            let mel_db = limit_globally(&mel_db, 80.0);
+            let coefficients: Vec<_> = mel_db.iter().map(|m| mfcc.cepstrum(m).unwrap()).collect();
+            let delta = deltas(&coefficients, DELTA_WIDTH).unwrap();
+            let delta_delta = deltas(&delta, DELTA_WIDTH).unwrap();
+
+            assert_frames_eq(&expected.mel_db, &mel_db, 0.01);
+            assert_frames_eq(&expected.mfcc, &coefficients, 0.01);
+            assert_frames_eq(&expected.delta, &delta, 0.01);
+            assert_frames_eq(&expected.delta_delta, &delta_delta, 0.01);
+        }
+    }
+
+    #[test]
+    fn test_top_db_per_frame() {
+        let spectra = chirp_spectra();
+        let expected = parse_fixture(include_str!("../wav/fixtures/mfcc_slaney.txt"));
+
+        let filterbank = MelFilterbank::new(NUM_MELS, FFT_LEN, SAMPLE_RATE, 0.0, 8000.0, MelScale::Slaney).unwrap();
+        let mfcc = Mfcc::new(filterbank, NUM_MFCC).unwrap();
+        let produced: Vec<_> = spectra.iter().map(|s| mfcc.log_mel(s).unwrap()).collect();
+
+        // Each frame is limited to 80 dB below its own loudest band, rather than the loudest band of the whole chirp,
+        // so quieter frames keep detail that librosa raises to its floor.
+        let global_floor = expected.mel_db.iter().flatten().cloned().fold(SignalStrength::NEG_INFINITY, SignalStrength::max) - 80.0;
+        let mut num_kept = 0;
+        for (e, p) in expected.mel_db.iter().zip(&produced) {
+            let floor = p.iter().cloned().fold(SignalStrength::NEG_INFINITY, SignalStrength::max) - 80.0;
+            for (e, p) in e.iter().zip(p) {
+                if *e > global_floor + 0.01 {
+                    assert_approx_eq!(e, p, 0.01);
+                } else {
+                    assert!(*p >= floor && *p <= e + 0.01);
+                    if *p < e - 0.01 { num_kept += 1 }
+                }
+            }
+        }
+        assert!(num_kept > 0);
+
+        let coefficients: Vec<_> = produced.iter().map(|m| mfcc.cepstrum(m).unwrap()).collect();
+        let computed: Vec<_> = spectra.iter().map(|s| mfcc.compute(s).unwrap()).collect();
+        assert_frames_eq(&coefficients, &computed, 1e-4);
+    }
+
+    #[test]
+    fn test_deltas() {
+        // A coefficient rising steadily has a constant delta, apart from at the ends, and no acceleration in the middle.
+        let frames: Vec<Vec<SignalStrength>> = (0..6).map(|i| vec![i as SignalStrength, 1.0]).collect();
+        let produced = deltas(&frames, 1).unwrap();
+
+        let expected = [0.5, 1.0, 1.0, 1.0, 1.0, 0.5];
+        for (e, p) in expected.iter().zip(&produced) {
+            assert_eq!(vec![*e, 0.0], *p);
+        }
+
+        assert_eq!(0.0, deltas(&produced, 1).unwrap()[3][0]);
+        assert!(deltas(&[], 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let inputs_and_expected = vec![
+            ((0, SAMPLE_RATE, 0.0, 8000.0), Error::NumBands),
+            ((NUM_MELS, 0, 0.0, 8000.0), Error::SamplingRate(0)),
+            ((NUM_MELS, SAMPLE_RATE, -1.0, 8000.0), Error::LowerCutoff),
+            ((NUM_MELS, SAMPLE_RATE, 0.0, 0.0), Error::UpperCutoff),
+            ((NUM_MELS, SAMPLE_RATE, 4000.0, 2000.0), Error::CutoffOrder),
+        ];
+
+        for ((num_mels, sampling_rate, lower, upper), expected) in inputs_and_expected {
+            let produced = MelFilterbank::new(num_mels, FFT_LEN, sampling_rate, lower, upper, MelScale::Slaney);
+            assert_eq!(Some(expected), produced.err());
+        }
+
+        let filterbank = MelFilterbank::new(NUM_MELS, FFT_LEN, SAMPLE_RATE, 0.0, 8000.0, MelScale::Htk).unwrap();
+        assert_eq!(Some(Error::InputBuffer(FFT_LEN, 4)), filterbank.apply(&[0.0; 4]).err());
+        assert_eq!(Some(Error::InputBuffer(NUM_MELS, 4)), Mfcc::new(filterbank.clone(), NUM_MFCC).unwrap().cepstrum(&[0.0; 4]).err());
+        assert_eq!(Some(Error::NumCoefficients(0)), Mfcc::new(filterbank.clone(), 0).err());
+        assert_eq!(Some(Error::NumCoefficients(41)), Mfcc::new(filterbank, 41).err());
+        assert_eq!(Some(Error::DeltaWidth), deltas(&[], 0).err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Analyzes recorded audio frame by frame, without needing to play it back in real time.
+
+use std::path::Path;
+
+use crate::Error;
+use crate::analyzer::Analyzer;
+use crate::audio_file::AudioFile;
+use crate::beat::Detector;
+use crate::buckets::Buckets;
+use crate::constant_q::ConstantQ;
+use crate::sample::Sample;
+use crate::sample::SampleBuffer;
+use crate::types::SignalStrength;
+use crate::window_kind::WindowKind;
+
+/// The results of analyzing a single window of audio.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// The index of this frame in the stream.
+    pub index: usize,
+
+    /// The offset of the first sample in the window, in samples.
+    pub offset: usize,
+
+    /// The offset of the first sample in the window, in seconds.
+    pub timestamp: f64,
+
+    /// The left and right power spectra.
+    pub spectrum: (Vec<SignalStrength>, Vec<SignalStrength>),
+
+    /// The left and right band levels, if buckets or a constant-Q transform were provided.
+    pub bands: Option<(Vec<SignalStrength>, Vec<SignalStrength>)>,
+
+    /// Whether this window contains a beat, if a beat detector was provided.
+    pub is_beat: Option<bool>,
+}
+
+/// Slides a window across a stream of samples, analyzing each window in turn.
+pub struct OfflineAnalyzer {
+    analyzer: Analyzer,
+    hop: usize,
+    buckets: Option<Buckets>,
+    constant_q: Option<ConstantQ>,
+    detector: Option<Detector>,
+}
+
+impl OfflineAnalyzer {
+    /// Creates a new analyzer whose windows are `fft_len` samples long and start `hop` samples apart.
+    pub fn new(fft_len: usize, hop: usize, window_kind: WindowKind) -> Result<Self, Error> {
+        if !(hop > 0) { Err(Error::HopSize)? }
+
+        Ok(Self {
+            analyzer: Analyzer::new(fft_len, window_kind),
+            hop,
+            buckets: None,
+            constant_q: None,
+            detector: None,
+        })
+    }
+
+    /// Also groups each spectrum into the given frequency bands.
+    pub fn with_buckets(mut self, buckets: Buckets) -> Self {
+        self.buckets = Some(buckets);
+        self
+    }
+
+    /// Also computes band levels with a constant-Q transform, in place of any buckets,
+    /// using the end of each window. The window must be at least as long as the transform's frames,
+    /// and streams must have the sampling rate the transform was created for.
+    pub fn with_constant_q(mut self, constant_q: ConstantQ) -> Result<Self, Error> {
+        if constant_q.len() > self.len() { Err(Error::TooFewSamples(constant_q.len(), self.len()))? }
+
+        self.constant_q = Some(constant_q);
+        Ok(self)
+    }
+
+    /// Also runs each window through the given beat detector.
+    /// The detector must have been created with the same FFT length as this analyzer.
+    pub fn with_detector(mut self, detector: Detector) -> Self {
+        self.detector = Some(detector);
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.analyzer.len()
+    }
+
+    #[inline]
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// Lazily analyzes a stream of stereo samples.
+    /// A trailing partial hop at the end of the stream is not analyzed.
+    pub fn stream<I>(&mut self, samples: I, sampling_rate: usize) -> Frames<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = (Sample, Sample)>,
+    {
+        let sample_buffer = SampleBuffer::new(self.len());
+
+        Frames {
+            offline: self,
+            samples: samples.into_iter(),
+            sampling_rate,
+            sample_buffer,
+            chunk: Vec::new(),
+            index: 0,
+            offset: 0,
+        }
+    }
+
+    /// Analyzes an entire audio file.
+    pub fn analyze_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<Frame>, Error> {
+        let mut audio_file = AudioFile::open(path)?;
+        let sampling_rate = audio_file.sample_rate();
+
+        let frames = self.stream(&mut audio_file, sampling_rate).collect::<Result<Vec<_>, _>>()?;
+
+        // Read errors end the stream early, so check for one once the stream is done.
+        match audio_file.error() {
+            Some(e) => Err(e),
+            None => Ok(frames),
+        }
+    }
+}
+
+/// Iterator over the analyzed frames of a stream of samples.
+pub struct Frames<'a, I> {
+    offline: &'a mut OfflineAnalyzer,
+    samples: I,
+    sampling_rate: usize,
+    sample_buffer: SampleBuffer,
+    chunk: Vec<(Sample, Sample)>,
+    index: usize,
+    offset: usize,
+}
+
+impl<I> Frames<'_, I>
+where
+    I: Iterator<Item = (Sample, Sample)>,
+{
+    fn analyze(&mut self) -> Result<Frame, Error> {
+        let (spectrum_l, spectrum_r) = self.offline.analyzer.analyze(&self.sample_buffer)?;
+
+        let bands = match (&mut self.offline.constant_q, &self.offline.buckets) {
+            (Some(constant_q), _) => Some(constant_q.transform(&self.sample_buffer)?),
+            (None, Some(buckets)) => Some((
+                buckets.bucketize(spectrum_l, self.sampling_rate)?,
+                buckets.bucketize(spectrum_r, self.sampling_rate)?,
+            )),
+            (None, None) => None,
+        };
+
+        let spectrum = (spectrum_l.to_vec(), spectrum_r.to_vec());
+
+        let is_beat = match &mut self.offline.detector {
+            Some(detector) => Some(detector.is_beat(&self.sample_buffer, self.sampling_rate)?),
+            None => None,
+        };
+
+        Ok(Frame {
+            index: self.index,
+            offset: self.offset,
+            timestamp: self.offset as f64 / self.sampling_rate as f64,
+            spectrum,
+            bands,
+            is_beat,
+        })
+    }
+}
+
+impl<I> Iterator for Frames<'_, I>
+where
+    I: Iterator<Item = (Sample, Sample)>,
+{
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !(self.sampling_rate > 0) { return Some(Err(Error::SamplingRate(self.sampling_rate))) }
+
+        // The transform's kernels are built for one sampling rate, and would put every band in the wrong place at another.
+        if let Some(constant_q) = &self.offline.constant_q {
+            if constant_q.sampling_rate() != self.sampling_rate { return Some(Err(Error::SamplingRate(self.sampling_rate))) }
+        }
+
+        // The first window needs to be filled completely, after that the window slides along by a hop.
+        let needed = if self.index == 0 { self.offline.len() } else { self.offline.hop };
+
+        self.chunk.clear();
+        self.chunk.extend(self.samples.by_ref().take(needed));
+
+        if self.chunk.len() < needed { return None }
+
+        self.sample_buffer.push(&self.chunk);
+
+        if self.index > 0 { self.offset += self.offline.hop }
+
+        let res = self.analyze();
+        self.index += 1;
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::TestUtil;
+    use crate::types::Frequency;
+
+    const FFT_LEN: usize = 4096;
+    const HOP: usize = 1024;
+
+    fn peak_frequency(spectrum: &[SignalStrength], sampling_rate: usize) -> Frequency {
+        // Include the Nyquist bin, since one of the fixtures is right up against it.
+        let peak_index =
+            spectrum
+            .iter()
+            .take(spectrum.len() / 2 + 1)
+            .enumerate()
+            .max_by(|(_, sa), (_, sb)| sa.partial_cmp(sb).unwrap())
+            .map(|(i, _)| i)
+            .unwrap()
+        ;
+
+        peak_index as Frequency * sampling_rate as Frequency / spectrum.len() as Frequency
+    }
+
+    #[test]
+    fn test_analyze_file() {
+        let inputs_and_expected = vec![
+            ("wav/sin_440hz_44100hz_samp.wav", 44100, 440.0),
+            ("wav/sin_1234hz_32000hz_samp.wav", 32000, 1234.0),
+            ("wav/sin_7999hz_16000hz_samp.wav", 16000, 7999.0),
+        ];
+
+        for (path, sampling_rate, expected) in inputs_and_expected {
+            let mut offline = OfflineAnalyzer::new(FFT_LEN, HOP, WindowKind::Hanning).unwrap();
+            let frames = offline.analyze_file(path).unwrap();
+
+            // Each fixture is three seconds long.
+            let expected_num_frames = (sampling_rate * 3 - FFT_LEN) / HOP + 1;
+            assert_eq!(expected_num_frames, frames.len());
+
+            let fft_bin_size = sampling_rate as Frequency / FFT_LEN as Frequency;
+
+            for (i, frame) in frames.iter().enumerate() {
+                assert_eq!(i, frame.index);
+                assert_eq!(i * HOP, frame.offset);
+                assert_approx_eq!((i * HOP) as f64 / sampling_rate as f64, frame.timestamp);
+                assert!(frame.bands.is_none());
+                assert!(frame.is_beat.is_none());
+
+                let produced = peak_frequency(&frame.spectrum.0, sampling_rate);
+                // The peak should land on one of the two bins straddling the expected frequency.
+                println!("{}: {}, {}", path, expected, produced);
+                assert!((expected - produced).abs() < fft_bin_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        const SAMPLES_PER_PERIOD: usize = 44100;
+
+        let buckets = Buckets::new(20.0, 10000.0, 16).unwrap();
+        let detector = Detector::new(10, 3, 2, 20, 200, 1024).unwrap();
+
+        let mut offline =
+            OfflineAnalyzer::new(1024, 512, WindowKind::Blackman).unwrap()
+            .with_buckets(buckets.clone())
+            .with_detector(detector)
+        ;
+
+        let samples = TestUtil::generate_wave_samples(SAMPLES_PER_PERIOD, 1000.0, 1024 + 512 * 4 + 100);
+        let frames = offline
+            .stream(samples.into_iter().map(|s| (s, s)), SAMPLES_PER_PERIOD)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+        ;
+
+        // The trailing partial hop is dropped.
+        assert_eq!(5, frames.len());
+
+        let expected_band = buckets.locate(1000.0).unwrap();
+
+        for frame in frames {
+            let (bands_l, bands_r) = frame.bands.unwrap();
+            assert_eq!(bands_l, bands_r);
+
+            let produced_band =
+                bands_l
+                .iter()
+                .enumerate()
+                .max_by(|(_, sa), (_, sb)| sa.partial_cmp(sb).unwrap())
+                .map(|(i, _)| i)
+                .unwrap()
+            ;
+            assert_eq!(expected_band, produced_band);
+            assert!(frame.is_beat.is_some());
+        }
+
+        assert_eq!(Err(Error::HopSize), OfflineAnalyzer::new(1024, 0, WindowKind::Blackman).map(|_| ()));
+    }
+}
@@ -0,0 +1,400 @@
+//! Measures sample peaks and true peaks as specified in ITU-R BS.1770-4, and detects clipping.
+
+use crate::Error;
+use crate::sample::Sample;
+use crate::types::SignalStrength;
+use crate::util::FrameMeter;
+
+// The polyphase interpolating filter from annex 2 of BS.1770-4, which oversamples by four.
+// Each phase estimates the signal a further quarter of a sample along, about six samples back.
+const OVERSAMPLING_PHASES: [[f64; 12]; 4] = [
+    [
+        0.0017089843750, 0.0109863281250, -0.0196533203125, 0.0332031250000, -0.0594482421875, 0.1373291015625,
+        0.9721679687500, -0.1022949218750, 0.0476074218750, -0.0266113281250, 0.0148925781250, -0.0083007812500,
+    ],
+    [
+        -0.0291748046875, 0.0292968750000, -0.0517578125000, 0.0891113281250, -0.1665039062500, 0.4650878906250,
+        0.7797851562500, -0.2003173828125, 0.1015625000000, -0.0582275390625, 0.0330810546875, -0.0189208984375,
+    ],
+    [
+        -0.0189208984375, 0.0330810546875, -0.0582275390625, 0.1015625000000, -0.2003173828125, 0.7797851562500,
+        0.4650878906250, -0.1665039062500, 0.0891113281250, -0.0517578125000, 0.0292968750000, -0.0291748046875,
+    ],
+    [
+        -0.0083007812500, 0.0148925781250, -0.0266113281250, 0.0476074218750, -0.1022949218750, 0.9721679687500,
+        0.1373291015625, -0.0594482421875, 0.0332031250000, -0.0196533203125, 0.0109863281250, 0.0017089843750,
+    ],
+];
+const OVERSAMPLING_TAPS: usize = 12;
+
+// The state of one channel of the meter.
+#[derive(Clone, Debug)]
+struct Channel {
+    // The most recent samples, newest first, for the oversampling filter.
+    history: [f64; OVERSAMPLING_TAPS],
+
+    sample_peak: SignalStrength,
+    true_peak: SignalStrength,
+    max_sample_peak: SignalStrength,
+    max_true_peak: SignalStrength,
+
+    held_peak: SignalStrength,
+    hold_remaining: usize,
+
+    // How many samples in a row have been at or above the clip level.
+    clip_run: usize,
+    clip_events: usize,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            history: [0.0; OVERSAMPLING_TAPS],
+            sample_peak: 0.0,
+            true_peak: 0.0,
+            max_sample_peak: 0.0,
+            max_true_peak: 0.0,
+            held_peak: 0.0,
+            hold_remaining: 0,
+            clip_run: 0,
+            clip_events: 0,
+        }
+    }
+
+    // Forgets the peaks of the previous slice, but not the running statistics.
+    fn start(&mut self) {
+        self.sample_peak = 0.0;
+        self.true_peak = 0.0;
+    }
+
+    fn process(&mut self, x: Sample, settings: &Settings) {
+        let magnitude = x.abs();
+
+        self.history.copy_within(..OVERSAMPLING_TAPS - 1, 1);
+        self.history[0] = x as f64;
+
+        // The true peak can't be lower than the sample peak, even though the filter doesn't pass through the samples themselves.
+        let interpolated =
+            OVERSAMPLING_PHASES
+            .iter()
+            .map(|phase| phase.iter().zip(&self.history).map(|(h, x)| h * x).sum::<f64>().abs())
+            .fold(0.0, f64::max)
+            as SignalStrength
+        ;
+        let true_peak = interpolated.max(magnitude);
+
+        self.sample_peak = self.sample_peak.max(magnitude);
+        self.true_peak = self.true_peak.max(true_peak);
+        self.max_sample_peak = self.max_sample_peak.max(magnitude);
+        self.max_true_peak = self.max_true_peak.max(true_peak);
+
+        // The held peak stays put for the hold time, then falls until it meets the signal again.
+        if true_peak >= self.held_peak {
+            self.held_peak = true_peak;
+            self.hold_remaining = settings.hold_samples;
+        }
+        else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
+        }
+        else {
+            self.held_peak = (self.held_peak * settings.decay).max(true_peak);
+        }
+
+        // A run of clipped samples counts once, as soon as it is long enough.
+        if magnitude >= settings.clip_level {
+            self.clip_run += 1;
+            if self.clip_run == settings.clip_run { self.clip_events += 1 }
+        }
+        else {
+            self.clip_run = 0;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Settings {
+    hold_samples: usize,
+    // The factor the held peak falls by with each sample once the hold time is over.
+    decay: SignalStrength,
+    clip_level: SignalStrength,
+    clip_run: usize,
+}
+
+/// Meters the sample peak and true peak of each channel of a stereo stream, and counts clipping events.
+/// Peaks are linear, relative to full scale; the true peak in dBTP is `20 log10` of it.
+/// The oversampling filter is designed for 48 kHz, and is also accurate at higher sample rates.
+#[derive(Clone, Debug)]
+pub struct PeakMeter {
+    sampling_rate: usize,
+    hold_time: f32,
+    decay_rate: f32,
+    settings: Settings,
+    channels: [Channel; 2],
+}
+
+impl PeakMeter {
+    /// Creates a meter that holds peaks for 1.5 s before they fall at 20 dB per second,
+    /// and that counts three or more samples in a row at full scale as clipping.
+    pub fn new(sampling_rate: usize) -> Result<Self, Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let hold_time = 1.5;
+        let decay_rate = 20.0;
+
+        Ok(Self {
+            sampling_rate,
+            hold_time,
+            decay_rate,
+            settings: Settings {
+                hold_samples: hold_samples(hold_time, sampling_rate),
+                decay: decay(decay_rate, sampling_rate),
+                clip_level: 1.0,
+                clip_run: 3,
+            },
+            channels: [Channel::new(), Channel::new()],
+        })
+    }
+
+    /// Sets how long, in seconds, a peak is held, and how fast it then falls, in dB per second.
+    pub fn with_hold(mut self, hold_time: f32, decay_rate: f32) -> Result<Self, Error> {
+        if !(hold_time >= 0.0) { Err(Error::HoldTime)? }
+        if !(decay_rate > 0.0) { Err(Error::DecayRate)? }
+
+        self.hold_time = hold_time;
+        self.decay_rate = decay_rate;
+        self.settings.hold_samples = hold_samples(hold_time, self.sampling_rate);
+        self.settings.decay = decay(decay_rate, self.sampling_rate);
+        Ok(self)
+    }
+
+    /// Sets the level at which samples count as clipped, and how many of them in a row make a clipping event.
+    pub fn with_clipping(mut self, clip_level: SignalStrength, clip_run: usize) -> Result<Self, Error> {
+        if !(clip_level > 0.0 && clip_level <= 1.0) { Err(Error::ClipLevel)? }
+        if !(clip_run > 0) { Err(Error::ClipRun)? }
+
+        self.settings.clip_level = clip_level;
+        self.settings.clip_run = clip_run;
+        Ok(self)
+    }
+
+    #[inline]
+    pub fn hold_time(&self) -> f32 {
+        self.hold_time
+    }
+
+    #[inline]
+    pub fn decay_rate(&self) -> f32 {
+        self.decay_rate
+    }
+
+    /// Meters a slice of stereo samples, continuing on from the previous ones.
+    pub fn push(&mut self, sample_pairs: &[(Sample, Sample)]) {
+        self.push_pairs(sample_pairs);
+    }
+
+    /// Meters a slice of interleaved stereo samples, as delivered by an audio device.
+    pub fn push_interleaved(&mut self, samples: &[Sample]) {
+        self.push_interleaved_samples(samples);
+    }
+
+    fn map<T>(&self, f: impl Fn(&Channel) -> T) -> (T, T) {
+        (f(&self.channels[0]), f(&self.channels[1]))
+    }
+
+    /// The highest sample magnitude of the left and right channels in the last slice.
+    pub fn sample_peak(&self) -> (SignalStrength, SignalStrength) {
+        self.map(|c| c.sample_peak)
+    }
+
+    /// The highest oversampled magnitude of the left and right channels in the last slice.
+    pub fn true_peak(&self) -> (SignalStrength, SignalStrength) {
+        self.map(|c| c.true_peak)
+    }
+
+    /// The highest sample magnitude of the left and right channels since the meter was created or reset.
+    pub fn max_sample_peak(&self) -> (SignalStrength, SignalStrength) {
+        self.map(|c| c.max_sample_peak)
+    }
+
+    /// The highest oversampled magnitude of the left and right channels since the meter was created or reset.
+    pub fn max_true_peak(&self) -> (SignalStrength, SignalStrength) {
+        self.map(|c| c.max_true_peak)
+    }
+
+    /// The held true peak of the left and right channels, for drawing a peak indicator.
+    pub fn held_peak(&self) -> (SignalStrength, SignalStrength) {
+        self.map(|c| c.held_peak)
+    }
+
+    /// How many times the left and right channels have clipped since the meter was created or reset.
+    pub fn clip_events(&self) -> (usize, usize) {
+        self.map(|c| c.clip_events)
+    }
+
+    /// Forgets everything metered so far.
+    pub fn reset(&mut self) {
+        self.channels = [Channel::new(), Channel::new()];
+    }
+}
+
+impl FrameMeter for PeakMeter {
+    fn start_slice(&mut self) {
+        for channel in self.channels.iter_mut() { channel.start() }
+    }
+
+    fn push_frame(&mut self, l: Sample, r: Sample) {
+        self.channels[0].process(l, &self.settings);
+        self.channels[1].process(r, &self.settings);
+    }
+}
+
+fn hold_samples(hold_time: f32, sampling_rate: usize) -> usize {
+    (hold_time * sampling_rate as f32).round() as usize
+}
+
+fn decay(decay_rate: f32, sampling_rate: usize) -> SignalStrength {
+    10.0f32.powf(-decay_rate / 20.0 / sampling_rate as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+
+    const SAMPLE_RATE: usize = 48000;
+
+    fn to_db(x: SignalStrength) -> SignalStrength {
+        20.0 * x.log10()
+    }
+
+    // A full scale sine at the given fraction of the sample rate and starting phase, in radians.
+    fn sine(fraction: f64, phase: f64, len: usize) -> Vec<(Sample, Sample)> {
+        (0..len)
+            .map(|n| {
+                let s = (2.0 * std::f64::consts::PI * fraction * n as f64 + phase).sin() as Sample;
+                (s, s)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_oversampling_filter() {
+        // Each phase passes a constant signal almost unchanged, since the published filter is slightly lossy at low frequencies.
+        for phase in OVERSAMPLING_PHASES.iter() {
+            assert_approx_eq!(1.0, phase.iter().sum::<f64>(), 0.03);
+        }
+    }
+
+    #[test]
+    fn test_true_peak() {
+        // Sines at a quarter of the sample rate, with samples at different points of the waveform.
+        // The sample peaks fall short of full scale, but the true peaks don't, to within the +0.2/-0.4 dB of EBU Tech 3341.
+        let inputs_and_expected = vec![
+            (0.0, 0.0),
+            (std::f64::consts::PI / 8.0, -0.688),
+            (std::f64::consts::PI / 4.0, -3.010),
+        ];
+
+        for (phase, expected_sample_peak) in inputs_and_expected {
+            let mut meter = PeakMeter::new(SAMPLE_RATE).unwrap();
+            meter.push(&sine(0.25, phase, SAMPLE_RATE / 10));
+
+            let (sample_peak, _) = meter.sample_peak();
+            let (true_peak, _) = meter.true_peak();
+            println!("{}: {} dBFS, {} dBTP", phase, to_db(sample_peak), to_db(true_peak));
+
+            assert_approx_eq!(expected_sample_peak, to_db(sample_peak), 0.01);
+            assert!(to_db(true_peak) > -0.4 && to_db(true_peak) < 0.2);
+        }
+
+        // Low frequencies barely change between samples, so the true peak is the sample peak.
+        let mut meter = PeakMeter::new(SAMPLE_RATE).unwrap();
+        let quiet = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 100.0).with_amplitude(0.5);
+        meter.push(&StereoGen::mono(quiet).take(SAMPLE_RATE / 10).collect::<Vec<_>>());
+        assert_approx_eq!(meter.sample_peak().0, meter.true_peak().0, 1e-3);
+    }
+
+    #[test]
+    fn test_instantaneous_and_running() {
+        let mut meter = PeakMeter::new(SAMPLE_RATE).unwrap();
+
+        let loud = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1000.0).with_amplitude(0.8);
+        let quiet = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1000.0).with_amplitude(0.2);
+        meter.push(&StereoGen::stereo(loud, quiet).take(SAMPLE_RATE / 10).collect::<Vec<_>>());
+
+        let (l, r) = meter.sample_peak();
+        assert_approx_eq!(0.8, l, 1e-3);
+        assert_approx_eq!(0.2, r, 1e-3);
+
+        // The instantaneous peaks follow each slice, while the running peaks remember the loudest.
+        meter.push(&vec![(0.1, 0.1); 100]);
+        assert_approx_eq!(0.1, meter.sample_peak().0, 1e-3);
+        assert_approx_eq!(0.8, meter.max_sample_peak().0, 1e-3);
+        assert_approx_eq!(0.8, meter.max_true_peak().0, 0.01);
+
+        // Interleaved samples are metered the same way.
+        let mut interleaved = PeakMeter::new(SAMPLE_RATE).unwrap();
+        interleaved.push_interleaved(&[0.5, -0.25, -0.75, 0.125]);
+        assert_eq!((0.75, 0.25), interleaved.sample_peak());
+
+        meter.reset();
+        assert_eq!((0.0, 0.0), meter.max_sample_peak());
+        assert_eq!((0.0, 0.0), meter.held_peak());
+    }
+
+    #[test]
+    fn test_held_peak() {
+        let mut meter = PeakMeter::new(SAMPLE_RATE).unwrap().with_hold(0.5, 20.0).unwrap();
+        meter.push(&[(1.0, 0.5)]);
+
+        // The peak is held for the hold time.
+        meter.push(&vec![(0.0, 0.0); SAMPLE_RATE / 2 - 100]);
+        let (l, r) = meter.held_peak();
+        assert_approx_eq!(1.0, l, 1e-6);
+        assert_approx_eq!(0.5, r, 1e-6);
+
+        // Then it falls at the decay rate, so one second later it is 20 dB down.
+        meter.push(&vec![(0.0, 0.0); 100 + SAMPLE_RATE]);
+        let (l, r) = meter.held_peak();
+        assert_approx_eq!(-20.0, to_db(l), 0.1);
+        assert_approx_eq!(-26.0, to_db(r), 0.1);
+
+        // A louder peak takes over straight away.
+        meter.push(&[(0.5, 0.5)]);
+        assert!(meter.held_peak().0 >= 0.5);
+    }
+
+    #[test]
+    fn test_clip_events() {
+        let mut meter = PeakMeter::new(SAMPLE_RATE).unwrap();
+
+        // Two runs long enough to count on the left, and one run too short and one too quiet on the right.
+        let samples = vec![
+            (1.0, 1.0), (-1.0, 1.0), (1.0, 0.5), (1.0, 1.0), (1.0, 0.99),
+            (0.5, 0.99), (-1.0, 0.99), (-1.0, 0.0), (-1.0, 0.0), (-1.0, 0.0),
+        ];
+        meter.push(&samples);
+        assert_eq!((2, 0), meter.clip_events());
+
+        // A run continues across slices.
+        let mut meter = PeakMeter::new(SAMPLE_RATE).unwrap().with_clipping(0.98, 4).unwrap();
+        meter.push(&samples[..4]);
+        meter.push(&samples[4..]);
+        assert_eq!((2, 1), meter.clip_events());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(Error::SamplingRate(0)), PeakMeter::new(0).err());
+
+        let meter = PeakMeter::new(SAMPLE_RATE).unwrap();
+        assert_eq!(Some(Error::HoldTime), meter.clone().with_hold(-1.0, 20.0).err());
+        assert_eq!(Some(Error::DecayRate), meter.clone().with_hold(1.0, 0.0).err());
+        assert_eq!(Some(Error::ClipLevel), meter.clone().with_clipping(0.0, 3).err());
+        assert_eq!(Some(Error::ClipLevel), meter.clone().with_clipping(1.5, 3).err());
+        assert_eq!(Some(Error::ClipRun), meter.clone().with_clipping(1.0, 0).err());
+    }
+}
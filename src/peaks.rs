@@ -0,0 +1,338 @@
+//! Finds the strongest peaks in the spectra produced by `Analyzer`,
+//! estimating their frequency and power more finely than the spacing of the bins.
+
+use rustfft::num_complex::Complex;
+
+use crate::Error;
+use crate::sample::Sample;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+use crate::util::MIN_POWER;
+use crate::util::interpolate_peak;
+
+/// How the position and height of a peak are estimated from the bins around it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The centre of the loudest bin.
+    None,
+
+    /// A parabola through the magnitudes of the peak bin and its neighbours.
+    Parabolic,
+
+    /// A parabola through the log magnitudes, which is exact for a Gaussian window and close for most others.
+    #[default]
+    Gaussian,
+
+    /// Quinn's second estimator, which uses the complex spectrum and assumes a rectangular window.
+    Quinn,
+}
+
+/// A peak in a spectrum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    /// The loudest bin of the peak.
+    pub bin: usize,
+
+    /// The interpolated frequency, in Hz.
+    pub frequency: Frequency,
+
+    /// The interpolated power, on the same scale as the spectrum.
+    pub power: SignalStrength,
+
+    /// How far the peak stands out above the higher of the lowest points between it
+    /// and the nearest louder peak on either side, in decibels.
+    pub prominence: SignalStrength,
+}
+
+/// Picks the loudest peaks out of a spectrum.
+#[derive(Clone, Debug)]
+pub struct PeakPicker {
+    max_peaks: usize,
+    interpolation: Interpolation,
+    min_distance: Frequency,
+    min_prominence: SignalStrength,
+}
+
+impl PeakPicker {
+    /// Creates a picker that finds up to `max_peaks` peaks, with Gaussian interpolation,
+    /// no minimum distance between peaks, and a prominence of at least 3 dB.
+    pub fn new(max_peaks: usize) -> Self {
+        Self {
+            max_peaks,
+            interpolation: Interpolation::default(),
+            min_distance: 0.0,
+            min_prominence: 3.0,
+        }
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// Sets how close a peak can be to a louder one, in Hz.
+    pub fn with_min_distance(mut self, min_distance: Frequency) -> Result<Self, Error> {
+        if !(min_distance >= 0.0) { Err(Error::PeakDistance)? }
+
+        self.min_distance = min_distance;
+        Ok(self)
+    }
+
+    /// Sets how far a peak must stand out from its surroundings, in decibels.
+    pub fn with_prominence(mut self, min_prominence: SignalStrength) -> Result<Self, Error> {
+        if !(min_prominence >= 0.0) { Err(Error::Prominence)? }
+
+        self.min_prominence = min_prominence;
+        Ok(self)
+    }
+
+    /// Finds the loudest peaks in a power spectrum, as produced by `Analyzer`, loudest first.
+    /// Quinn's estimator needs the complex spectrum, so it can only be used with `find_complex`.
+    pub fn find(&self, spectrum: &[SignalStrength], sampling_rate: usize) -> Result<Vec<Peak>, Error> {
+        if self.interpolation == Interpolation::Quinn { Err(Error::ComplexSpectrum)? }
+
+        self.find_peaks(spectrum, None, sampling_rate)
+    }
+
+    /// Finds the loudest peaks in a complex spectrum, as returned by `Analyzer::complex_spectra`, loudest first.
+    pub fn find_complex(&self, spectrum: &[Complex<Sample>], sampling_rate: usize) -> Result<Vec<Peak>, Error> {
+        let powers: Vec<SignalStrength> = spectrum.iter().map(|c| c.norm_sqr()).collect();
+
+        self.find_peaks(&powers, Some(spectrum), sampling_rate)
+    }
+
+    fn find_peaks(
+        &self,
+        spectrum: &[SignalStrength],
+        complex: Option<&[Complex<Sample>]>,
+        sampling_rate: usize,
+        ) -> Result<Vec<Peak>, Error>
+    {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let num_bins = spectrum.len() / 2 + 1;
+        if num_bins < 3 { return Ok(Vec::new()) }
+
+        let powers = &spectrum[..num_bins];
+        let bin_size = sampling_rate as f64 / spectrum.len() as f64;
+
+        // Local maxima, where a plateau counts once at its first bin.
+        let mut candidates: Vec<usize> = (1..num_bins - 1)
+            .filter(|&k| powers[k] > powers[k - 1] && powers[k] >= powers[k + 1] && powers[k] > 0.0)
+            .collect();
+        candidates.sort_by(|&a, &b| powers[b].partial_cmp(&powers[a]).unwrap());
+
+        let mut peaks: Vec<Peak> = Vec::new();
+
+        for k in candidates {
+            if peaks.len() >= self.max_peaks { break }
+
+            let prominence = prominence(powers, k);
+            if !(prominence >= self.min_prominence) { continue }
+
+            let (offset, power) = match (self.interpolation, complex) {
+                (Interpolation::None, _) => (0.0, powers[k] as f64),
+                (Interpolation::Parabolic, _) => {
+                    let m = |i: usize| (powers[i] as f64).sqrt();
+                    let (offset, magnitude) = interpolate_peak(m(k - 1), m(k), m(k + 1));
+                    (offset, magnitude * magnitude)
+                },
+                (Interpolation::Gaussian, _) => {
+                    let l = |i: usize| 0.5 * (powers[i] as f64).max(MIN_POWER).ln();
+                    let (offset, log_magnitude) = interpolate_peak(l(k - 1), l(k), l(k + 1));
+                    (offset, (2.0 * log_magnitude).exp())
+                },
+                (Interpolation::Quinn, Some(complex)) => quinn(complex, k),
+                (Interpolation::Quinn, None) => Err(Error::ComplexSpectrum)?,
+            };
+
+            let frequency = (k as f64 + offset) * bin_size;
+            if peaks.iter().any(|p| ((p.frequency as f64) - frequency).abs() < self.min_distance as f64) { continue }
+
+            peaks.push(Peak {
+                bin: k,
+                frequency: frequency as Frequency,
+                power: power as SignalStrength,
+                prominence,
+            });
+        }
+
+        // Interpolation can change which of two similar peaks is louder.
+        peaks.sort_by(|a, b| b.power.partial_cmp(&a.power).unwrap());
+
+        Ok(peaks)
+    }
+}
+
+// How far a peak stands out above the higher of the lowest points on either side of it,
+// before reaching a louder bin or the end of the spectrum, in decibels.
+fn prominence(powers: &[SignalStrength], k: usize) -> SignalStrength {
+    let base = |bins: &mut dyn Iterator<Item = &SignalStrength>| -> SignalStrength {
+        bins.take_while(|&&p| p <= powers[k]).cloned().fold(powers[k], SignalStrength::min)
+    };
+
+    let left = base(&mut powers[..k].iter().rev());
+    let right = base(&mut powers[k + 1..].iter());
+
+    let db = |p: SignalStrength| 10.0 * (p as f64).max(MIN_POWER).log10();
+    (db(powers[k]) - db(left.max(right))) as SignalStrength
+}
+
+// Quinn's second estimator, from Quinn, "Estimation of frequency, amplitude, and phase from the DFT of a time series" (1997).
+// The power is corrected for where the tone falls between bins, assuming a rectangular window.
+fn quinn(spectrum: &[Complex<Sample>], k: usize) -> (f64, f64) {
+    let x = |i: usize| Complex::new(spectrum[i].re as f64, spectrum[i].im as f64);
+    let peak = x(k);
+    let norm = peak.norm_sqr();
+    if !(norm > 0.0) { return (0.0, 0.0) }
+
+    let ratio = |i: usize| (x(i) * peak.conj()).re / norm;
+    let (ap, am) = (ratio(k + 1), ratio(k - 1));
+    let (dp, dm) = (-ap / (1.0 - ap), am / (1.0 - am));
+
+    let tau = |x: f64| {
+        let r = (2.0f64 / 3.0).sqrt();
+        0.25 * (3.0 * x * x + 6.0 * x + 1.0).ln() - 6.0f64.sqrt() / 24.0 * ((x + 1.0 - r) / (x + 1.0 + r)).ln()
+    };
+    let offset = (dp + dm) / 2.0 + tau(dp * dp) - tau(dm * dm);
+
+    let x = std::f64::consts::PI * offset;
+    let gain = if x.abs() > 1e-9 { x.sin() / x } else { 1.0 };
+
+    (offset, norm / (gain * gain))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analyzer::Analyzer;
+    use crate::sample::SampleBuffer;
+    use crate::wave::MultiTone;
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+    use crate::window_kind::WindowKind;
+
+    const SAMPLE_RATE: usize = 44100;
+    const FFT_LEN: usize = 2048;
+
+    fn analyze<I>(signal: I, window_kind: WindowKind) -> Analyzer
+    where
+        I: Iterator<Item = Sample> + Send + 'static,
+    {
+        let samples: Vec<(Sample, Sample)> = StereoGen::mono(signal).take(FFT_LEN).collect();
+        let mut analyzer = Analyzer::new(FFT_LEN, window_kind).with_amplitude_correction();
+        analyzer.analyze(&SampleBuffer::from(samples)).unwrap();
+        analyzer
+    }
+
+    fn db(power: SignalStrength) -> SignalStrength {
+        10.0 * power.log10()
+    }
+
+    #[test]
+    fn test_interpolation() {
+        // A tone at 440 Hz falls in bin 20, which is centred on 430.7 Hz.
+        // Interpolation, window, and the largest frequency and level errors expected.
+        let inputs_and_expected = vec![
+            ((Interpolation::None, WindowKind::Hanning), (10.0, 1.5)),
+            ((Interpolation::Parabolic, WindowKind::Hanning), (1.0, 0.6)),
+            ((Interpolation::Gaussian, WindowKind::Hanning), (0.3, 0.3)),
+            ((Interpolation::Quinn, WindowKind::Rectangular), (0.15, 0.05)),
+        ];
+
+        let expected_power = db((0.25 * FFT_LEN as SignalStrength / 2.0).powi(2));
+
+        for ((interpolation, window_kind), (frequency_error, level_error)) in inputs_and_expected {
+            let analyzer = analyze(WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 440.0), window_kind);
+            let picker = PeakPicker::new(1).with_interpolation(interpolation);
+
+            let produced = picker.find_complex(analyzer.complex_spectra().0, SAMPLE_RATE).unwrap();
+            println!("{:?}: {:?}", interpolation, produced);
+
+            assert_eq!(1, produced.len());
+            assert_eq!(20, produced[0].bin);
+            assert!((produced[0].frequency - 440.0).abs() < frequency_error);
+
+            // The complex spectrum isn't corrected for the window, unlike the power spectrum.
+            let power =
+                if window_kind == WindowKind::Rectangular { produced[0].power }
+                else { produced[0].power / 0.25 }
+            ;
+            assert!((db(power) - expected_power).abs() < level_error);
+        }
+    }
+
+    #[test]
+    fn test_power_spectrum() {
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let samples: Vec<(Sample, Sample)> = StereoGen::mono(WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1234.5)).take(FFT_LEN).collect();
+        let spectrum = analyzer.analyze(&SampleBuffer::from(samples)).unwrap().0.to_vec();
+
+        let produced = PeakPicker::new(1).find(&spectrum, SAMPLE_RATE).unwrap();
+        assert!((produced[0].frequency - 1234.5).abs() < 0.5);
+
+        // The power spectrum gives the same results as the complex one.
+        let complex = PeakPicker::new(1).find_complex(analyzer.complex_spectra().0, SAMPLE_RATE).unwrap();
+        assert_approx_eq!(produced[0].frequency, complex[0].frequency, 1e-3);
+
+        let quinn = PeakPicker::new(1).with_interpolation(Interpolation::Quinn);
+        assert_eq!(Some(Error::ComplexSpectrum), quinn.find(&spectrum, SAMPLE_RATE).err());
+    }
+
+    #[test]
+    fn test_top_peaks() {
+        let loudest = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 2500.0).with_amplitude(0.5);
+        let analyzer = analyze(MultiTone::new(SAMPLE_RATE, &[300.0, 1000.0, 4000.0]).with_tone(loudest), WindowKind::BlackmanHarris);
+        let spectrum = analyzer.complex_spectra().0;
+
+        // Loudest first, and only as many as asked for.
+        let produced = PeakPicker::new(4).find_complex(spectrum, SAMPLE_RATE).unwrap();
+        let frequencies: Vec<Frequency> = produced.iter().map(|p| p.frequency).collect();
+        println!("{:?}", produced);
+
+        assert_eq!(4, frequencies.len());
+        assert!((frequencies[0] - 2500.0).abs() < 1.0);
+
+        let mut quieter = frequencies[1..].to_vec();
+        quieter.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (produced, expected) in quieter.iter().zip(&[300.0, 1000.0, 4000.0]) {
+            assert!((produced - expected).abs() < 1.0);
+        }
+        assert!(produced.windows(2).all(|w| w[0].power >= w[1].power));
+
+        assert_eq!(2, PeakPicker::new(2).find_complex(spectrum, SAMPLE_RATE).unwrap().len());
+    }
+
+    #[test]
+    fn test_distance_and_prominence() {
+        // Two tones 150 Hz apart, one 20 dB quieter than the other.
+        let signal = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1000.0)
+            .with_amplitude(0.5)
+            .zip(WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1150.0).with_amplitude(0.05))
+            .map(|(a, b)| a + b)
+        ;
+        let analyzer = analyze(signal, WindowKind::BlackmanHarris);
+        let spectrum = analyzer.complex_spectra().0;
+
+        let produced = PeakPicker::new(2).find_complex(spectrum, SAMPLE_RATE).unwrap();
+        assert_eq!(2, produced.len());
+        assert!((produced[1].frequency - 1150.0).abs() < 1.0);
+        assert!(produced[0].prominence > produced[1].prominence);
+
+        let produced = PeakPicker::new(2).with_min_distance(200.0).unwrap().find_complex(spectrum, SAMPLE_RATE).unwrap();
+        assert!((produced[1].frequency - 1150.0).abs() > 200.0);
+
+        let produced = PeakPicker::new(2).with_prominence(150.0).unwrap().find_complex(spectrum, SAMPLE_RATE).unwrap();
+        assert!(produced.len() < 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(Error::PeakDistance), PeakPicker::new(1).with_min_distance(-1.0).err());
+        assert_eq!(Some(Error::Prominence), PeakPicker::new(1).with_prominence(-1.0).err());
+        assert_eq!(Some(Error::SamplingRate(0)), PeakPicker::new(1).find(&[0.0; 8], 0).err());
+        assert_eq!(Ok(Vec::new()), PeakPicker::new(1).find(&[0.0; 8], SAMPLE_RATE));
+    }
+}
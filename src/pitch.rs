@@ -0,0 +1,263 @@
+//! Estimates the fundamental frequency of monophonic audio in the time domain, using the McLeod pitch method
+//! from McLeod and Wyvill, "A smarter way to find pitch" (2005).
+
+use crate::Error;
+use crate::sample::Sample;
+use crate::sample::SampleBuffer;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+use crate::util::interpolate_peak;
+
+// Key maxima within this fraction of the highest are candidates for the period, and the shortest is chosen,
+// so that a strong second harmonic isn't mistaken for the fundamental.
+const KEY_MAXIMUM_FRACTION: f64 = 0.93;
+
+/// The pitch found in a window of audio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pitch {
+    /// A clear fundamental, and how periodic the audio is, from 0 to 1.
+    Voiced { frequency: Frequency, clarity: SignalStrength },
+
+    /// Silence, noise, or a pitch outside the detector's range.
+    Unvoiced,
+}
+
+impl Pitch {
+    pub fn frequency(&self) -> Option<Frequency> {
+        match self {
+            &Pitch::Voiced { frequency, .. } => Some(frequency),
+            &Pitch::Unvoiced => None,
+        }
+    }
+}
+
+/// Detects the pitch of the most recent samples in a buffer.
+#[derive(Clone, Debug)]
+pub struct PitchDetector {
+    len: usize,
+    lower_cutoff: Frequency,
+    upper_cutoff: Frequency,
+    threshold: SignalStrength,
+}
+
+impl PitchDetector {
+    /// Creates a detector that looks at the last `len` samples,
+    /// for pitches from 40 Hz to 4 kHz with a clarity of at least 0.8.
+    /// The window needs to hold at least two periods of the lowest pitch.
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            lower_cutoff: 40.0,
+            upper_cutoff: 4000.0,
+            threshold: 0.8,
+        }
+    }
+
+    /// Sets the range of pitches that are detected.
+    pub fn with_range(mut self, lower_cutoff: Frequency, upper_cutoff: Frequency) -> Result<Self, Error> {
+        if !(lower_cutoff > 0.0) { Err(Error::LowerCutoff)? }
+        if !(upper_cutoff > 0.0) { Err(Error::UpperCutoff)? }
+        if !(lower_cutoff < upper_cutoff) { Err(Error::CutoffOrder)? }
+
+        self.lower_cutoff = lower_cutoff;
+        self.upper_cutoff = upper_cutoff;
+        Ok(self)
+    }
+
+    /// Sets the clarity below which audio is considered unvoiced.
+    pub fn with_threshold(mut self, threshold: SignalStrength) -> Result<Self, Error> {
+        if !(threshold > 0.0 && threshold <= 1.0) { Err(Error::ClarityThreshold)? }
+
+        self.threshold = threshold;
+        Ok(self)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Detects the pitch of the left and right channels of the last `len` samples.
+    pub fn detect(&self, samples: &SampleBuffer, sampling_rate: usize) -> Result<(Pitch, Pitch), Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let (left, right): (Vec<Sample>, Vec<Sample>) = samples.iter_tail(self.len)?.unzip();
+
+        Ok((self.detect_samples(&left, sampling_rate), self.detect_samples(&right, sampling_rate)))
+    }
+
+    /// Detects the pitch of a single channel, using all of the given samples.
+    pub fn detect_samples(&self, samples: &[Sample], sampling_rate: usize) -> Pitch {
+        let sampling_rate = sampling_rate as f64;
+        let min_lag = (sampling_rate / self.upper_cutoff as f64).floor() as usize;
+        let max_lag = ((sampling_rate / self.lower_cutoff as f64).ceil() as usize + 1).min(samples.len() / 2);
+
+        let nsdf = nsdf(samples, max_lag + 1);
+
+        // A key maximum is the highest point between each rising and falling zero crossing,
+        // skipping the lobe around zero lag.
+        let mut key_maxima = Vec::new();
+        let mut current: Option<usize> = None;
+        let start = nsdf.iter().position(|&n| n <= 0.0).unwrap_or(nsdf.len());
+
+        for tau in start.max(1)..nsdf.len() {
+            if nsdf[tau] > 0.0 {
+                let higher = match current {
+                    Some(c) => nsdf[tau] > nsdf[c],
+                    None => true,
+                };
+                if higher { current = Some(tau) }
+            }
+            else if let Some(c) = current.take() {
+                key_maxima.push(c);
+            }
+        }
+        key_maxima.extend(current);
+
+        let highest = key_maxima.iter().map(|&k| nsdf[k]).fold(0.0, f64::max);
+        let chosen = key_maxima.into_iter().find(|&k| nsdf[k] >= KEY_MAXIMUM_FRACTION * highest);
+
+        // The period is chosen before checking the range, so that a pitch above the range isn't reported as one of its subharmonics.
+        let tau = match chosen {
+            Some(tau) if tau >= min_lag && tau + 1 < nsdf.len() => tau,
+            _ => return Pitch::Unvoiced,
+        };
+
+        let (lag, clarity) = interpolate_peak(nsdf[tau - 1], nsdf[tau], nsdf[tau + 1]);
+        let frequency = sampling_rate / (tau as f64 + lag);
+
+        if !(clarity >= self.threshold as f64) { return Pitch::Unvoiced }
+        if !(frequency >= self.lower_cutoff as f64 && frequency <= self.upper_cutoff as f64) { return Pitch::Unvoiced }
+
+        Pitch::Voiced {
+            frequency: frequency as Frequency,
+            clarity: clarity.min(1.0) as SignalStrength,
+        }
+    }
+}
+
+// The normalized square difference function, from lag zero up to but not including `num_lags`.
+// It is one where the signal matches itself exactly, and zero where there is no correlation.
+fn nsdf(samples: &[Sample], num_lags: usize) -> Vec<f64> {
+    let x: Vec<f64> = samples.iter().map(|&s| s as f64).collect();
+
+    (0..num_lags.min(x.len()))
+        .map(|tau| {
+            let (a, b) = (&x[..x.len() - tau], &x[tau..]);
+            let r: f64 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+            let m: f64 = a.iter().chain(b).map(|v| v * v).sum();
+            if m > 0.0 { 2.0 * r / m } else { 0.0 }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::audio_file::AudioFile;
+    use crate::wave::NoiseColor;
+    use crate::wave::NoiseGen;
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+
+    const SAMPLE_RATE: usize = 44100;
+    const LEN: usize = 4096;
+
+    fn cents(expected: Frequency, produced: Frequency) -> f64 {
+        1200.0 * (produced as f64 / expected as f64).log2()
+    }
+
+    fn detect<I>(signal: I) -> Pitch
+    where
+        I: Iterator<Item = Sample>,
+    {
+        let samples: Vec<Sample> = signal.take(LEN).collect();
+        PitchDetector::new(LEN).detect_samples(&samples, SAMPLE_RATE)
+    }
+
+    #[test]
+    fn test_tones() {
+        let inputs = vec![
+            (WaveFunction::Sine, 41.2),
+            (WaveFunction::Sine, 82.41),
+            (WaveFunction::Sine, 440.0),
+            (WaveFunction::Sine, 1234.5),
+            (WaveFunction::Sine, 3951.07),
+            (WaveFunction::BandLimitedSawtooth, 110.0),
+            (WaveFunction::BandLimitedSquare, 261.63),
+            (WaveFunction::BandLimitedTriangle, 987.77),
+        ];
+
+        for (function, frequency) in inputs {
+            let produced = detect(WaveGen::new(function, SAMPLE_RATE, frequency));
+            println!("{:?} {}: {:?}", function, frequency, produced);
+
+            match produced {
+                Pitch::Voiced { frequency: f, clarity } => {
+                    assert!(cents(frequency, f).abs() < 1.0);
+                    assert!(clarity > 0.95);
+                },
+                Pitch::Unvoiced => panic!("no pitch found"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let inputs_and_expected = vec![
+            (("wav/sin_440hz_44100hz_samp.wav", PitchDetector::new(LEN)), 440.0),
+            (("wav/sin_1234hz_32000hz_samp.wav", PitchDetector::new(LEN)), 1234.0),
+            (("wav/sin_7999hz_16000hz_samp.wav", PitchDetector::new(LEN).with_range(1000.0, 8000.0).unwrap()), 7999.0),
+        ];
+
+        for ((path, detector), expected) in inputs_and_expected {
+            let audio_file = AudioFile::open(path).unwrap();
+            let sampling_rate = audio_file.sample_rate();
+            let samples = SampleBuffer::from(audio_file.take(LEN * 2).collect::<Vec<_>>());
+
+            let (produced, _) = detector.detect(&samples, sampling_rate).unwrap();
+            println!("{}: {:?}", path, produced);
+
+            assert!(cents(expected, produced.frequency().unwrap()).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_unvoiced() {
+        assert_eq!(Pitch::Unvoiced, detect(std::iter::repeat(0.0)));
+        assert_eq!(Pitch::Unvoiced, detect(NoiseGen::new(NoiseColor::White, 5)));
+
+        // Tones outside the range are unvoiced, rather than reported as some other pitch.
+        let detector = PitchDetector::new(LEN).with_range(100.0, 500.0).unwrap();
+        for &frequency in &[1000.0, 1100.0] {
+            let samples: Vec<Sample> = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, frequency).take(LEN).collect();
+            assert_eq!(Pitch::Unvoiced, detector.detect_samples(&samples, SAMPLE_RATE));
+        }
+    }
+
+    #[test]
+    fn test_stereo() {
+        let left = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 220.0);
+        let right = NoiseGen::new(NoiseColor::Pink, 1);
+        let samples = SampleBuffer::from(StereoGen::stereo(left, right).take(LEN + 100).collect::<Vec<_>>());
+
+        let (produced_l, produced_r) = PitchDetector::new(LEN).detect(&samples, SAMPLE_RATE).unwrap();
+        assert!(cents(220.0, produced_l.frequency().unwrap()).abs() < 1.0);
+        assert_eq!(Pitch::Unvoiced, produced_r);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(Error::LowerCutoff), PitchDetector::new(LEN).with_range(0.0, 100.0).err());
+        assert_eq!(Some(Error::UpperCutoff), PitchDetector::new(LEN).with_range(50.0, 0.0).err());
+        assert_eq!(Some(Error::CutoffOrder), PitchDetector::new(LEN).with_range(500.0, 100.0).err());
+        assert_eq!(Some(Error::ClarityThreshold), PitchDetector::new(LEN).with_threshold(0.0).err());
+        assert_eq!(Some(Error::ClarityThreshold), PitchDetector::new(LEN).with_threshold(1.1).err());
+
+        let samples = SampleBuffer::new(LEN / 2);
+        assert_eq!(Some(Error::TooFewSamples(LEN, LEN / 2)), PitchDetector::new(LEN).detect(&samples, SAMPLE_RATE).err());
+        assert_eq!(Some(Error::SamplingRate(0)), PitchDetector::new(LEN).detect(&samples, 0).err());
+    }
+}
@@ -115,6 +115,11 @@ impl<'a> SampleBufferIter<'a> {
             }
         }
     }
+
+    /// Finds the highest magnitude of the left and right channels of the samples in this iterator.
+    pub fn peak(self) -> (SignalStrength, SignalStrength) {
+        self.fold((0.0, 0.0), |(pl, pr), (l, r)| (pl.max(l.abs()), pr.max(r.abs())))
+    }
 }
 
 impl Iterator for SampleBufferIter<'_> {
@@ -158,4 +163,20 @@ mod tests {
             assert_approx_eq!(expected, produced);
         }
     }
+
+    #[test]
+    fn test_peak() {
+        let inputs_and_expected = vec![
+            (SampleBuffer::from(vec![0.0; 16]), (0.0, 0.0)),
+            (SampleBuffer::from(vec![(0.5, -0.25), (-0.75, 0.125)]), (0.75, 0.25)),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            assert_eq!(expected, input.iter().peak());
+        }
+
+        // Only the tail is measured.
+        let buffer = SampleBuffer::from(vec![(1.0, 1.0), (0.5, -0.5)]);
+        assert_eq!((0.5, 0.5), buffer.iter_tail(1).unwrap().peak());
+    }
 }
@@ -0,0 +1,360 @@
+//! Combinators for building test signals out of generators, such as a beat over noise with a tone fading in.
+
+use crate::sample::Sample;
+use crate::wave::Signal;
+
+/// Adds combinators to any iterator of samples.
+pub trait SignalExt: Iterator<Item = Sample> + Sized {
+    /// Scales every sample by the given gain.
+    fn gain(self, gain: Sample) -> Gain<Self> {
+        Gain { signal: self, gain }
+    }
+
+    /// Adds another signal to this one.
+    /// The mix carries on until both signals end, with whichever ends first treated as silence.
+    fn mix<I>(self, other: I) -> Mix<Self, I>
+    where
+        I: Iterator<Item = Sample>,
+    {
+        Mix { a: self, b: other }
+    }
+
+    /// Shapes this signal with an envelope, releasing it `hold` samples after it starts.
+    /// The signal ends once the release is over.
+    fn envelope(self, adsr: Adsr, hold: usize) -> Envelope<Self> {
+        Envelope {
+            signal: self,
+            adsr,
+            hold,
+            sample_index: 0,
+            release_level: 0.0,
+        }
+    }
+
+    /// Repeatedly lets this signal through for `open` samples, and then silences it for `closed` samples.
+    fn gate(self, open: usize, closed: usize) -> Gate<Self> {
+        Gate {
+            signal: self,
+            open,
+            period: open + closed,
+            sample_index: 0,
+        }
+    }
+
+    /// Plays `len` samples of this signal, followed by the next signal.
+    fn then<I>(self, len: usize, next: I) -> std::iter::Chain<std::iter::Take<Self>, I>
+    where
+        I: Iterator<Item = Sample>,
+    {
+        self.take(len).chain(next)
+    }
+}
+
+impl<I: Iterator<Item = Sample>> SignalExt for I {}
+
+#[derive(Clone, Debug)]
+pub struct Gain<I> {
+    signal: I,
+    gain: Sample,
+}
+
+impl<I: Iterator<Item = Sample>> Iterator for Gain<I> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.signal.next().map(|s| s * self.gain)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mix<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> Iterator for Mix<A, B>
+where
+    A: Iterator<Item = Sample>,
+    B: Iterator<Item = Sample>,
+{
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.a.next(), self.b.next()) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+        }
+    }
+}
+
+/// An attack, decay, sustain and release envelope, with linear segments.
+/// Times are given in samples, and the sustain level as a fraction of the peak.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adsr {
+    pub attack: usize,
+    pub decay: usize,
+    pub sustain: Sample,
+    pub release: usize,
+}
+
+impl Adsr {
+    pub fn new(attack: usize, decay: usize, sustain: Sample, release: usize) -> Self {
+        Self {
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+
+    /// The level of the envelope before it is released, `n` samples after it starts.
+    fn level(&self, n: usize) -> Sample {
+        if n < self.attack {
+            n as Sample / self.attack as Sample
+        }
+        else if n - self.attack < self.decay {
+            let x = (n - self.attack) as Sample / self.decay as Sample;
+            1.0 - (1.0 - self.sustain) * x
+        }
+        else { self.sustain }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Envelope<I> {
+    signal: I,
+    adsr: Adsr,
+    hold: usize,
+    sample_index: usize,
+
+    // The level the envelope was at when it was released.
+    release_level: Sample,
+}
+
+impl<I: Iterator<Item = Sample>> Iterator for Envelope<I> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.sample_index;
+
+        let level =
+            if n < self.hold { self.adsr.level(n) }
+            else if n - self.hold < self.adsr.release {
+                if n == self.hold { self.release_level = self.adsr.level(n) }
+                let x = (n - self.hold) as Sample / self.adsr.release as Sample;
+                self.release_level * (1.0 - x)
+            }
+            else { return None }
+        ;
+
+        self.sample_index += 1;
+        self.signal.next().map(|s| s * level)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Gate<I> {
+    signal: I,
+    open: usize,
+    period: usize,
+    sample_index: usize,
+}
+
+impl<I: Iterator<Item = Sample>> Iterator for Gate<I> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_open = self.sample_index < self.open;
+        self.sample_index = (self.sample_index + 1) % self.period.max(1);
+
+        self.signal.next().map(|s| if is_open { s } else { 0.0 })
+    }
+}
+
+/// Plays signals at scheduled times, mixing together any that overlap, with silence in between.
+/// The sequence ends once every signal has ended.
+pub struct Sequence {
+    // Signals yet to start, with their start times, latest first.
+    pending: Vec<(usize, Signal)>,
+    playing: Vec<Signal>,
+    sample_index: usize,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            playing: Vec::new(),
+            sample_index: 0,
+        }
+    }
+
+    /// Starts a signal `start` samples into the sequence.
+    pub fn with_event<I>(mut self, start: usize, signal: I) -> Self
+    where
+        I: Iterator<Item = Sample> + Send + 'static,
+    {
+        self.pending.push((start, Box::new(signal)));
+        self.pending.sort_by_key(|&(start, _)| std::cmp::Reverse(start));
+        self
+    }
+
+    /// Starts `count` copies of a signal, the first `start` samples into the sequence
+    /// and the rest every `interval` samples after that.
+    pub fn with_repeated_event<I>(self, start: usize, interval: usize, count: usize, signal: I) -> Self
+    where
+        I: Iterator<Item = Sample> + Clone + Send + 'static,
+    {
+        (0..count).fold(self, |sequence, i| sequence.with_event(start + i * interval, signal.clone()))
+    }
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Iterator for Sequence {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((start, _)) = self.pending.last() {
+            if *start > self.sample_index { break }
+
+            let (_, signal) = self.pending.pop()?;
+            self.playing.push(signal);
+        }
+
+        let mut sum = 0.0;
+        let mut is_playing = false;
+
+        self.playing.retain_mut(|signal| match signal.next() {
+            Some(s) => {
+                sum += s;
+                is_playing = true;
+                true
+            },
+            None => false,
+        });
+
+        // Until the next signal starts, there is silence.
+        if !is_playing && self.pending.is_empty() { return None }
+
+        self.sample_index += 1;
+        Some(sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::beat::Detector;
+    use crate::beat::estimate_tempo;
+    use crate::sample::SampleBuffer;
+    use crate::wave::NoiseColor;
+    use crate::wave::NoiseGen;
+    use crate::wave::SweepKind;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+
+    const SAMPLE_RATE: usize = 44100;
+    const FFT_LEN: usize = 1024;
+
+    fn ones() -> impl Iterator<Item = Sample> + Clone + Send + 'static {
+        std::iter::repeat(1.0)
+    }
+
+    #[test]
+    fn test_gain_and_mix() {
+        let produced: Vec<Sample> = vec![1.0, 2.0, 3.0].into_iter().gain(2.0).mix(vec![0.5].into_iter()).collect();
+        assert_eq!(vec![2.5, 4.0, 6.0], produced);
+
+        let produced: Vec<Sample> = vec![1.0].into_iter().mix(vec![1.0, 1.0].into_iter()).collect();
+        assert_eq!(vec![2.0, 1.0], produced);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let inputs_and_expected = vec![
+            ((Adsr::new(2, 2, 0.5, 2), 6), vec![0.0, 0.5, 1.0, 0.75, 0.5, 0.5, 0.5, 0.25]),
+            // Released during the attack, from wherever it had got to.
+            ((Adsr::new(4, 0, 1.0, 2), 2), vec![0.0, 0.25, 0.5, 0.25]),
+            ((Adsr::new(0, 0, 1.0, 0), 3), vec![1.0, 1.0, 1.0]),
+        ];
+
+        for ((adsr, hold), expected) in inputs_and_expected {
+            let produced: Vec<Sample> = ones().envelope(adsr, hold).collect();
+            assert_eq!(expected, produced);
+        }
+
+        // The envelope ends early if the signal does.
+        assert_eq!(2, vec![1.0, 1.0].into_iter().envelope(Adsr::new(2, 2, 0.5, 2), 6).count());
+    }
+
+    #[test]
+    fn test_gate_and_then() {
+        let produced: Vec<Sample> = ones().gate(2, 1).take(7).collect();
+        assert_eq!(vec![1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0], produced);
+
+        let produced: Vec<Sample> = ones().then(2, ones().gain(3.0)).take(4).collect();
+        assert_eq!(vec![1.0, 1.0, 3.0, 3.0], produced);
+    }
+
+    #[test]
+    fn test_sequence() {
+        let produced: Vec<Sample> = Sequence::new()
+            .with_event(4, ones().take(2).gain(2.0))
+            .with_repeated_event(1, 2, 3, ones().take(1))
+            .collect()
+        ;
+        assert_eq!(vec![0.0, 1.0, 0.0, 1.0, 2.0, 3.0], produced);
+
+        assert_eq!(0, Sequence::new().count());
+    }
+
+    #[test]
+    fn test_beats_over_noise() {
+        // A kick every 500 ms over pink noise, with a 3 kHz tone fading in over the first two seconds.
+        let kick = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 150.0)
+            .with_amplitude(1.0)
+            .with_sweep(50.0, SAMPLE_RATE / 10, SweepKind::Logarithmic)
+            .unwrap()
+            .envelope(Adsr::new(SAMPLE_RATE / 500, SAMPLE_RATE / 10, 0.0, 0), SAMPLE_RATE / 10)
+        ;
+        let tone = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 3000.0).envelope(Adsr::new(SAMPLE_RATE * 2, 0, 1.0, 0), usize::MAX);
+        let noise = NoiseGen::new(NoiseColor::Pink, 0).gain(0.1);
+
+        let signal: Vec<Sample> = Sequence::new()
+            .with_repeated_event(SAMPLE_RATE, SAMPLE_RATE / 2, 8, kick)
+            .mix(tone)
+            .mix(noise)
+            .take(SAMPLE_RATE * 5)
+            .collect()
+        ;
+
+        let mut detector = Detector::new(10, 3, 2, 20, 200, FFT_LEN).unwrap();
+        let mut was_beat = false;
+        let mut onsets = Vec::new();
+
+        for (i, w) in signal.windows(FFT_LEN).step_by(FFT_LEN / 2).enumerate() {
+            let is_beat = detector.is_beat(&SampleBuffer::from(w.to_vec()), SAMPLE_RATE).unwrap();
+            if is_beat && !was_beat { onsets.push((i * FFT_LEN / 2) as f64 / SAMPLE_RATE as f64) }
+            was_beat = is_beat;
+        }
+
+        // The detector triggers now and then while it settles on the noise, before the first kick a second in.
+        // After that, each kick is picked up by one of the windows leading up to it, and nothing else is.
+        let window_len = FFT_LEN as f64 / SAMPLE_RATE as f64;
+        onsets.retain(|&t| t > 1.0 - 2.0 * window_len);
+        assert_eq!(8, onsets.len());
+
+        for (i, t) in onsets.iter().enumerate() {
+            let kick = 1.0 + 0.5 * i as f64;
+            assert!(*t <= kick && kick - t < 2.0 * window_len);
+        }
+
+        assert_approx_eq!(120.0, estimate_tempo(&onsets).unwrap(), 5.0);
+    }
+}
@@ -0,0 +1,527 @@
+//! Abstracts over producers of audio data that fill a sample buffer in the background.
+
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread::Builder as ThreadBuilder;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Error;
+use crate::audio_file::AudioFile;
+use crate::sample::Sample;
+use crate::sample::SampleBuffer;
+use crate::wave::WaveGen;
+
+// How long a paused source sleeps before checking its flags again.
+const IDLE_INTERVAL: Duration = Duration::from_millis(5);
+
+// How many chunks a reading thread can get ahead of the source.
+const READ_AHEAD: usize = 4;
+
+/// A producer of stereo audio that pushes samples into a `SampleBuffer` in the background.
+pub trait Source {
+    /// The sampling rate of the produced audio, in Hz.
+    fn sample_rate(&self) -> usize;
+
+    /// The buffer that receives the produced samples.
+    fn sample_buffer(&self) -> &SampleBuffer;
+
+    /// Starts or resumes producing samples.
+    fn play(&self);
+
+    /// Suspends producing samples.
+    fn pause(&self);
+
+    /// Stops producing samples for good, waiting for any thread of the source's own to finish,
+    /// apart from one blocked reading input, which finishes once its read returns.
+    fn stop(&mut self);
+
+    /// Returns true if the source has not yet been stopped or run out of samples.
+    fn is_running(&self) -> bool;
+}
+
+/// Controls how quickly a `StreamSource` pushes samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    /// Push samples at the rate they would be heard.
+    RealTime,
+
+    /// Push samples as fast as possible.
+    Unpaced,
+}
+
+/// Format of raw PCM input, always interleaved and little-endian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmFormat {
+    F32,
+    I16,
+}
+
+impl PcmFormat {
+    fn sample_size(&self) -> usize {
+        match self {
+            &PcmFormat::F32 => 4,
+            &PcmFormat::I16 => 2,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Sample {
+        match self {
+            &PcmFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            &PcmFormat::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as Sample / 32768.0,
+        }
+    }
+}
+
+struct PcmFrames<R: Read> {
+    reader: BufReader<R>,
+    num_channels: usize,
+    format: PcmFormat,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> Iterator for PcmFrames<R> {
+    type Item = (Sample, Sample);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A short read or an error ends the stream.
+        self.reader.read_exact(&mut self.bytes).ok()?;
+
+        let size = self.format.sample_size();
+        let l = self.format.decode(&self.bytes[..size]);
+        let r = if self.num_channels > 1 { self.format.decode(&self.bytes[size..]) } else { l };
+
+        Some((l, r))
+    }
+}
+
+// Pulls frames from an iterator that may block indefinitely, such as one reading from a pipe,
+// on a thread of its own, so that the source can still notice when it is stopped.
+// The thread is only started once the first frame is wanted, and is never joined.
+struct ReadAhead<I> {
+    frames: Option<I>,
+    read_size: usize,
+    receiver: Option<Receiver<Vec<(Sample, Sample)>>>,
+    chunk: std::vec::IntoIter<(Sample, Sample)>,
+    stop_flag: Arc<AtomicBool>,
+}
+
+impl<I> ReadAhead<I>
+where
+    I: Iterator<Item = (Sample, Sample)> + Send + 'static,
+{
+    fn new(frames: I, read_size: usize, stop_flag: Arc<AtomicBool>) -> Self {
+        Self {
+            frames: Some(frames),
+            read_size: read_size.max(1),
+            receiver: None,
+            chunk: Vec::new().into_iter(),
+            stop_flag,
+        }
+    }
+
+    fn start(mut frames: I, read_size: usize) -> Result<Receiver<Vec<(Sample, Sample)>>, Error> {
+        let (sender, receiver) = mpsc::sync_channel(READ_AHEAD);
+
+        ThreadBuilder::new().spawn(move || {
+            loop {
+                let chunk: Vec<_> = frames.by_ref().take(read_size).collect();
+
+                // Once the source is gone, there is no one left to send to.
+                if chunk.is_empty() || sender.send(chunk).is_err() { break }
+            }
+        })?;
+
+        Ok(receiver)
+    }
+}
+
+impl<I> Iterator for ReadAhead<I>
+where
+    I: Iterator<Item = (Sample, Sample)> + Send + 'static,
+{
+    type Item = (Sample, Sample);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(frame) = self.chunk.next() { return Some(frame) }
+
+            if let Some(frames) = self.frames.take() {
+                self.receiver = Some(Self::start(frames, self.read_size).ok()?);
+            }
+
+            match self.receiver.as_ref()?.recv_timeout(IDLE_INTERVAL) {
+                Ok(chunk) => self.chunk = chunk.into_iter(),
+                Err(RecvTimeoutError::Timeout) => if self.stop_flag.load(AtomicOrdering::SeqCst) { return None },
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+/// A source that pushes frames from an arbitrary iterator on a background thread.
+/// Sources are created paused, and stop once the iterator is exhausted.
+pub struct StreamSource {
+    sample_rate: usize,
+    sample_buffer: SampleBuffer,
+    playing: Arc<AtomicBool>,
+    stop_flag: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl StreamSource {
+    pub fn new<I>(frames: I, sample_rate: usize, buffer_len: usize, read_size: usize, pacing: Pacing) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (Sample, Sample)>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::with_stop_flag(frames, Arc::new(AtomicBool::new(false)), sample_rate, buffer_len, read_size, pacing)
+    }
+
+    fn with_stop_flag<I>(
+        frames: I,
+        stop_flag: Arc<AtomicBool>,
+        sample_rate: usize,
+        buffer_len: usize,
+        read_size: usize,
+        pacing: Pacing,
+        ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (Sample, Sample)>,
+        I::IntoIter: Send + 'static,
+    {
+        if !(sample_rate > 0) { Err(Error::SamplingRate(sample_rate))? }
+
+        let sample_buffer = SampleBuffer::new(buffer_len);
+
+        let playing = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        // Scope for thread spawning.
+        let handle = {
+            let mut frames = frames.into_iter();
+            let mut sample_buffer = sample_buffer.clone();
+            let playing = playing.clone();
+            let stop_flag = stop_flag.clone();
+            let finished = finished.clone();
+
+            let read_size = read_size.max(1);
+
+            ThreadBuilder::new()
+                .spawn(move || {
+                    let mut chunk = Vec::with_capacity(read_size);
+
+                    // The time playback (re)started, and the number of frames pushed since.
+                    let mut clock: Option<(Instant, usize)> = None;
+
+                    while !stop_flag.load(AtomicOrdering::SeqCst) {
+                        if !playing.load(AtomicOrdering::SeqCst) {
+                            clock = None;
+                            std::thread::sleep(IDLE_INTERVAL);
+                            continue;
+                        }
+
+                        chunk.clear();
+                        chunk.extend(frames.by_ref().take(read_size));
+
+                        if chunk.is_empty() { break }
+
+                        sample_buffer.push(&chunk);
+
+                        if pacing == Pacing::RealTime {
+                            let (start, pushed) = clock.get_or_insert_with(|| (Instant::now(), 0));
+                            *pushed += chunk.len();
+
+                            // Sleeping until an absolute deadline keeps timing errors from accumulating.
+                            let deadline = *start + Duration::from_secs_f64(*pushed as f64 / sample_rate as f64);
+                            let now = Instant::now();
+                            if deadline > now { std::thread::sleep(deadline - now) }
+                        }
+                    }
+
+                    finished.store(true, AtomicOrdering::SeqCst);
+                })
+                .unwrap()
+        };
+
+        Ok(Self {
+            sample_rate,
+            sample_buffer,
+            playing,
+            stop_flag,
+            finished,
+            handle: Some(handle),
+        })
+    }
+
+    /// Creates a source that reads frames from an audio file.
+    pub fn file<P: AsRef<Path>>(path: P, buffer_len: usize, read_size: usize, pacing: Pacing) -> Result<Self, Error> {
+        let audio_file = AudioFile::open(path)?;
+        let sample_rate = audio_file.sample_rate();
+
+        Self::new(audio_file, sample_rate, buffer_len, read_size, pacing)
+    }
+
+    /// Creates a source that reads interleaved raw PCM data from a reader.
+    /// Mono input is duplicated to both channels, and channels past the first two are ignored.
+    /// Reads happen on a separate thread, so that stopping the source doesn't wait for more input;
+    /// a read still blocked at that point is left to finish on its own.
+    pub fn raw_pcm<R>(
+        reader: R,
+        format: PcmFormat,
+        num_channels: usize,
+        sample_rate: usize,
+        buffer_len: usize,
+        read_size: usize,
+        pacing: Pacing,
+        ) -> Result<Self, Error>
+    where
+        R: Read + Send + 'static,
+    {
+        if !(num_channels > 0) { Err(Error::NumChannels(num_channels))? }
+
+        let frames = PcmFrames {
+            reader: BufReader::new(reader),
+            num_channels,
+            format,
+            bytes: vec![0; format.sample_size() * num_channels],
+        };
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let frames = ReadAhead::new(frames, read_size, stop_flag.clone());
+
+        Self::with_stop_flag(frames, stop_flag, sample_rate, buffer_len, read_size, pacing)
+    }
+
+    /// Creates a source that reads interleaved raw PCM data from standard input.
+    pub fn stdin(
+        format: PcmFormat,
+        num_channels: usize,
+        sample_rate: usize,
+        buffer_len: usize,
+        read_size: usize,
+        pacing: Pacing,
+        ) -> Result<Self, Error>
+    {
+        Self::raw_pcm(std::io::stdin(), format, num_channels, sample_rate, buffer_len, read_size, pacing)
+    }
+
+    /// Creates a source that endlessly synthesizes a waveform, duplicated to both channels.
+    pub fn wave_gen(wave_gen: WaveGen, buffer_len: usize, read_size: usize, pacing: Pacing) -> Result<Self, Error> {
+        let sample_rate = wave_gen.samples_per_period();
+
+        Self::new(wave_gen.map(|s| (s, s)), sample_rate, buffer_len, read_size, pacing)
+    }
+
+    /// Returns true if all frames have been pushed.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Blocks until all frames have been pushed, joining the background thread.
+    /// This will block forever if the source is paused or never runs out of frames.
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Source for StreamSource {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn sample_buffer(&self) -> &SampleBuffer {
+        &self.sample_buffer
+    }
+
+    fn play(&self) {
+        self.playing.store(true, AtomicOrdering::SeqCst);
+    }
+
+    fn pause(&self) {
+        self.playing.store(false, AtomicOrdering::SeqCst);
+    }
+
+    fn stop(&mut self) {
+        self.stop_flag.store(true, AtomicOrdering::SeqCst);
+        self.wait();
+    }
+
+    fn is_running(&self) -> bool {
+        self.handle.is_some() && !self.is_finished()
+    }
+}
+
+impl Drop for StreamSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::analyzer::Analyzer;
+    use crate::window_kind::WindowKind;
+    use crate::wave::WaveFunction;
+
+    const SAMPLE_RATE: usize = 44100;
+    const FFT_LEN: usize = 2048;
+
+    // Stands in for pipeline code that only knows about the `Source` trait.
+    fn peak_bin<S: Source>(source: &S, analyzer: &mut Analyzer) -> usize {
+        let (spectrum, _) = analyzer.analyze(source.sample_buffer()).unwrap();
+
+        spectrum
+            .iter()
+            .take(spectrum.len() / 2)
+            .enumerate()
+            .max_by(|(_, sa), (_, sb)| sa.partial_cmp(sb).unwrap())
+            .map(|(i, _)| i)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_wave_gen() {
+        let wave_gen = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 440.0);
+        let mut source = StreamSource::new(wave_gen.take(SAMPLE_RATE).map(|s| (s, s)), SAMPLE_RATE, FFT_LEN, 256, Pacing::Unpaced).unwrap();
+
+        source.play();
+        source.wait();
+
+        assert!(source.is_finished());
+        assert!(!source.is_running());
+
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let expected = (440.0 / (SAMPLE_RATE as f32 / FFT_LEN as f32)).round() as usize;
+
+        assert_eq!(expected, peak_bin(&source, &mut analyzer));
+    }
+
+    #[test]
+    fn test_pause_and_stop() {
+        let wave_gen = WaveGen::new(WaveFunction::Square, SAMPLE_RATE, 440.0);
+        let mut source = StreamSource::wave_gen(wave_gen, 64, 16, Pacing::RealTime).unwrap();
+
+        // Nothing should be pushed until the source is played.
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(source.sample_buffer().iter().all(|(l, r)| l == 0.0 && r == 0.0));
+
+        source.play();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(source.sample_buffer().iter().any(|(l, r)| l != 0.0 && r != 0.0));
+
+        assert!(source.is_running());
+        source.stop();
+        assert!(!source.is_running());
+    }
+
+    #[test]
+    fn test_real_time() {
+        // A tenth of a second of audio should take about a tenth of a second to push.
+        let frames = vec![(0.5, -0.5); SAMPLE_RATE / 10];
+        let mut source = StreamSource::new(frames, SAMPLE_RATE, 16, 441, Pacing::RealTime).unwrap();
+
+        let start = Instant::now();
+        source.play();
+        source.wait();
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= Duration::from_millis(90));
+        assert!(source.sample_buffer().iter().all(|(l, r)| l == 0.5 && r == -0.5));
+    }
+
+    #[test]
+    fn test_raw_pcm() {
+        let samples: Vec<i16> = vec![0, 16384, -16384, 32767, -32768, 8192];
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes().to_vec()).collect();
+
+        let mut source = StreamSource::raw_pcm(Cursor::new(bytes.clone()), PcmFormat::I16, 2, SAMPLE_RATE, 3, 2, Pacing::Unpaced).unwrap();
+        source.play();
+        source.wait();
+
+        let produced: Vec<_> = source.sample_buffer().iter().collect();
+        assert_eq!(vec![(0.0, 0.5), (-0.5, 32767.0 / 32768.0), (-1.0, 0.25)], produced);
+
+        let mut source = StreamSource::raw_pcm(Cursor::new(bytes), PcmFormat::I16, 1, SAMPLE_RATE, 2, 2, Pacing::Unpaced).unwrap();
+        source.play();
+        source.wait();
+
+        let produced: Vec<_> = source.sample_buffer().iter().collect();
+        assert_eq!(vec![(-1.0, -1.0), (0.25, 0.25)], produced);
+
+        let floats: Vec<u8> = [0.25f32, -0.75].iter().flat_map(|s| s.to_le_bytes().to_vec()).collect();
+        let mut source = StreamSource::raw_pcm(Cursor::new(floats), PcmFormat::F32, 2, SAMPLE_RATE, 1, 1, Pacing::Unpaced).unwrap();
+        source.play();
+        source.wait();
+
+        let produced: Vec<_> = source.sample_buffer().iter().collect();
+        assert_eq!(vec![(0.25, -0.75)], produced);
+
+        assert_eq!(
+            Some(Error::NumChannels(0)),
+            StreamSource::raw_pcm(Cursor::new(vec![]), PcmFormat::I16, 0, SAMPLE_RATE, 1, 1, Pacing::Unpaced).err(),
+        );
+    }
+
+    #[test]
+    fn test_blocked_reader() {
+        // Stands in for standard input with nothing more to read until its sender goes away.
+        struct BlockedReader(Receiver<()>);
+
+        impl Read for BlockedReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                let _ = self.0.recv();
+                Ok(0)
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let mut source = StreamSource::raw_pcm(BlockedReader(receiver), PcmFormat::I16, 2, SAMPLE_RATE, 16, 4, Pacing::RealTime).unwrap();
+
+        source.play();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(source.is_running());
+
+        // Stopping doesn't wait for the read to return.
+        let start = Instant::now();
+        source.stop();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!source.is_running());
+
+        drop(sender);
+    }
+
+    #[test]
+    fn test_file() {
+        let mut source = StreamSource::file("wav/sin_440hz_44100hz_samp.wav", FFT_LEN, 1024, Pacing::Unpaced).unwrap();
+        assert_eq!(SAMPLE_RATE, source.sample_rate());
+
+        source.play();
+        source.wait();
+
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let expected = (440.0 / (SAMPLE_RATE as f32 / FFT_LEN as f32)).round() as usize;
+
+        assert_eq!(expected, peak_bin(&source, &mut analyzer));
+
+        assert_eq!(
+            Some(Error::Io(std::io::ErrorKind::NotFound)),
+            StreamSource::file("wav/missing.wav", FFT_LEN, 1024, Pacing::Unpaced).err(),
+        );
+    }
+}
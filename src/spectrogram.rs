@@ -0,0 +1,499 @@
+//! Renders the frames of an offline analysis as a spectrogram image, and saves it as a PNG.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use png::ColorType;
+use png::BitDepth;
+use png::Encoder;
+use png::EncodingError;
+
+use crate::Error;
+use crate::audio_file::AudioFile;
+use crate::buckets::Buckets;
+use crate::offline::Frame;
+use crate::offline::OfflineAnalyzer;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+
+// Colour of the beat markers.
+const MARKER_COLOR: [u8; 3] = [255, 255, 255];
+
+// Evenly spaced stops of the viridis and magma colormaps, from matplotlib.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 45, 123],
+    [59, 82, 139],
+    [44, 114, 142],
+    [33, 145, 140],
+    [40, 174, 128],
+    [94, 201, 98],
+    [173, 220, 48],
+    [253, 231, 37],
+];
+
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+impl From<EncodingError> for Error {
+    fn from(e: EncodingError) -> Self {
+        match e {
+            EncodingError::IoError(e) => Error::Io(e.kind()),
+            _ => Error::Encode,
+        }
+    }
+}
+
+/// How frequencies are laid out along the vertical axis.
+#[derive(Clone)]
+pub enum FrequencyScale {
+    /// Evenly spaced from zero up to the Nyquist frequency.
+    Linear,
+
+    /// Logarithmically spaced from the given frequency up to the Nyquist frequency.
+    Log(Frequency),
+
+    /// One band per bucket, each drawn with the same height.
+    Buckets(Buckets),
+}
+
+/// Maps a value between zero and one to a colour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Grayscale,
+}
+
+impl Colormap {
+    pub fn color(&self, value: SignalStrength) -> [u8; 3] {
+        let value = value.clamp(0.0, 1.0);
+
+        let stops = match self {
+            &Colormap::Viridis => &VIRIDIS,
+            &Colormap::Magma => &MAGMA,
+            &Colormap::Grayscale => {
+                let v = (value * 255.0).round() as u8;
+                return [v, v, v];
+            },
+        };
+
+        // Interpolate linearly between the two nearest stops.
+        let position = value * (stops.len() - 1) as SignalStrength;
+        let i = (position.floor() as usize).min(stops.len() - 2);
+        let t = position - i as SignalStrength;
+
+        let mut color = [0; 3];
+        for c in 0..3 {
+            let (a, b) = (stops[i][c] as SignalStrength, stops[i + 1][c] as SignalStrength);
+            color[c] = (a + (b - a) * t).round() as u8;
+        }
+        color
+    }
+}
+
+/// An 8-bit RGB image, stored row by row from the top left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * self.width + x) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        let i = (y * self.width + x) * 3;
+        self.pixels[i..i + 3].copy_from_slice(&color);
+    }
+
+    /// Encodes the image as a PNG.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut encoder = Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(ColorType::Rgb);
+        encoder.set_depth(BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Saves the image to a PNG file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        self.write_png(BufWriter::new(file))
+    }
+}
+
+/// Draws one column per analyzed frame, with low frequencies at the bottom.
+/// Levels are given relative to a full scale sine wave, and coloured on a decibel scale.
+#[derive(Clone)]
+pub struct Spectrogram {
+    height: usize,
+    scale: FrequencyScale,
+    colormap: Colormap,
+    min_db: SignalStrength,
+    max_db: SignalStrength,
+    beat_markers: bool,
+}
+
+impl Spectrogram {
+    /// Creates a spectrogram `height` pixels tall, with a linear axis, the viridis colormap,
+    /// a range of -100 to 0 dB, and no beat markers.
+    pub fn new(height: usize) -> Result<Self, Error> {
+        if !(height > 0) { Err(Error::GraphHeight)? }
+
+        Ok(Self {
+            height,
+            scale: FrequencyScale::Linear,
+            colormap: Colormap::Viridis,
+            min_db: -100.0,
+            max_db: 0.0,
+            beat_markers: false,
+        })
+    }
+
+    /// Sets the frequency axis. A logarithmic axis has to start above zero, and bands need at least one band.
+    pub fn with_scale(mut self, scale: FrequencyScale) -> Result<Self, Error> {
+        match scale {
+            FrequencyScale::Log(lower) if !(lower > 0.0) => Err(Error::LowerCutoff)?,
+            FrequencyScale::Buckets(ref buckets) if !(buckets.len() > 0) => Err(Error::NumBands)?,
+            _ => {},
+        }
+
+        self.scale = scale;
+        Ok(self)
+    }
+
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+
+    /// Sets the decibel levels mapped to the two ends of the colormap.
+    pub fn with_range(mut self, min_db: SignalStrength, max_db: SignalStrength) -> Result<Self, Error> {
+        if !(min_db < max_db) { Err(Error::DbRange)? }
+
+        self.min_db = min_db;
+        self.max_db = max_db;
+        Ok(self)
+    }
+
+    /// Marks the top of each frame that the beat detector flagged.
+    /// Frames analyzed without a beat detector are never marked.
+    pub fn with_beat_markers(mut self, beat_markers: bool) -> Self {
+        self.beat_markers = beat_markers;
+        self
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Converts a power to a position along the colormap.
+    fn to_fraction(&self, power: SignalStrength, full_scale: SignalStrength) -> SignalStrength {
+        let db = 10.0 * (power / full_scale).max(1e-20).log10();
+        (db - self.min_db) / (self.max_db - self.min_db)
+    }
+
+    // The frequency range covered by a row, counting up from the bottom.
+    fn row_range(&self, row: usize, nyquist: Frequency) -> (Frequency, Frequency) {
+        let lo = row as Frequency / self.height as Frequency;
+        let hi = (row + 1) as Frequency / self.height as Frequency;
+
+        match &self.scale {
+            &FrequencyScale::Log(lower) => {
+                let ratio = nyquist / lower;
+                (lower * ratio.powf(lo), lower * ratio.powf(hi))
+            },
+            _ => (nyquist * lo, nyquist * hi),
+        }
+    }
+
+    // The loudest bin within a frequency range, or the nearest bin if the range falls between bins.
+    fn level(spectrum: &[SignalStrength], fft_bin_size: Frequency, (lo, hi): (Frequency, Frequency)) -> SignalStrength {
+        let last_bin = spectrum.len() / 2;
+        let first = ((lo / fft_bin_size).ceil() as usize).min(last_bin);
+        let last = ((hi / fft_bin_size).floor() as usize).min(last_bin);
+
+        if first <= last {
+            spectrum[first..=last].iter().cloned().fold(0.0, SignalStrength::max)
+        }
+        else {
+            let nearest = (((lo + hi) / 2.0 / fft_bin_size).round() as usize).min(last_bin);
+            spectrum[nearest]
+        }
+    }
+
+    // Renders a single frame, from the top of the image down.
+    fn column(&self, frame: &Frame, sampling_rate: usize) -> Result<Vec<[u8; 3]>, Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let (spectrum_l, spectrum_r) = &frame.spectrum;
+        let spectrum: Vec<_> = spectrum_l.iter().zip(spectrum_r).map(|(l, r)| (l + r) / 2.0).collect();
+
+        // A full scale sine wave in a single bin has this power.
+        let full_scale = (spectrum.len() as SignalStrength / 2.0).powi(2);
+        let fft_bin_size = sampling_rate as Frequency / spectrum.len() as Frequency;
+        let nyquist = sampling_rate as Frequency / 2.0;
+
+        // A logarithmic axis runs up to the Nyquist frequency, so it has to start below it.
+        if let &FrequencyScale::Log(lower) = &self.scale {
+            if !(lower < nyquist) { Err(Error::CutoffOrder)? }
+        }
+
+        let band_levels = match &self.scale {
+            FrequencyScale::Buckets(buckets) => Some(buckets.bucketize(&spectrum, sampling_rate)?),
+            _ => None,
+        };
+
+        let mut column: Vec<_> = (0..self.height).rev().map(|row| {
+            let power = match &band_levels {
+                Some(band_levels) => band_levels[row * band_levels.len() / self.height],
+                None => Self::level(&spectrum, fft_bin_size, self.row_range(row, nyquist)),
+            };
+
+            self.colormap.color(self.to_fraction(power, full_scale))
+        }).collect();
+
+        if self.beat_markers && frame.is_beat == Some(true) {
+            for pixel in column.iter_mut().take((self.height / 8).max(1)) {
+                *pixel = MARKER_COLOR;
+            }
+        }
+
+        Ok(column)
+    }
+
+    fn assemble(&self, columns: &[Vec<[u8; 3]>]) -> Image {
+        let mut image = Image::new(columns.len(), self.height);
+
+        for (x, column) in columns.iter().enumerate() {
+            for (y, color) in column.iter().enumerate() {
+                image.set_pixel(x, y, *color);
+            }
+        }
+
+        image
+    }
+
+    /// Renders a sequence of analyzed frames, one column per frame.
+    pub fn render(&self, frames: &[Frame], sampling_rate: usize) -> Result<Image, Error> {
+        let columns = frames.iter().map(|f| self.column(f, sampling_rate)).collect::<Result<Vec<_>, _>>()?;
+        Ok(self.assemble(&columns))
+    }
+
+    /// Analyzes an entire audio file and renders it.
+    /// Only the rendered columns are kept in memory, rather than every spectrum.
+    /// Beat markers are taken from the detector of the analyzer, if it has one.
+    pub fn render_file<P: AsRef<Path>>(&self, offline: &mut OfflineAnalyzer, path: P) -> Result<Image, Error> {
+        let mut audio_file = AudioFile::open(path)?;
+        let sampling_rate = audio_file.sample_rate();
+
+        let mut columns = Vec::new();
+        for frame in offline.stream(&mut audio_file, sampling_rate) {
+            columns.push(self.column(&frame?, sampling_rate)?);
+        }
+
+        if let Some(e) = audio_file.error() { Err(e)? }
+
+        Ok(self.assemble(&columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use png::Decoder;
+
+    use crate::beat::Detector;
+    use crate::window_kind::WindowKind;
+
+    const FFT_LEN: usize = 1024;
+    const HOP: usize = 1024;
+    const HEIGHT: usize = 128;
+
+    // Set this environment variable to rewrite the golden images instead of checking against them.
+    const BLESS_VAR: &str = "VOXANE_BLESS";
+
+    fn read_png(path: &Path) -> Image {
+        let decoder = Decoder::new(File::open(path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(ColorType::Rgb, info.color_type);
+
+        pixels.truncate(info.buffer_size());
+        Image { width: info.width as usize, height: info.height as usize, pixels }
+    }
+
+    #[test]
+    fn test_golden_images() {
+        let inputs_and_expected = vec![
+            (
+                "wav/sin_440hz_44100hz_samp.wav",
+                Spectrogram::new(HEIGHT).unwrap(),
+                "wav/golden/sin_440hz_44100hz_linear_viridis.png",
+            ),
+            (
+                "wav/sin_1234hz_32000hz_samp.wav",
+                Spectrogram::new(HEIGHT).unwrap()
+                    .with_scale(FrequencyScale::Log(50.0)).unwrap()
+                    .with_colormap(Colormap::Magma)
+                    .with_range(-80.0, 0.0).unwrap(),
+                "wav/golden/sin_1234hz_32000hz_log_magma.png",
+            ),
+            (
+                "wav/sin_7999hz_16000hz_samp.wav",
+                Spectrogram::new(HEIGHT).unwrap()
+                    .with_scale(FrequencyScale::Buckets(Buckets::new(100.0, 8000.0, 16).unwrap())).unwrap()
+                    .with_colormap(Colormap::Grayscale)
+                    .with_range(-60.0, -10.0).unwrap(),
+                "wav/golden/sin_7999hz_16000hz_buckets_grayscale.png",
+            ),
+        ];
+
+        for (path, spectrogram, golden) in inputs_and_expected {
+            let mut offline = OfflineAnalyzer::new(FFT_LEN, HOP, WindowKind::Hanning).unwrap();
+            let produced = spectrogram.render_file(&mut offline, path).unwrap();
+
+            let golden = PathBuf::from(golden);
+            if std::env::var_os(BLESS_VAR).is_some() { produced.save(&golden).unwrap() }
+
+            let expected = read_png(&golden);
+            assert_eq!(expected.width(), produced.width());
+            assert_eq!(expected.height(), produced.height());
+
+            // Allow for slight differences in floating point rounding between platforms.
+            for (e, p) in expected.pixels().iter().zip(produced.pixels()) {
+                assert!((*e as i32 - *p as i32).abs() <= 2, "{}: {} vs {}", golden.display(), e, p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_beat_markers() {
+        const SAMPLING_RATE: usize = 44100;
+
+        // Bursts of a low tone every half second.
+        let samples: Vec<_> = (0..SAMPLING_RATE * 2).map(|i| {
+            let t = (i % (SAMPLING_RATE / 2)) as f32 / SAMPLING_RATE as f32;
+            let s = if t < 0.1 { (2.0 * std::f32::consts::PI * 60.0 * t).sin() * 0.5 } else { 0.0 };
+            (s, s)
+        }).collect();
+
+        let detector = Detector::new(10, 3, 2, 20, 200, FFT_LEN).unwrap();
+        let mut offline = OfflineAnalyzer::new(FFT_LEN, FFT_LEN / 2, WindowKind::Hanning).unwrap().with_detector(detector);
+        let frames = offline.stream(samples, SAMPLING_RATE).collect::<Result<Vec<_>, _>>().unwrap();
+
+        let spectrogram = Spectrogram::new(HEIGHT).unwrap().with_beat_markers(true);
+        let image = spectrogram.render(&frames, SAMPLING_RATE).unwrap();
+        assert_eq!(frames.len(), image.width());
+
+        for (x, frame) in frames.iter().enumerate() {
+            let is_marked = image.pixel(x, 0) == MARKER_COLOR && image.pixel(x, HEIGHT / 8 - 1) == MARKER_COLOR;
+            assert_eq!(frame.is_beat == Some(true), is_marked);
+            assert_ne!(MARKER_COLOR, image.pixel(x, HEIGHT / 8));
+        }
+
+        assert!(frames.iter().any(|f| f.is_beat == Some(true)));
+
+        // Without markers enabled, the same frames are left untouched.
+        let image = Spectrogram::new(HEIGHT).unwrap().render(&frames, SAMPLING_RATE).unwrap();
+        assert!((0..image.width()).all(|x| image.pixel(x, 0) != MARKER_COLOR));
+    }
+
+    #[test]
+    fn test_colormap() {
+        let inputs_and_expected = vec![
+            ((Colormap::Viridis, 0.0), [68, 1, 84]),
+            ((Colormap::Viridis, 0.5), [33, 145, 140]),
+            ((Colormap::Viridis, 1.0), [253, 231, 37]),
+            ((Colormap::Viridis, 1.5), [253, 231, 37]),
+            ((Colormap::Magma, 0.0), [0, 0, 4]),
+            ((Colormap::Magma, 0.0625), [14, 8, 36]),
+            ((Colormap::Magma, 1.0), [252, 253, 191]),
+            ((Colormap::Grayscale, -1.0), [0, 0, 0]),
+            ((Colormap::Grayscale, 0.5), [128, 128, 128]),
+            ((Colormap::Grayscale, 1.0), [255, 255, 255]),
+        ];
+
+        for ((colormap, value), expected) in inputs_and_expected {
+            let produced = colormap.color(value);
+            assert_eq!(expected, produced);
+        }
+    }
+
+    #[test]
+    fn test_write_png() {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(2, 1, [1, 2, 3]);
+
+        let mut bytes = Vec::new();
+        image.write_png(&mut bytes).unwrap();
+        assert_eq!(&[0x89, b'P', b'N', b'G'], &bytes[..4]);
+
+        let path = std::env::temp_dir().join(format!("voxane_{}_write_png.png", std::process::id()));
+        image.save(&path).unwrap();
+        assert_eq!(image, read_png(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(Some(Error::GraphHeight), Spectrogram::new(0).err());
+        assert_eq!(Some(Error::DbRange), Spectrogram::new(1).unwrap().with_range(-10.0, -20.0).err());
+        assert_eq!(Some(Error::LowerCutoff), Spectrogram::new(1).unwrap().with_scale(FrequencyScale::Log(0.0)).err());
+
+        // Bands with nothing in them have no rows to fill.
+        let buckets = Buckets::new(20.0, 20000.0, 0).unwrap();
+        assert_eq!(Some(Error::NumBands), Spectrogram::new(1).unwrap().with_scale(FrequencyScale::Buckets(buckets)).err());
+
+        // A logarithmic axis starting at or above the Nyquist frequency can't be rendered.
+        let spectrogram = Spectrogram::new(1).unwrap().with_scale(FrequencyScale::Log(4000.0)).unwrap();
+        let frames = vec![Frame { index: 0, offset: 0, timestamp: 0.0, spectrum: (vec![0.0; 8], vec![0.0; 8]), bands: None, is_beat: None }];
+        assert_eq!(Some(Error::CutoffOrder), spectrogram.render(&frames, 8000).err());
+        assert!(spectrogram.render(&frames, 16000).is_ok());
+    }
+}
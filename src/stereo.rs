@@ -0,0 +1,377 @@
+//! Measures the stereo image: how correlated the channels are, how they are balanced,
+//! and how much of the signal is in the sides rather than the middle.
+
+use rustfft::num_complex::Complex;
+
+use crate::Error;
+use crate::buckets::Buckets;
+use crate::sample::Sample;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+use crate::util::FrameMeter;
+
+// Mean square levels below this, -60 dBFS, count as silence, which is never reported as phase inverted.
+const SILENCE: f64 = 1e-6;
+
+// The energy in each channel and between them, over some stretch of samples.
+#[derive(Clone, Copy, Debug, Default)]
+struct Energies {
+    left: f64,
+    right: f64,
+    cross: f64,
+    mid: f64,
+    side: f64,
+}
+
+impl Energies {
+    fn add(&mut self, l: Sample, r: Sample, weight: f64) {
+        let (l, r) = (l as f64, r as f64);
+        let (m, s) = ((l + r) / 2.0, (l - r) / 2.0);
+
+        self.left += weight * l * l;
+        self.right += weight * r * r;
+        self.cross += weight * l * r;
+        self.mid += weight * m * m;
+        self.side += weight * s * s;
+    }
+
+    fn scale(&mut self, factor: f64) {
+        self.left *= factor;
+        self.right *= factor;
+        self.cross *= factor;
+        self.mid *= factor;
+        self.side *= factor;
+    }
+
+    fn image(&self) -> StereoImage {
+        let total = self.left + self.right;
+
+        let correlation = match self.left * self.right {
+            p if p > 0.0 => (self.cross / p.sqrt()).clamp(-1.0, 1.0),
+            _ => 0.0,
+        };
+        let balance = if total > 0.0 { (self.right - self.left) / total } else { 0.0 };
+        let mid_side_ratio = match (self.mid > 0.0, self.side > 0.0) {
+            (true, true) => 10.0 * (self.mid / self.side).log10(),
+            (true, false) => f64::INFINITY,
+            (false, true) => f64::NEG_INFINITY,
+            (false, false) => 0.0,
+        };
+
+        StereoImage {
+            correlation: correlation as SignalStrength,
+            balance: balance as SignalStrength,
+            mid_side_ratio: mid_side_ratio as SignalStrength,
+        }
+    }
+}
+
+/// A measurement of the stereo image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StereoImage {
+    /// The phase correlation between the channels, from -1 for one channel inverting the other,
+    /// through 0 for unrelated channels, to 1 for mono. It is 0 when either channel is silent.
+    pub correlation: SignalStrength,
+
+    /// The balance of energy between the channels, from -1 for only the left, to 1 for only the right.
+    pub balance: SignalStrength,
+
+    /// The energy of the mid signal over the energy of the side signal, in decibels.
+    /// It is infinite for mono, and negative infinity when the channels cancel out completely.
+    pub mid_side_ratio: SignalStrength,
+}
+
+impl StereoImage {
+    /// Measures the stereo image of some samples, such as those of a `SampleBuffer`.
+    pub fn measure<I>(samples: I) -> Self
+    where
+        I: Iterator<Item = (Sample, Sample)>,
+    {
+        let mut energies = Energies::default();
+        for (l, r) in samples { energies.add(l, r, 1.0) }
+        energies.image()
+    }
+}
+
+/// Measures the stereo image of a stream, both for each slice of samples and smoothed over time,
+/// and detects material that would cancel out when mixed down to mono.
+#[derive(Clone, Debug)]
+pub struct StereoMeter {
+    sampling_rate: usize,
+    time_constant: f32,
+    threshold: SignalStrength,
+
+    // How much of the smoothed energies is kept with each sample.
+    retention: f64,
+
+    instantaneous: Energies,
+    smoothed: Energies,
+}
+
+impl StereoMeter {
+    /// Creates a meter that smooths over 300 ms, and that reports material as phase inverted
+    /// when its smoothed correlation is below -0.5.
+    pub fn new(sampling_rate: usize) -> Result<Self, Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let time_constant = 0.3;
+
+        Ok(Self {
+            sampling_rate,
+            time_constant,
+            threshold: -0.5,
+            retention: retention(time_constant, sampling_rate),
+            instantaneous: Energies::default(),
+            smoothed: Energies::default(),
+        })
+    }
+
+    /// Sets the time constant of the smoothing, in seconds.
+    pub fn with_time_constant(mut self, time_constant: f32) -> Result<Self, Error> {
+        if !(time_constant > 0.0) { Err(Error::TimeConstant)? }
+
+        self.time_constant = time_constant;
+        self.retention = retention(time_constant, self.sampling_rate);
+        Ok(self)
+    }
+
+    /// Sets the correlation below which material is reported as phase inverted.
+    pub fn with_threshold(mut self, threshold: SignalStrength) -> Result<Self, Error> {
+        if !(-1.0..=0.0).contains(&threshold) { Err(Error::CorrelationThreshold)? }
+
+        self.threshold = threshold;
+        Ok(self)
+    }
+
+    #[inline]
+    pub fn time_constant(&self) -> f32 {
+        self.time_constant
+    }
+
+    /// Measures a slice of stereo samples, continuing on from the previous ones.
+    pub fn push(&mut self, sample_pairs: &[(Sample, Sample)]) {
+        self.push_pairs(sample_pairs);
+    }
+
+    /// Measures a slice of interleaved stereo samples, as delivered by an audio device.
+    pub fn push_interleaved(&mut self, samples: &[Sample]) {
+        self.push_interleaved_samples(samples);
+    }
+
+    /// The stereo image of the last slice.
+    pub fn instantaneous(&self) -> StereoImage {
+        self.instantaneous.image()
+    }
+
+    /// The stereo image smoothed over the time constant.
+    pub fn smoothed(&self) -> StereoImage {
+        self.smoothed.image()
+    }
+
+    /// Whether the smoothed correlation is below the threshold, and the signal is loud enough to matter.
+    /// Such material loses much of its level, or vanishes entirely, when the channels are summed to mono.
+    pub fn is_phase_inverted(&self) -> bool {
+        let level = (self.smoothed.left + self.smoothed.right) / 2.0;
+        level > SILENCE && self.smoothed().correlation < self.threshold
+    }
+
+    /// Forgets everything measured so far.
+    pub fn reset(&mut self) {
+        self.instantaneous = Energies::default();
+        self.smoothed = Energies::default();
+    }
+}
+
+impl FrameMeter for StereoMeter {
+    fn start_slice(&mut self) {
+        self.instantaneous = Energies::default();
+    }
+
+    fn push_frame(&mut self, l: Sample, r: Sample) {
+        self.instantaneous.add(l, r, 1.0);
+
+        self.smoothed.scale(self.retention);
+        self.smoothed.add(l, r, 1.0 - self.retention);
+    }
+}
+
+fn retention(time_constant: f32, sampling_rate: usize) -> f64 {
+    (-1.0 / (time_constant as f64 * sampling_rate as f64)).exp()
+}
+
+/// Measures how wide the stereo image is in each band, from the left and right complex spectra
+/// returned by `Analyzer::complex_spectra`.
+/// The width is the fraction of each band's energy in the side signal: 0 for mono, 0.5 for unrelated channels
+/// or a signal in only one channel, and 1 when one channel inverts the other. Empty bands have a width of 0.
+pub fn band_widths(
+    spectrum_l: &[Complex<Sample>],
+    spectrum_r: &[Complex<Sample>],
+    buckets: &Buckets,
+    sampling_rate: usize,
+    ) -> Result<Vec<SignalStrength>, Error>
+{
+    if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+    if spectrum_l.len() != spectrum_r.len() { Err(Error::InputBuffer(spectrum_l.len(), spectrum_r.len()))? }
+
+    let mut mid = vec![0.0f64; buckets.len()];
+    let mut side = vec![0.0f64; buckets.len()];
+
+    // As in `Buckets::bucketize`, only the bins up to the Nyquist frequency are used, and the zero frequency is skipped.
+    let n = spectrum_l.len();
+    let fft_bin_size = sampling_rate as Frequency / n as Frequency;
+
+    for i in 1..=(n / 2) {
+        if let Some(band_index) = buckets.locate(fft_bin_size * i as Frequency) {
+            let (l, r) = (spectrum_l[i], spectrum_r[i]);
+            mid[band_index] += ((l + r) * 0.5).norm_sqr() as f64;
+            side[band_index] += ((l - r) * 0.5).norm_sqr() as f64;
+        }
+    }
+
+    let widths =
+        mid
+        .into_iter()
+        .zip(side)
+        .map(|(m, s)| if m + s > 0.0 { (s / (m + s)) as SignalStrength } else { 0.0 })
+        .collect()
+    ;
+
+    Ok(widths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analyzer::Analyzer;
+    use crate::sample::SampleBuffer;
+    use crate::wave::Channel;
+    use crate::wave::MultiTone;
+    use crate::wave::NoiseColor;
+    use crate::wave::NoiseGen;
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+    use crate::window_kind::WindowKind;
+
+    const SAMPLE_RATE: usize = 48000;
+    const LEN: usize = 4800;
+
+    fn sine() -> WaveGen {
+        WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1000.0)
+    }
+
+    fn noise(seed: u64) -> NoiseGen {
+        NoiseGen::new(NoiseColor::White, seed)
+    }
+
+    #[test]
+    fn test_measure() {
+        // Each signal, with its expected correlation, balance and mid/side ratio.
+        let inputs_and_expected = vec![
+            (StereoGen::mono(sine()), (1.0, 0.0, f32::INFINITY)),
+            (StereoGen::mono(sine()).with_inversion(Channel::Right), (-1.0, 0.0, f32::NEG_INFINITY)),
+            (StereoGen::stereo(noise(1), noise(2)), (0.0, 0.0, 0.0)),
+            (StereoGen::correlated(noise(1), noise(2), noise(3), 0.5), (0.5, 0.0, 4.77)),
+            (StereoGen::mono(sine()).with_pan(-1.0), (0.0, -1.0, 0.0)),
+            (StereoGen::mono(sine()).with_gain(Channel::Left, 0.5), (1.0, 0.6, 9.54)),
+        ];
+
+        for (input, (correlation, balance, mid_side_ratio)) in inputs_and_expected {
+            let produced = StereoImage::measure(input.take(LEN));
+            println!("{:?}", produced);
+
+            assert_approx_eq!(correlation, produced.correlation, 0.05);
+            assert_approx_eq!(balance, produced.balance, 0.05);
+            if mid_side_ratio.is_finite() { assert_approx_eq!(mid_side_ratio, produced.mid_side_ratio, 0.5) }
+            else { assert_eq!(mid_side_ratio, produced.mid_side_ratio) }
+        }
+
+        // Silence is neutral.
+        let silence = StereoImage { correlation: 0.0, balance: 0.0, mid_side_ratio: 0.0 };
+        assert_eq!(silence, StereoImage::measure(std::iter::repeat((0.0, 0.0)).take(LEN)));
+
+        // The samples can come straight from a buffer.
+        let buffer = SampleBuffer::from(StereoGen::mono(sine()).take(LEN).collect::<Vec<_>>());
+        assert_approx_eq!(1.0, StereoImage::measure(buffer.iter()).correlation);
+    }
+
+    #[test]
+    fn test_instantaneous_and_smoothed() {
+        let mut meter = StereoMeter::new(SAMPLE_RATE).unwrap();
+
+        let in_phase: Vec<(Sample, Sample)> = StereoGen::mono(sine()).take(SAMPLE_RATE).collect();
+        let inverted: Vec<(Sample, Sample)> = StereoGen::mono(sine()).with_inversion(Channel::Left).take(SAMPLE_RATE / 20).collect();
+
+        meter.push(&in_phase);
+        assert_approx_eq!(1.0, meter.smoothed().correlation, 1e-3);
+        assert!(!meter.is_phase_inverted());
+
+        // A brief inversion shows up straight away in the instantaneous image, but only slowly in the smoothed one.
+        meter.push(&inverted);
+        assert_approx_eq!(-1.0, meter.instantaneous().correlation, 1e-3);
+        assert!(meter.smoothed().correlation > 0.5);
+        assert!(!meter.is_phase_inverted());
+
+        // Once it lasts, the smoothed correlation follows, and the material is reported as phase inverted.
+        for _ in 0..40 { meter.push(&inverted) }
+        assert_approx_eq!(-1.0, meter.smoothed().correlation, 0.05);
+        assert!(meter.is_phase_inverted());
+
+        // Interleaved samples are measured the same way.
+        let interleaved: Vec<Sample> = inverted.iter().flat_map(|&(l, r)| vec![l, r]).collect();
+        meter.push_interleaved(&interleaved);
+        assert_eq!(StereoImage::measure(inverted.iter().cloned()), meter.instantaneous());
+
+        meter.reset();
+        assert_eq!(0.0, meter.smoothed().correlation);
+        assert!(!meter.is_phase_inverted());
+    }
+
+    #[test]
+    fn test_quiet_inversion() {
+        // Inverted material below -60 dBFS is ignored.
+        let mut meter = StereoMeter::new(SAMPLE_RATE).unwrap();
+        let quiet = sine().with_amplitude(0.0001);
+        meter.push(&StereoGen::mono(quiet).with_inversion(Channel::Left).take(SAMPLE_RATE).collect::<Vec<_>>());
+
+        assert!(meter.smoothed().correlation < -0.99);
+        assert!(!meter.is_phase_inverted());
+    }
+
+    #[test]
+    fn test_band_widths() {
+        const FFT_LEN: usize = 4096;
+
+        // A mono tone at 200 Hz, an inverted tone at 2 kHz, and a tone in only the left channel at 8 kHz.
+        let tone = |frequency: Frequency| WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, frequency);
+        let left = MultiTone::new(SAMPLE_RATE, &[]).with_tone(tone(200.0)).with_tone(tone(2000.0)).with_tone(tone(8000.0));
+        let right = MultiTone::new(SAMPLE_RATE, &[]).with_tone(tone(200.0)).with_tone(tone(2000.0).with_amplitude(-0.25));
+        let samples = SampleBuffer::from(StereoGen::stereo(left, right).take(FFT_LEN).collect::<Vec<_>>());
+
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        analyzer.analyze(&samples).unwrap();
+        let (spectrum_l, spectrum_r) = analyzer.complex_spectra();
+
+        let buckets = Buckets::new(100.0, 16000.0, 3).unwrap();
+        let produced = band_widths(spectrum_l, spectrum_r, &buckets, SAMPLE_RATE).unwrap();
+        println!("{:?}", produced);
+
+        let expected = [0.0, 1.0, 0.5];
+        for (e, p) in expected.iter().zip(&produced) { assert_approx_eq!(e, p, 0.01) }
+
+        let short = vec![Complex::new(0.0, 0.0); FFT_LEN / 2];
+        assert_eq!(Some(Error::InputBuffer(FFT_LEN / 2, FFT_LEN)), band_widths(&short, spectrum_r, &buckets, SAMPLE_RATE).err());
+        assert_eq!(Some(Error::SamplingRate(0)), band_widths(spectrum_l, spectrum_r, &buckets, 0).err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(Error::SamplingRate(0)), StereoMeter::new(0).err());
+
+        let meter = StereoMeter::new(SAMPLE_RATE).unwrap();
+        assert_eq!(Some(Error::TimeConstant), meter.clone().with_time_constant(0.0).err());
+        assert_eq!(Some(Error::CorrelationThreshold), meter.clone().with_threshold(-1.5).err());
+        assert_eq!(Some(Error::CorrelationThreshold), meter.clone().with_threshold(0.5).err());
+    }
+}
@@ -0,0 +1,55 @@
+//! Helpers shared by the spectral, pitch and metering modules.
+
+use crate::sample::Sample;
+
+// Floor applied to powers before taking logarithms, so that silence doesn't produce infinities.
+pub(crate) const MIN_POWER: f64 = 1e-20;
+
+// Fits a parabola through three points around a peak, returning the offset of its vertex from the middle point and its height.
+pub(crate) fn interpolate_peak(a: f64, b: f64, c: f64) -> (f64, f64) {
+    let denominator = a - 2.0 * b + c;
+    if !(denominator < 0.0) { return (0.0, b) }
+
+    let offset = 0.5 * (a - c) / denominator;
+    (offset, b - 0.25 * (a - c) * offset)
+}
+
+// A stereo meter that measures one frame at a time, and takes slices of frames either as pairs or interleaved.
+pub(crate) trait FrameMeter {
+    // Called before each slice, for meters that report on the last slice alone.
+    fn start_slice(&mut self) {}
+
+    fn push_frame(&mut self, l: Sample, r: Sample);
+
+    fn push_pairs(&mut self, sample_pairs: &[(Sample, Sample)]) {
+        self.start_slice();
+        for &(l, r) in sample_pairs { self.push_frame(l, r) }
+    }
+
+    fn push_interleaved_samples(&mut self, samples: &[Sample]) {
+        self.start_slice();
+        for chunk in samples.chunks_exact(2) { self.push_frame(chunk[0], chunk[1]) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate_peak() {
+        // Points on y = 1 - (x - 0.25)^2 at x = -1, 0 and 1, and points with no peak between them.
+        let inputs_and_expected = vec![
+            ((1.0 - 1.5625, 1.0 - 0.0625, 1.0 - 0.5625), (0.25, 1.0)),
+            ((0.5, 1.0, 0.5), (0.0, 1.0)),
+            ((1.0, 1.0, 1.0), (0.0, 1.0)),
+            ((0.0, 1.0, 2.0), (0.0, 1.0)),
+        ];
+
+        for ((a, b, c), (offset, height)) in inputs_and_expected {
+            let produced = interpolate_peak(a, b, c);
+            assert_approx_eq!(offset, produced.0, 1e-12);
+            assert_approx_eq!(height, produced.1, 1e-12);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Renders band levels as a bar graph of Unicode block characters, for display in a terminal.
+
+use crate::Error;
+use crate::types::SignalStrength;
+
+// Partial blocks in eighths of a cell, from empty to full.
+const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// Drawn at the top of the cell holding a band's peak.
+const PEAK_MARKER: char = '▔';
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// Colour of a row, based on how far up the graph it is.
+fn row_color(row: usize, height: usize) -> &'static str {
+    let fraction = (row + 1) as SignalStrength / height as SignalStrength;
+
+    if fraction > 0.85 { RED }
+    else if fraction > 0.6 { YELLOW }
+    else { GREEN }
+}
+
+// Converts a power to a fraction of the graph height.
+fn to_fraction(power: SignalStrength, min_db: SignalStrength, max_db: SignalStrength) -> SignalStrength {
+    let db = 10.0 * power.max(1e-20).log10();
+    ((db - min_db) / (max_db - min_db)).clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Peak {
+    level: SignalStrength,
+    age: usize,
+}
+
+/// A bar graph of band levels, one bar per band.
+/// Levels are given as powers relative to full scale, and drawn on a decibel scale.
+#[derive(Clone, Debug)]
+pub struct BarGraph {
+    height: usize,
+    bar_width: usize,
+    min_db: SignalStrength,
+    max_db: SignalStrength,
+    color: bool,
+
+    // How many frames a peak is held for, and how far it falls per frame afterwards.
+    hold_frames: usize,
+    fall: SignalStrength,
+
+    // Current levels and peaks, as fractions of the graph height.
+    levels: Vec<SignalStrength>,
+    peaks: Option<Vec<Peak>>,
+}
+
+impl BarGraph {
+    /// Creates a graph `height` rows tall, with a range of -60 to 0 dB, no colour and no peak markers.
+    pub fn new(num_bands: usize, height: usize) -> Result<Self, Error> {
+        if !(num_bands > 0) { Err(Error::NumBands)? }
+        if !(height > 0) { Err(Error::GraphHeight)? }
+
+        Ok(Self {
+            height,
+            bar_width: 1,
+            min_db: -60.0,
+            max_db: 0.0,
+            color: false,
+            hold_frames: 0,
+            fall: 0.0,
+            levels: vec![0.0; num_bands],
+            peaks: None,
+        })
+    }
+
+    /// The number of bands of the given bar width that fit across `width` columns,
+    /// with a single column gap between bars.
+    pub fn bands_for_width(width: usize, bar_width: usize) -> usize {
+        (width + 1) / (bar_width.max(1) + 1)
+    }
+
+    /// Sets the decibel levels drawn at the bottom and top of the graph.
+    pub fn with_range(mut self, min_db: SignalStrength, max_db: SignalStrength) -> Result<Self, Error> {
+        if !(min_db < max_db) { Err(Error::DbRange)? }
+
+        self.min_db = min_db;
+        self.max_db = max_db;
+        Ok(self)
+    }
+
+    /// Sets the number of columns each bar takes up.
+    pub fn with_bar_width(mut self, bar_width: usize) -> Self {
+        self.bar_width = bar_width.max(1);
+        self
+    }
+
+    /// Enables or disables ANSI colour codes in the rendered output.
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Marks the highest recent level of each band, holding it for `hold_frames` updates
+    /// before letting it fall by `fall` of the graph height per update.
+    pub fn with_peak_hold(mut self, hold_frames: usize, fall: SignalStrength) -> Self {
+        self.hold_frames = hold_frames;
+        self.fall = fall;
+        self.peaks = Some(vec![Peak { level: 0.0, age: 0 }; self.levels.len()]);
+        self
+    }
+
+    #[inline]
+    pub fn num_bands(&self) -> usize {
+        self.levels.len()
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Converts a fraction of the graph height to a number of eighths of a row.
+    fn to_eighths(&self, fraction: SignalStrength) -> usize {
+        (fraction * (self.height * 8) as SignalStrength).round() as usize
+    }
+
+    /// Sets the current band levels, and updates the held peaks.
+    pub fn update(&mut self, levels: &[SignalStrength]) -> Result<(), Error> {
+        if levels.len() != self.num_bands() { Err(Error::InputBuffer(self.num_bands(), levels.len()))? }
+
+        let (min_db, max_db) = (self.min_db, self.max_db);
+        for (level, &power) in self.levels.iter_mut().zip(levels) {
+            *level = to_fraction(power, min_db, max_db);
+        }
+
+        if let Some(peaks) = &mut self.peaks {
+            for (peak, &level) in peaks.iter_mut().zip(&self.levels) {
+                if level >= peak.level {
+                    *peak = Peak { level, age: 0 };
+                }
+                else if peak.age < self.hold_frames {
+                    peak.age += 1;
+                }
+                else {
+                    peak.level = (peak.level - self.fall).max(level);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renders the graph, one line per row from top to bottom, each ending in a newline.
+    /// The output only depends on the levels given so far, so it can be compared against a snapshot.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for row in (0..self.height).rev() {
+            let color = row_color(row, self.height);
+            if self.color { out.push_str(color) }
+
+            for (i, &level) in self.levels.iter().enumerate() {
+                if i > 0 { out.push(' ') }
+
+                // How many eighths of this row the bar fills.
+                let filled = self.to_eighths(level).saturating_sub(row * 8).min(8);
+
+                // The row holding the top of the peak, if there is one.
+                let peak_row = match &self.peaks {
+                    Some(peaks) => match self.to_eighths(peaks[i].level) {
+                        0 => None,
+                        e => Some((e - 1) / 8),
+                    },
+                    None => None,
+                };
+
+                let c =
+                    if filled == 0 && peak_row == Some(row) { PEAK_MARKER }
+                    else { BLOCKS[filled] }
+                ;
+
+                for _ in 0..self.bar_width { out.push(c) }
+            }
+
+            if self.color { out.push_str(RESET) }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Converts a decibel level to a power.
+    fn power(db: SignalStrength) -> SignalStrength {
+        10.0f32.powf(db / 10.0)
+    }
+
+    #[test]
+    fn test_render() {
+        let mut graph = BarGraph::new(4, 2).unwrap().with_range(-40.0, 0.0).unwrap();
+
+        let inputs_and_expected = vec![
+            (vec![0.0, 0.0, 0.0, 0.0], "       \n       \n"),
+            (vec![power(0.0), power(-10.0), power(-20.0), power(-35.0)], "█ ▄    \n█ █ █ ▂\n"),
+            (vec![power(-5.0), power(-25.0), power(-40.0), power(-60.0)], "▆      \n█ ▆    \n"),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            graph.update(&input).unwrap();
+            let produced = graph.render();
+            assert_eq!(expected, produced);
+        }
+
+        let graph = graph.with_bar_width(2);
+        assert_eq!("▆▆         \n██ ▆▆      \n", graph.render());
+
+        assert_eq!(Err(Error::InputBuffer(4, 3)), graph.clone().update(&[0.0; 3]));
+    }
+
+    #[test]
+    fn test_render_color() {
+        let mut graph = BarGraph::new(2, 4).unwrap().with_range(-40.0, 0.0).unwrap().with_color(true);
+        graph.update(&[power(0.0), power(-30.0)]).unwrap();
+
+        let expected = concat!(
+            "\x1b[31m█  \x1b[0m\n",
+            "\x1b[33m█  \x1b[0m\n",
+            "\x1b[32m█  \x1b[0m\n",
+            "\x1b[32m█ █\x1b[0m\n",
+        );
+        assert_eq!(expected, graph.render());
+    }
+
+    #[test]
+    fn test_peak_hold() {
+        let mut graph = BarGraph::new(1, 4).unwrap().with_range(-40.0, 0.0).unwrap().with_peak_hold(1, 0.25);
+
+        // The peak is held for one update, and then falls a row per update until it meets the bar.
+        let inputs_and_expected = vec![
+            (power(0.0), "█\n█\n█\n█\n"),
+            (power(-30.0), "▔\n \n \n█\n"),
+            (power(-30.0), " \n▔\n \n█\n"),
+            (power(-30.0), " \n \n▔\n█\n"),
+            (power(-30.0), " \n \n \n█\n"),
+            (power(-20.0), " \n \n█\n█\n"),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            graph.update(&[input]).unwrap();
+            let produced = graph.render();
+            assert_eq!(expected, produced);
+        }
+    }
+
+    #[test]
+    fn test_bands_for_width() {
+        let inputs_and_expected = vec![
+            ((80, 1), 40),
+            ((79, 1), 40),
+            ((78, 1), 39),
+            ((80, 2), 27),
+            ((80, 3), 20),
+            ((1, 1), 1),
+            ((0, 1), 0),
+            ((10, 0), 5),
+        ];
+
+        for ((width, bar_width), expected) in inputs_and_expected {
+            let produced = BarGraph::bands_for_width(width, bar_width);
+            assert_eq!(expected, produced);
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(Some(Error::NumBands), BarGraph::new(0, 10).err());
+        assert_eq!(Some(Error::GraphHeight), BarGraph::new(10, 0).err());
+        assert_eq!(Some(Error::DbRange), BarGraph::new(10, 10).unwrap().with_range(0.0, 0.0).err());
+    }
+}
@@ -0,0 +1,252 @@
+//! Writes audio to WAV files, such as generated test signals or audio captured in the field.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use hound::SampleFormat as WavSampleFormat;
+use hound::WavSpec;
+
+use crate::Error;
+use crate::sample::Sample;
+use crate::sample::SampleBuffer;
+
+/// Format of the samples stored in a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavFormat {
+    I16,
+    I24,
+    F32,
+}
+
+impl WavFormat {
+    fn spec(&self, sample_rate: usize, num_channels: usize) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            &WavFormat::I16 => (16, WavSampleFormat::Int),
+            &WavFormat::I24 => (24, WavSampleFormat::Int),
+            &WavFormat::F32 => (32, WavSampleFormat::Float),
+        };
+
+        WavSpec {
+            channels: num_channels as u16,
+            sample_rate: sample_rate as u32,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+/// A WAV file opened for writing.
+/// Integer samples are clipped to full scale, and scaled the same way as `AudioFile` reads them back.
+/// The header is only complete once the writer is finalized or dropped.
+pub struct WavWriter {
+    writer: hound::WavWriter<BufWriter<File>>,
+    format: WavFormat,
+    num_channels: usize,
+    len: usize,
+}
+
+impl WavWriter {
+    /// Creates a WAV file with one or two channels.
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: usize, num_channels: usize, format: WavFormat) -> Result<Self, Error> {
+        if !(num_channels > 0 && num_channels <= 2) { Err(Error::NumChannels(num_channels))? }
+        if !(sample_rate > 0 && sample_rate <= u32::MAX as usize) { Err(Error::SamplingRate(sample_rate))? }
+
+        let writer = hound::WavWriter::create(path, format.spec(sample_rate, num_channels))?;
+
+        Ok(Self {
+            writer,
+            format,
+            num_channels,
+            len: 0,
+        })
+    }
+
+    /// The number of frames written so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    fn write_sample(&mut self, sample: Sample) -> Result<(), Error> {
+        match self.format {
+            WavFormat::I16 => self.writer.write_sample(quantize(sample, 16) as i16)?,
+            WavFormat::I24 => self.writer.write_sample(quantize(sample, 24))?,
+            WavFormat::F32 => self.writer.write_sample(sample)?,
+        }
+
+        Ok(())
+    }
+
+    /// Writes a stereo frame. Mono files get the average of the two channels.
+    pub fn write_frame(&mut self, (l, r): (Sample, Sample)) -> Result<(), Error> {
+        if self.num_channels == 1 {
+            self.write_sample((l + r) / 2.0)?;
+        }
+        else {
+            self.write_sample(l)?;
+            self.write_sample(r)?;
+        }
+
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Writes stereo frames, such as those from a `StereoGen`.
+    pub fn write_frames<I>(&mut self, frames: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (Sample, Sample)>,
+    {
+        frames.into_iter().try_for_each(|frame| self.write_frame(frame))
+    }
+
+    /// Writes mono samples, such as those from a `WaveGen`, duplicating them into both channels of stereo files.
+    pub fn write_samples<I>(&mut self, samples: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Sample>,
+    {
+        samples.into_iter().try_for_each(|s| self.write_frame((s, s)))
+    }
+
+    /// Writes interleaved stereo samples, as delivered by an audio device.
+    pub fn write_interleaved(&mut self, samples: &[Sample]) -> Result<(), Error> {
+        samples.chunks_exact(2).try_for_each(|c| self.write_frame((c[0], c[1])))
+    }
+
+    /// Writes the current contents of a sample buffer, oldest first.
+    pub fn write_sample_buffer(&mut self, sample_buffer: &SampleBuffer) -> Result<(), Error> {
+        self.write_frames(sample_buffer.iter())
+    }
+
+    /// Completes the header and flushes the file to disk.
+    pub fn finalize(self) -> Result<(), Error> {
+        self.writer.finalize()?;
+        Ok(())
+    }
+}
+
+// Converts a sample to an integer with the given number of bits, clipping it to full scale.
+fn quantize(sample: Sample, bits: u32) -> i32 {
+    let scale = (1i64 << (bits - 1)) as f64;
+    (sample as f64 * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::audio_file::AudioFile;
+    use crate::wave::StereoGen;
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("voxane_{}_{}.wav", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let left = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 440.0).with_amplitude(0.9);
+        let right = WaveGen::new(WaveFunction::Sawtooth, SAMPLE_RATE, 100.0);
+        let frames: Vec<(Sample, Sample)> = StereoGen::stereo(left, right).take(SAMPLE_RATE / 10).collect();
+
+        // Format, and the largest error expected once read back.
+        let inputs_and_expected = vec![
+            (WavFormat::I16, 0.5 / 32768.0),
+            (WavFormat::I24, 0.5 / 8388608.0),
+            (WavFormat::F32, 0.0),
+        ];
+
+        for (format, expected) in inputs_and_expected {
+            let path = temp_path(&format!("{:?}", format));
+
+            let mut writer = WavWriter::create(&path, SAMPLE_RATE, 2, format).unwrap();
+            writer.write_frames(frames.iter().cloned()).unwrap();
+            assert_eq!(frames.len(), writer.len());
+            writer.finalize().unwrap();
+
+            let audio_file = AudioFile::open(&path).unwrap();
+            assert_eq!(SAMPLE_RATE, audio_file.sample_rate());
+            assert_eq!(2, audio_file.num_channels());
+            assert_eq!(Some(frames.len()), audio_file.len());
+
+            let produced: Vec<(Sample, Sample)> = audio_file.collect();
+            assert_eq!(frames.len(), produced.len());
+
+            for ((el, er), (pl, pr)) in frames.iter().zip(produced) {
+                assert!((el - pl).abs() <= expected);
+                assert!((er - pr).abs() <= expected);
+            }
+
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let path = temp_path("header");
+
+        let mut writer = WavWriter::create(&path, 48000, 1, WavFormat::I24).unwrap();
+        writer.write_samples(vec![0.5, -0.5, 2.0, -2.0]).unwrap();
+        writer.finalize().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        assert_eq!(b"RIFF", &bytes[0..4]);
+        assert_eq!(bytes.len() as u32 - 8, u32_at(4));
+        assert_eq!(b"WAVE", &bytes[8..12]);
+
+        // Search for the chunks, since 24-bit files use the extensible format with a larger format chunk.
+        let fmt = bytes.windows(4).position(|w| w == b"fmt ").unwrap() + 8;
+        assert_eq!(1, u16_at(fmt + 2));
+        assert_eq!(48000, u32_at(fmt + 4));
+        assert_eq!(48000 * 3, u32_at(fmt + 8));
+        assert_eq!(3, u16_at(fmt + 12));
+        assert_eq!(24, u16_at(fmt + 14));
+
+        // Out of range samples are clipped to full scale.
+        let data = bytes.windows(4).position(|w| w == b"data").unwrap() + 8;
+        assert_eq!(12, u32_at(data - 4));
+        assert_eq!(&[0x00, 0x00, 0x40, 0x00, 0x00, 0xc0, 0xff, 0xff, 0x7f, 0x00, 0x00, 0x80], &bytes[data..data + 12]);
+    }
+
+    #[test]
+    fn test_sample_buffer() {
+        let path = temp_path("sample_buffer");
+
+        let sample_buffer = SampleBuffer::from(vec![(0.25, -0.25), (0.5, -0.5)]);
+
+        // Mono files get the average of both channels.
+        let mut writer = WavWriter::create(&path, SAMPLE_RATE, 1, WavFormat::F32).unwrap();
+        writer.write_sample_buffer(&sample_buffer).unwrap();
+        writer.write_interleaved(&[1.0, 0.0, 0.5]).unwrap();
+        writer.finalize().unwrap();
+
+        let produced: Vec<(Sample, Sample)> = AudioFile::open(&path).unwrap().collect();
+        std::fs::remove_file(&path).unwrap();
+
+        // A trailing unpaired sample in interleaved data is dropped.
+        assert_eq!(vec![(0.0, 0.0), (0.0, 0.0), (0.5, 0.5)], produced);
+    }
+
+    #[test]
+    fn test_create() {
+        let path = temp_path("create");
+
+        assert_eq!(Some(Error::NumChannels(0)), WavWriter::create(&path, SAMPLE_RATE, 0, WavFormat::I16).err());
+        assert_eq!(Some(Error::NumChannels(3)), WavWriter::create(&path, SAMPLE_RATE, 3, WavFormat::I16).err());
+        assert_eq!(Some(Error::SamplingRate(0)), WavWriter::create(&path, 0, 2, WavFormat::I16).err());
+        assert!(!path.exists());
+    }
+}
@@ -1,46 +1,198 @@
-use std::f32::consts::PI;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
 
+use crate::Error;
 use crate::sample::Sample;
 use crate::types::Frequency;
 
 const AMPLITUDE: f32 = 0.25;
 
-#[derive(Clone, Copy)]
+// Polynomial approximation of the difference between a band-limited and a naive unit step,
+// given the phase `t` of the wave and the phase increment `dt` per sample.
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t = t / dt;
+        2.0 * t - t * t - 1.0
+    }
+    else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + 2.0 * t + 1.0
+    }
+    else { 0.0 }
+}
+
+// Polynomial approximation of the difference between a band-limited and a naive unit ramp,
+// which smooths the corners of a wave the same way `poly_blep` smooths its steps.
+fn poly_blamp(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t = t / dt - 1.0;
+        -t * t * t / 3.0
+    }
+    else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt + 1.0;
+        t * t * t / 3.0
+    }
+    else { 0.0 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum WaveFunction {
     Sine,
     Square,
     Triangle,
     Sawtooth,
     SineMag,
+    /// Square wave with its steps smoothed by PolyBLEP, which greatly reduces aliasing.
+    BandLimitedSquare,
+    /// Triangle wave with its corners smoothed by PolyBLAMP.
+    BandLimitedTriangle,
+    /// Sawtooth wave with its steps smoothed by PolyBLEP.
+    BandLimitedSawtooth,
+    /// A single non-zero sample at the start of each period.
+    ImpulseTrain,
 }
 
 impl WaveFunction {
+    /// The value of the wave at the given sample, using the default amplitude of 0.25.
     pub fn val(&self, sample_index: usize, samples_per_period: usize, frequency: Frequency) -> Sample {
-        let f_x = sample_index as f32 * frequency / samples_per_period as f32;
-        AMPLITUDE * match self {
-            &WaveFunction::Sine => (2.0 * PI * f_x).sin(),
-            &WaveFunction::Square => (-1.0f32).powf((2.0 * f_x).floor()),
-            &WaveFunction::Triangle => 1.0 - 4.0 * (0.5 - (f_x + 0.25).fract()).abs(),
-            &WaveFunction::Sawtooth => 2.0 * f_x.fract() - 1.0,
-            &WaveFunction::SineMag => 2.0 * (PI * f_x).sin().abs() - 1.0,
+        let dt = frequency as f64 / samples_per_period as f64;
+        let f_x = sample_index as f64 * dt;
+
+        match self {
+            &WaveFunction::ImpulseTrain => {
+                // A new period starts on this sample if the phase wrapped around since the last one.
+                let prev_f_x = sample_index.wrapping_sub(1) as f64 * dt;
+                if sample_index == 0 || f_x.floor() != prev_f_x.floor() { AMPLITUDE } else { 0.0 }
+            },
+            _ => AMPLITUDE * self.at_phase(f_x.rem_euclid(1.0), dt),
         }
     }
+
+    /// The value of the wave, between -1 and 1, at the given phase in cycles from 0 to 1.
+    /// `dt` is how far the phase moves per sample, which the band-limited waves need to know.
+    pub fn at_phase(&self, t: f64, dt: f64) -> Sample {
+        let dt = dt.abs();
+
+        let v = match self {
+            &WaveFunction::Sine => (2.0 * PI * t).sin(),
+            &WaveFunction::Square => if t < 0.5 { 1.0 } else { -1.0 },
+            &WaveFunction::Triangle => 1.0 - 4.0 * (0.5 - (t + 0.25).fract()).abs(),
+            &WaveFunction::Sawtooth => 2.0 * t - 1.0,
+            &WaveFunction::SineMag => 2.0 * (PI * t).sin().abs() - 1.0,
+            &WaveFunction::BandLimitedSquare => {
+                let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(t, dt) - poly_blep((t + 0.5).fract(), dt)
+            },
+            &WaveFunction::BandLimitedTriangle => {
+                // The slope changes by -8 at the peak, a quarter of the way through the period, and by 8 at the trough.
+                // `poly_blamp` is scaled for a slope change of 2 per sample, like `poly_blep` is for a step of 2.
+                let naive = 1.0 - 4.0 * (0.5 - (t + 0.25).fract()).abs();
+                naive - 4.0 * dt * poly_blamp((t + 0.75).fract(), dt) + 4.0 * dt * poly_blamp((t + 0.25).fract(), dt)
+            },
+            &WaveFunction::BandLimitedSawtooth => 2.0 * t - 1.0 - poly_blep(t, dt),
+            // The phase wraps around to somewhere below `dt` on the first sample of each period.
+            &WaveFunction::ImpulseTrain => if t < dt { 1.0 } else { 0.0 },
+        };
+
+        v as Sample
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SweepKind {
+    /// The frequency rises by the same number of hertz every second.
+    Linear,
+    /// The frequency rises by the same number of octaves every second.
+    Logarithmic,
 }
 
+#[derive(Clone, Copy, Debug)]
+struct Sweep {
+    kind: SweepKind,
+    end: Frequency,
+    len: usize,
+}
+
+/// Generates a wave one sample at a time.
+/// The phase is accumulated from sample to sample, so it stays accurate for any frequency, however long it runs.
+#[derive(Clone, Debug)]
 pub struct WaveGen {
     function: WaveFunction,
     samples_per_period: usize,
-    sample_index: usize,
     frequency: Frequency,
+    amplitude: Sample,
+    dc_offset: Sample,
+    sweep: Option<Sweep>,
+
+    // Phase of the next sample, in cycles from 0 to 1.
+    phase: f64,
+    sample_index: usize,
 }
 
 impl WaveGen {
+    /// Creates a generator with an amplitude of 0.25, starting at a phase of zero.
+    /// `samples_per_period` is the number of samples per second, i.e. the sampling rate.
     pub fn new(function: WaveFunction, samples_per_period: usize, frequency: Frequency) -> Self {
         Self {
             function,
             samples_per_period,
-            sample_index: 0,
             frequency,
+            amplitude: AMPLITUDE,
+            dc_offset: 0.0,
+            sweep: None,
+            phase: 0.0,
+            sample_index: 0,
+        }
+    }
+
+    /// Sets the peak amplitude of the wave.
+    pub fn with_amplitude(mut self, amplitude: Sample) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    /// Sets the phase of the first sample, in radians.
+    pub fn with_phase(mut self, phase: f64) -> Self {
+        self.phase = (phase / (2.0 * PI)).rem_euclid(1.0);
+        self
+    }
+
+    /// Adds a constant offset to every sample.
+    pub fn with_dc_offset(mut self, dc_offset: Sample) -> Self {
+        self.dc_offset = dc_offset;
+        self
+    }
+
+    /// Sweeps the frequency from its starting value to `end` over `len` samples, holding it at `end` afterwards.
+    /// Logarithmic sweeps need both frequencies to be greater than zero.
+    pub fn with_sweep(mut self, end: Frequency, len: usize, kind: SweepKind) -> Result<Self, Error> {
+        if kind == SweepKind::Logarithmic && !(self.frequency > 0.0 && end > 0.0) { Err(Error::SweepRange)? }
+
+        self.sweep = Some(Sweep { kind, end, len });
+        Ok(self)
+    }
+
+    pub fn samples_per_period(&self) -> usize {
+        self.samples_per_period
+    }
+
+    /// The frequency of the next sample, which only changes while sweeping.
+    pub fn frequency(&self) -> Frequency {
+        match self.sweep {
+            Some(sweep) => {
+                if self.sample_index >= sweep.len { return sweep.end }
+
+                let x = self.sample_index as f64 / sweep.len as f64;
+                let (start, end) = (self.frequency as f64, sweep.end as f64);
+
+                let frequency = match sweep.kind {
+                    SweepKind::Linear => start + (end - start) * x,
+                    SweepKind::Logarithmic => start * (end / start).powf(x),
+                };
+
+                frequency as Frequency
+            },
+            None => self.frequency,
         }
     }
 }
@@ -49,8 +201,633 @@ impl Iterator for WaveGen {
     type Item = Sample;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let v = self.function.val(self.sample_index, self.samples_per_period, self.frequency);
-        self.sample_index = (self.sample_index + 1) % self.samples_per_period;
+        let dt = self.frequency() as f64 / self.samples_per_period as f64;
+        let v = self.dc_offset + self.amplitude * self.function.at_phase(self.phase, dt);
+
+        self.phase = (self.phase + dt).rem_euclid(1.0);
+        self.sample_index = self.sample_index.saturating_add(1);
         Some(v)
     }
 }
+
+/// The sum of several waves, such as a set of test tones.
+#[derive(Clone, Debug)]
+pub struct MultiTone {
+    tones: Vec<WaveGen>,
+}
+
+impl MultiTone {
+    /// Creates sine waves of the given frequencies, with equal amplitudes that sum to at most 0.25.
+    pub fn new(samples_per_period: usize, frequencies: &[Frequency]) -> Self {
+        let amplitude = AMPLITUDE / frequencies.len().max(1) as Sample;
+
+        let tones = frequencies
+            .iter()
+            .map(|&f| WaveGen::new(WaveFunction::Sine, samples_per_period, f).with_amplitude(amplitude))
+            .collect();
+
+        Self { tones }
+    }
+
+    /// Adds another wave to the sum.
+    pub fn with_tone(mut self, tone: WaveGen) -> Self {
+        self.tones.push(tone);
+        self
+    }
+}
+
+impl Iterator for MultiTone {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.tones.iter_mut().filter_map(|t| t.next()).sum())
+    }
+}
+
+/// A small pseudo-random number generator (SplitMix64), so that noise can be reproduced from a seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A sample uniformly distributed between -1 and 1.
+    pub fn next_sample(&mut self) -> Sample {
+        // The top 24 bits fill the mantissa of an f32 exactly.
+        let x = (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32;
+        2.0 * x - 1.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseColor {
+    /// Equal power at every frequency.
+    White,
+    /// Power falling by 3 dB per octave, so that each octave holds equal power.
+    Pink,
+    /// Power falling by 6 dB per octave, like a random walk.
+    Brown,
+}
+
+/// Generates noise of the given color, which is the same every time for a given seed.
+#[derive(Clone, Debug)]
+pub struct NoiseGen {
+    color: NoiseColor,
+    rng: Rng,
+    amplitude: Sample,
+
+    // Filter state used to color white noise.
+    state: [f32; 7],
+}
+
+impl NoiseGen {
+    /// Creates a generator whose samples stay within an amplitude of 0.25.
+    pub fn new(color: NoiseColor, seed: u64) -> Self {
+        Self {
+            color,
+            rng: Rng::new(seed),
+            amplitude: AMPLITUDE,
+            state: [0.0; 7],
+        }
+    }
+
+    /// Scales the noise to stay within the given amplitude.
+    pub fn with_amplitude(mut self, amplitude: Sample) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+}
+
+impl Iterator for NoiseGen {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let white = self.rng.next_sample();
+        let b = &mut self.state;
+
+        let v = match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                // Paul Kellet's filter, which is accurate to within 0.05 dB above 9 Hz at 44.1 kHz.
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                0.11 * pink
+            },
+            NoiseColor::Brown => {
+                // A slightly leaky integrator, so that the walk doesn't wander off.
+                b[0] = (b[0] + 0.02 * white) / 1.02;
+                3.5 * b[0]
+            },
+        };
+
+        Some(self.amplitude * v)
+    }
+}
+
+/// A single non-zero sample after `delay` samples of silence, followed by silence forever.
+#[derive(Clone, Debug)]
+pub struct Impulse {
+    delay: usize,
+    amplitude: Sample,
+    sample_index: usize,
+}
+
+impl Impulse {
+    /// Creates an impulse with an amplitude of 0.25.
+    pub fn new(delay: usize) -> Self {
+        Self {
+            delay,
+            amplitude: AMPLITUDE,
+            sample_index: 0,
+        }
+    }
+
+    pub fn with_amplitude(mut self, amplitude: Sample) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+}
+
+impl Iterator for Impulse {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let v = if self.sample_index == self.delay { self.amplitude } else { 0.0 };
+        self.sample_index = self.sample_index.saturating_add(1);
+        Some(v)
+    }
+}
+
+// A boxed mono signal, so that different kinds of generator can be combined.
+pub(crate) type Signal = Box<dyn Iterator<Item = Sample> + Send>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Left,
+    Right,
+}
+
+enum StereoContent {
+    Mono(Signal),
+    Stereo(Signal, Signal),
+    Correlated {
+        shared: Signal,
+        left: Signal,
+        right: Signal,
+        correlation: Sample,
+    },
+}
+
+/// Generates stereo frames, with separate content, gain and delay for each channel.
+pub struct StereoGen {
+    content: StereoContent,
+    gains: (Sample, Sample),
+
+    // Samples waiting to come out of each channel, if it is delayed.
+    delays: (VecDeque<Sample>, VecDeque<Sample>),
+}
+
+impl StereoGen {
+    fn from_content(content: StereoContent) -> Self {
+        Self {
+            content,
+            gains: (1.0, 1.0),
+            delays: (VecDeque::new(), VecDeque::new()),
+        }
+    }
+
+    /// Plays the same signal in both channels.
+    pub fn mono<I>(signal: I) -> Self
+    where
+        I: Iterator<Item = Sample> + Send + 'static,
+    {
+        Self::from_content(StereoContent::Mono(Box::new(signal)))
+    }
+
+    /// Plays independent signals in each channel.
+    pub fn stereo<L, R>(left: L, right: R) -> Self
+    where
+        L: Iterator<Item = Sample> + Send + 'static,
+        R: Iterator<Item = Sample> + Send + 'static,
+    {
+        Self::from_content(StereoContent::Stereo(Box::new(left), Box::new(right)))
+    }
+
+    /// Mixes a signal shared by both channels with a signal of its own in each channel.
+    /// If all three signals are uncorrelated and have the same power, the channels have the given correlation, from 0 to 1.
+    pub fn correlated<S, L, R>(shared: S, left: L, right: R, correlation: Sample) -> Self
+    where
+        S: Iterator<Item = Sample> + Send + 'static,
+        L: Iterator<Item = Sample> + Send + 'static,
+        R: Iterator<Item = Sample> + Send + 'static,
+    {
+        Self::from_content(StereoContent::Correlated {
+            shared: Box::new(shared),
+            left: Box::new(left),
+            right: Box::new(right),
+            correlation: correlation.clamp(0.0, 1.0),
+        })
+    }
+
+    /// Scales one channel by the given gain.
+    pub fn with_gain(mut self, channel: Channel, gain: Sample) -> Self {
+        match channel {
+            Channel::Left => self.gains.0 *= gain,
+            Channel::Right => self.gains.1 *= gain,
+        }
+        self
+    }
+
+    /// Inverts the phase of one channel.
+    pub fn with_inversion(self, channel: Channel) -> Self {
+        self.with_gain(channel, -1.0)
+    }
+
+    /// Pans between the left, at -1, and the right, at 1, using a constant power pan law,
+    /// so that a centred signal is 3 dB quieter in each channel.
+    pub fn with_pan(self, pan: Sample) -> Self {
+        let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        self.with_gain(Channel::Left, angle.cos()).with_gain(Channel::Right, angle.sin())
+    }
+
+    /// Delays one channel by the given number of samples, filling the gap with silence.
+    pub fn with_delay(mut self, channel: Channel, delay: usize) -> Self {
+        let buffer = match channel {
+            Channel::Left => &mut self.delays.0,
+            Channel::Right => &mut self.delays.1,
+        };
+
+        buffer.extend(std::iter::repeat(0.0).take(delay));
+        self
+    }
+}
+
+impl Iterator for StereoGen {
+    type Item = (Sample, Sample);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (l, r) = match &mut self.content {
+            StereoContent::Mono(signal) => {
+                let s = signal.next()?;
+                (s, s)
+            },
+            StereoContent::Stereo(left, right) => (left.next()?, right.next()?),
+            StereoContent::Correlated { shared, left, right, correlation } => {
+                let s = shared.next()?;
+                let (l, r) = (left.next()?, right.next()?);
+
+                // The shared part makes up the given fraction of the power of each channel.
+                let (c, d) = (correlation.sqrt(), (1.0 - *correlation).sqrt());
+                (c * s + d * l, c * s + d * r)
+            },
+        };
+
+        let (delay_l, delay_r) = &mut self.delays;
+        delay_l.push_back(l * self.gains.0);
+        delay_r.push_back(r * self.gains.1);
+
+        Some((delay_l.pop_front()?, delay_r.pop_front()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analyzer::Analyzer;
+    use crate::sample::SampleBuffer;
+    use crate::types::SignalStrength;
+    use crate::window_kind::WindowKind;
+
+    const FFT_LEN: usize = 4096;
+
+    fn rising_zero_crossings(samples: &[Sample]) -> usize {
+        samples.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    // Average power spectrum of a signal over consecutive windows.
+    fn average_spectrum<I: Iterator<Item = Sample>>(samples: I, num_windows: usize) -> Vec<SignalStrength> {
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let mut samples = samples;
+        let mut average = vec![0.0; FFT_LEN / 2];
+
+        for _ in 0..num_windows {
+            let window: Vec<Sample> = samples.by_ref().take(FFT_LEN).collect();
+            let (spectrum, _) = analyzer.analyze(&SampleBuffer::from(window)).unwrap();
+
+            for (a, s) in average.iter_mut().zip(spectrum) {
+                *a += s / num_windows as SignalStrength;
+            }
+        }
+
+        average
+    }
+
+    #[test]
+    fn test_band_limited() {
+        // Using the FFT length as the sampling rate puts every harmonic that doesn't alias right on a bin.
+        const FREQUENCY: usize = 301;
+
+        let inputs = vec![
+            (WaveFunction::Square, WaveFunction::BandLimitedSquare),
+            (WaveFunction::Triangle, WaveFunction::BandLimitedTriangle),
+            (WaveFunction::Sawtooth, WaveFunction::BandLimitedSawtooth),
+        ];
+
+        // The fraction of the power of a wave that falls outside of its harmonics, from aliasing.
+        let aliased_fraction = |function: WaveFunction| {
+            let samples: Vec<Sample> = WaveGen::new(function, FFT_LEN, FREQUENCY as Frequency).take(FFT_LEN).collect();
+            let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Rectangular);
+            let (spectrum, _) = analyzer.analyze(&SampleBuffer::from(samples)).unwrap();
+
+            let spectrum = &spectrum[1..FFT_LEN / 2];
+            let total: SignalStrength = spectrum.iter().sum();
+            let aliased: SignalStrength = spectrum.iter().enumerate().filter(|(i, _)| (i + 1) % FREQUENCY != 0).map(|(_, s)| s).sum();
+            aliased / total
+        };
+
+        for (naive, band_limited) in inputs {
+            let naive_fraction = aliased_fraction(naive);
+            let band_limited_fraction = aliased_fraction(band_limited);
+            println!("{:?}: {}, {:?}: {}", naive, naive_fraction, band_limited, band_limited_fraction);
+
+            assert!(band_limited_fraction < naive_fraction / 5.0);
+        }
+    }
+
+    #[test]
+    fn test_band_limited_shape() {
+        // Away from the steps and corners, the band-limited waves match the naive ones.
+        let inputs = vec![
+            (WaveFunction::Square, WaveFunction::BandLimitedSquare),
+            (WaveFunction::Triangle, WaveFunction::BandLimitedTriangle),
+            (WaveFunction::Sawtooth, WaveFunction::BandLimitedSawtooth),
+        ];
+
+        for (naive, band_limited) in inputs {
+            for i in [3, 10, 20, 30, 40, 60, 70, 90] {
+                assert_approx_eq!(naive.val(i, 100, 1.0), band_limited.val(i, 100, 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_impulses() {
+        let produced: Vec<Sample> = Impulse::new(3).take(6).collect();
+        assert_eq!(vec![0.0, 0.0, 0.0, AMPLITUDE, 0.0, 0.0], produced);
+
+        let inputs_and_expected = vec![
+            (100.0, 100),
+            (441.0, 441),
+            (1000.0, 1000),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let produced = WaveGen::new(WaveFunction::ImpulseTrain, 44100, input).take(44100).filter(|&s| s != 0.0).count();
+            assert_eq!(expected, produced);
+        }
+    }
+
+    #[test]
+    fn test_noise_seed() {
+        for &color in &[NoiseColor::White, NoiseColor::Pink, NoiseColor::Brown] {
+            let a: Vec<Sample> = NoiseGen::new(color, 1).take(1000).collect();
+            let b: Vec<Sample> = NoiseGen::new(color, 1).take(1000).collect();
+            let c: Vec<Sample> = NoiseGen::new(color, 2).take(1000).collect();
+
+            assert_eq!(a, b);
+            assert_ne!(a, c);
+            assert!(a.iter().all(|s| s.abs() <= 1.0));
+        }
+    }
+
+    #[test]
+    fn test_white_noise() {
+        const LEN: usize = 100_000;
+
+        let samples: Vec<Sample> = NoiseGen::new(NoiseColor::White, 0).take(LEN).collect();
+
+        // Uniform noise between -A and A has a mean of 0 and a variance of A^2 / 3.
+        let mean = samples.iter().sum::<Sample>() / LEN as Sample;
+        let variance = samples.iter().map(|s| s * s).sum::<Sample>() / LEN as Sample;
+
+        assert!(mean.abs() < 0.01 * AMPLITUDE);
+        assert!((variance / (AMPLITUDE * AMPLITUDE / 3.0) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_noise_slope() {
+        const NUM_WINDOWS: usize = 32;
+
+        // Decibel drop in average power per bin between bins 32 to 64 and bins 256 to 512, three octaves higher.
+        let inputs_and_expected = vec![
+            (NoiseColor::White, 0.0),
+            (NoiseColor::Pink, 9.0),
+            (NoiseColor::Brown, 18.0),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let spectrum = average_spectrum(NoiseGen::new(input, 7), NUM_WINDOWS);
+
+            let low = spectrum[32..64].iter().sum::<SignalStrength>() / 32.0;
+            let high = spectrum[256..512].iter().sum::<SignalStrength>() / 256.0;
+            let produced = 10.0 * (low / high).log10();
+            println!("{:?}: {}", input, produced);
+
+            assert!((expected - produced).abs() < 1.5);
+        }
+    }
+
+    #[test]
+    fn test_phase_accumulator() {
+        const SAMPLE_RATE: usize = 44100;
+        const FREQUENCY: Frequency = 440.7;
+
+        // Far into the run, the wave still matches its closed form.
+        let inputs = vec![0, 1, 44100, 1_000_000, 10_000_000];
+
+        for input in inputs {
+            let produced = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, FREQUENCY).nth(input).unwrap();
+            let expected = AMPLITUDE * (2.0 * PI * (input as f64 * FREQUENCY as f64 / SAMPLE_RATE as f64).fract()).sin() as Sample;
+            assert!((expected - produced).abs() < 1e-4);
+        }
+
+        // A frequency that doesn't divide the sampling rate still completes the right number of cycles,
+        // 4407 in ten seconds, all but the first of which start with a rising zero crossing.
+        let wave: Vec<Sample> = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, FREQUENCY).take(SAMPLE_RATE * 10).collect();
+        assert_eq!(4406, rising_zero_crossings(&wave));
+    }
+
+    #[test]
+    fn test_amplitude_phase_and_dc_offset() {
+        let mut wave_gen = WaveGen::new(WaveFunction::Sine, 100, 1.0)
+            .with_amplitude(1.0)
+            .with_phase(PI / 2.0)
+            .with_dc_offset(0.5)
+        ;
+
+        let expected = vec![1.5, 0.5, -0.5, 0.5];
+        for e in expected {
+            assert_approx_eq!(e, wave_gen.next().unwrap());
+            wave_gen.nth(23);
+        }
+
+        // Negative phases wrap around.
+        let a: Vec<Sample> = WaveGen::new(WaveFunction::Triangle, 100, 3.0).with_phase(-PI / 2.0).take(100).collect();
+        let b: Vec<Sample> = WaveGen::new(WaveFunction::Triangle, 100, 3.0).with_phase(3.0 * PI / 2.0).take(100).collect();
+        for (a, b) in a.into_iter().zip(b) {
+            assert_approx_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_sweep() {
+        const SAMPLE_RATE: usize = 44100;
+
+        // Number of cycles in a one second sweep from 1000 to 2000 Hz, the integral of its frequency.
+        let inputs_and_expected = vec![
+            (SweepKind::Linear, (1500.0, 1500)),
+            (SweepKind::Logarithmic, (2000.0f32.sqrt() * 1000.0f32.sqrt(), (1000.0 / 2.0f64.ln()) as usize)),
+        ];
+
+        for (kind, (midpoint, cycles)) in inputs_and_expected {
+            let mut wave_gen = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 1000.0).with_sweep(2000.0, SAMPLE_RATE, kind).unwrap();
+            assert_approx_eq!(1000.0, wave_gen.frequency());
+
+            let first_half: Vec<Sample> = wave_gen.by_ref().take(SAMPLE_RATE / 2).collect();
+            assert!((midpoint - wave_gen.frequency()).abs() < 0.1);
+
+            let second_half: Vec<Sample> = wave_gen.by_ref().take(SAMPLE_RATE / 2).collect();
+            assert_approx_eq!(2000.0, wave_gen.frequency());
+
+            let produced = rising_zero_crossings(&[first_half, second_half].concat());
+            assert!((cycles as isize - produced as isize).abs() <= 1);
+
+            // The frequency holds once the sweep is over.
+            wave_gen.nth(SAMPLE_RATE);
+            assert_approx_eq!(2000.0, wave_gen.frequency());
+        }
+
+        let wave_gen = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 0.0);
+        assert!(wave_gen.clone().with_sweep(1000.0, SAMPLE_RATE, SweepKind::Linear).is_ok());
+        assert_eq!(Some(Error::SweepRange), wave_gen.with_sweep(1000.0, SAMPLE_RATE, SweepKind::Logarithmic).err());
+    }
+
+    #[test]
+    fn test_multi_tone() {
+        // Tones right in the middle of bins 10, 50 and 200.
+        let frequencies = [10.0, 50.0, 200.0];
+        let samples: Vec<Sample> = MultiTone::new(FFT_LEN, &frequencies).take(FFT_LEN).collect();
+
+        assert!(samples.iter().all(|s| s.abs() <= AMPLITUDE));
+
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Rectangular);
+        let (spectrum, _) = analyzer.analyze(&SampleBuffer::from(samples)).unwrap();
+
+        let mut peaks: Vec<usize> = (0..FFT_LEN / 2).collect();
+        peaks.sort_by(|&a, &b| spectrum[b].partial_cmp(&spectrum[a]).unwrap());
+        peaks.truncate(3);
+        peaks.sort();
+        assert_eq!(vec![10, 50, 200], peaks);
+
+        // Each tone has a third of the amplitude.
+        let expected = (AMPLITUDE / 3.0 * FFT_LEN as Sample / 2.0).powi(2);
+        for &peak in &peaks {
+            assert!((spectrum[peak] / expected - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_stereo_channels() {
+        // Independent tones in each channel show up in their own spectrum.
+        let left = WaveGen::new(WaveFunction::Sine, FFT_LEN, 100.0);
+        let right = WaveGen::new(WaveFunction::Sine, FFT_LEN, 300.0);
+        let frames: Vec<(Sample, Sample)> = StereoGen::stereo(left, right).take(FFT_LEN).collect();
+
+        let mut analyzer = Analyzer::new(FFT_LEN, WindowKind::Hanning);
+        let (spectrum_l, spectrum_r) = analyzer.analyze(&SampleBuffer::from(frames)).unwrap();
+        assert!(spectrum_l[100] > 1e6 * spectrum_l[300]);
+        assert!(spectrum_r[300] > 1e6 * spectrum_r[100]);
+
+        // Inverting a channel of a mono signal cancels it out when the channels are summed.
+        let wave_gen = WaveGen::new(WaveFunction::Sine, 44100, 440.0);
+        let frames = StereoGen::mono(wave_gen).with_inversion(Channel::Right).take(1000);
+        assert!(frames.map(|(l, r)| l + r).all(|s| s == 0.0));
+    }
+
+    #[test]
+    fn test_stereo_gain_and_delay() {
+        let produced: Vec<(Sample, Sample)> = StereoGen::mono(Impulse::new(1).with_amplitude(1.0))
+            .with_gain(Channel::Left, 0.5)
+            .with_delay(Channel::Right, 2)
+            .take(5)
+            .collect()
+        ;
+        assert_eq!(vec![(0.0, 0.0), (0.5, 0.0), (0.0, 0.0), (0.0, 1.0), (0.0, 0.0)], produced);
+
+        // The stream ends once the signal does, without flushing the delayed channel.
+        let produced = StereoGen::mono(vec![1.0, 2.0, 3.0].into_iter()).with_delay(Channel::Left, 1).collect::<Vec<_>>();
+        assert_eq!(vec![(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)], produced);
+    }
+
+    #[test]
+    fn test_stereo_pan() {
+        let inputs_and_expected = vec![
+            (-1.0, (1.0, 0.0)),
+            (-0.5, (0.9238795, 0.38268343)),
+            (0.0, (std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2)),
+            (1.0, (0.0, 1.0)),
+            (2.0, (0.0, 1.0)),
+        ];
+
+        for (input, (expected_l, expected_r)) in inputs_and_expected {
+            let (l, r) = StereoGen::mono(std::iter::repeat(1.0)).with_pan(input).next().unwrap();
+            assert_approx_eq!(expected_l, l);
+            assert_approx_eq!(expected_r, r);
+        }
+    }
+
+    #[test]
+    fn test_stereo_correlation() {
+        const LEN: usize = 100_000;
+
+        let inputs = vec![0.0, 0.3, 0.5, 0.9, 1.0];
+
+        for input in inputs {
+            let frames: Vec<(Sample, Sample)> = StereoGen::correlated(
+                NoiseGen::new(NoiseColor::White, 1),
+                NoiseGen::new(NoiseColor::White, 2),
+                NoiseGen::new(NoiseColor::White, 3),
+                input,
+            ).take(LEN).collect();
+
+            let (lr, ll, rr) = frames.iter().fold((0.0, 0.0, 0.0), |(lr, ll, rr), (l, r)| (lr + l * r, ll + l * l, rr + r * r));
+            let produced: Sample = lr / (ll * rr).sqrt();
+            println!("{}: {}", input, produced);
+
+            assert!((input - produced).abs() < 0.01);
+
+            // The power of each channel doesn't depend on the correlation.
+            assert!((ll / LEN as Sample / (AMPLITUDE * AMPLITUDE / 3.0) - 1.0).abs() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! The A, C and Z frequency weightings from IEC 61672-1, as filters for level metering
+//! and as curves for weighting spectra.
+
+use crate::Error;
+use crate::buckets::Buckets;
+use crate::filter::Biquad;
+use crate::sample::Sample;
+use crate::types::Frequency;
+use crate::types::SignalStrength;
+
+// The pole frequencies of the weightings, from annex E of IEC 61672-1.
+const F1: f64 = 20.598997;
+const F2: f64 = 107.65265;
+const F3: f64 = 737.86223;
+const F4: f64 = 12194.217;
+
+// The weightings are normalized to unity gain here.
+const REFERENCE_FREQUENCY: f64 = 1000.0;
+
+/// A frequency weighting, which models how loud sounds of different frequencies seem.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Weighting {
+    /// Follows hearing at low levels, cutting low frequencies steeply. Used for most noise measurements.
+    #[default]
+    A,
+    /// Follows hearing at high levels, only cutting the extremes. Used for peak levels.
+    C,
+    /// No weighting at all.
+    Z,
+}
+
+impl Weighting {
+    // The unnormalized analog response at a frequency.
+    fn response(&self, frequency: f64) -> f64 {
+        let f2 = frequency * frequency;
+
+        match self {
+            &Weighting::A => {
+                F4 * F4 * f2 * f2
+                / ((f2 + F1 * F1) * ((f2 + F2 * F2) * (f2 + F3 * F3)).sqrt() * (f2 + F4 * F4))
+            },
+            &Weighting::C => F4 * F4 * f2 / ((f2 + F1 * F1) * (f2 + F4 * F4)),
+            &Weighting::Z => 1.0,
+        }
+    }
+
+    /// The amplitude gain of the weighting at a frequency, which is one at 1 kHz.
+    pub fn gain(&self, frequency: Frequency) -> SignalStrength {
+        (self.response(frequency as f64) / self.response(REFERENCE_FREQUENCY)) as SignalStrength
+    }
+
+    /// The gain of the weighting at a frequency, in decibels.
+    pub fn gain_db(&self, frequency: Frequency) -> SignalStrength {
+        20.0 * self.gain(frequency).log10()
+    }
+
+    /// Weights a power spectrum, as produced by `Analyzer`, with each bin weighted at its own frequency.
+    /// Bins above the Nyquist frequency mirror those below it.
+    pub fn weigh_spectrum(&self, spectrum: &[SignalStrength], sampling_rate: usize) -> Result<Vec<SignalStrength>, Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let n = spectrum.len();
+        let fft_bin_size = sampling_rate as Frequency / n as Frequency;
+
+        let weighted =
+            spectrum
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s * self.gain(fft_bin_size * i.min(n - i) as Frequency).powi(2))
+            .collect()
+        ;
+
+        Ok(weighted)
+    }
+
+    /// Weights the band powers produced by `Buckets`, with each band weighted at its geometric centre.
+    pub fn weigh_bands(&self, bands: &[SignalStrength], buckets: &Buckets) -> Result<Vec<SignalStrength>, Error> {
+        if bands.len() != buckets.len() { Err(Error::InputBuffer(buckets.len(), bands.len()))? }
+
+        let weighted =
+            bands
+            .iter()
+            .zip(buckets.bands())
+            .map(|(s, (lo, hi))| s * self.gain((lo * hi).sqrt()).powi(2))
+            .collect()
+        ;
+
+        Ok(weighted)
+    }
+}
+
+/// Applies a frequency weighting to a stream of samples, for level metering.
+/// The analog poles are prewarped to land at their own frequencies, so at 44.1 kHz and above the filter follows
+/// the weighting to within 1 dB up to 10 kHz, and falls away faster towards the Nyquist frequency.
+#[derive(Clone, Debug)]
+pub struct WeightingFilter {
+    weighting: Weighting,
+    sections: Vec<Biquad>,
+}
+
+impl WeightingFilter {
+    /// Creates a filter for a sample rate, which must be more than twice the highest pole frequency of about 12.2 kHz,
+    /// unless the weighting is Z.
+    pub fn new(weighting: Weighting, sampling_rate: usize) -> Result<Self, Error> {
+        if !(sampling_rate > 0) { Err(Error::SamplingRate(sampling_rate))? }
+        if weighting != Weighting::Z && !(sampling_rate as f64 > 2.0 * F4) { Err(Error::SamplingRate(sampling_rate))? }
+
+        let sr = sampling_rate as f64;
+        let mut sections = match weighting {
+            Weighting::A => vec![high_pass_pair(F1, F1, sr), high_pass_pair(F2, F3, sr), low_pass_pair(F4, F4, sr)],
+            Weighting::C => vec![high_pass_pair(F1, F1, sr), low_pass_pair(F4, F4, sr)],
+            Weighting::Z => vec![],
+        };
+
+        // Scales the first section, so that the whole filter has unity gain at the reference frequency.
+        let gain: f64 = sections.iter().map(|s| s.gain(REFERENCE_FREQUENCY as Frequency, sampling_rate)).product();
+        if let Some(first) = sections.first_mut() {
+            let b = first.b();
+            *first = Biquad::new([b[0] / gain, b[1] / gain, b[2] / gain], first.a());
+        }
+
+        Ok(Self { weighting, sections })
+    }
+
+    #[inline]
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Filters the next sample.
+    pub fn process(&mut self, x: Sample) -> Sample {
+        self.sections.iter_mut().fold(x as f64, |y, section| section.process(y)) as Sample
+    }
+
+    /// Filters a slice of samples, continuing on from the previous ones.
+    pub fn process_samples(&mut self, samples: &[Sample]) -> Vec<Sample> {
+        samples.iter().map(|&x| self.process(x)).collect()
+    }
+
+    /// Clears the filter's memory of previous samples.
+    pub fn reset(&mut self) {
+        for section in self.sections.iter_mut() { section.reset() }
+    }
+
+    /// The gain of the filter at a frequency.
+    pub fn gain(&self, frequency: Frequency, sampling_rate: usize) -> SignalStrength {
+        self.sections.iter().map(|s| s.gain(frequency, sampling_rate)).product::<f64>() as SignalStrength
+    }
+}
+
+// The bilinear transform of an analog pole at `frequency`, prewarped so that it lands at the same frequency,
+// as the coefficients of its denominator in z^-1.
+fn digital_pole(frequency: f64, sampling_rate: f64) -> [f64; 2] {
+    let k = 2.0 * sampling_rate;
+    let w = k * (std::f64::consts::PI * frequency / sampling_rate).tan();
+    [k + w, w - k]
+}
+
+// Two first order high passes, `s / (s + w)`, in one section.
+fn high_pass_pair(f_a: f64, f_b: f64, sampling_rate: f64) -> Biquad {
+    let k = 2.0 * sampling_rate;
+    let (p, q) = (digital_pole(f_a, sampling_rate), digital_pole(f_b, sampling_rate));
+    Biquad::new([k * k, -2.0 * k * k, k * k], [p[0] * q[0], p[0] * q[1] + p[1] * q[0], p[1] * q[1]])
+}
+
+// Two first order low passes, `1 / (s + w)`, in one section. Their gain is set later on.
+fn low_pass_pair(f_a: f64, f_b: f64, sampling_rate: f64) -> Biquad {
+    let (p, q) = (digital_pole(f_a, sampling_rate), digital_pole(f_b, sampling_rate));
+    Biquad::new([1.0, 2.0, 1.0], [p[0] * q[0], p[0] * q[1] + p[1] * q[0], p[1] * q[1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::wave::WaveFunction;
+    use crate::wave::WaveGen;
+
+    // The A and C weightings in decibels at the nominal one-third octave frequencies from 10 Hz to 20 kHz,
+    // from table 3 of IEC 61672-1. Each is calculated at the exact base ten frequency, `1000 * 10^(n / 10)`.
+    const TABLE: [(Frequency, SignalStrength, SignalStrength); 34] = [
+        (10.0, -70.4, -14.3), (12.5, -63.4, -11.2), (16.0, -56.7, -8.5), (20.0, -50.5, -6.2),
+        (25.0, -44.7, -4.4), (31.5, -39.4, -3.0), (40.0, -34.6, -2.0), (50.0, -30.2, -1.3),
+        (63.0, -26.2, -0.8), (80.0, -22.5, -0.5), (100.0, -19.1, -0.3), (125.0, -16.1, -0.2),
+        (160.0, -13.4, -0.1), (200.0, -10.9, 0.0), (250.0, -8.6, 0.0), (315.0, -6.6, 0.0),
+        (400.0, -4.8, 0.0), (500.0, -3.2, 0.0), (630.0, -1.9, 0.0), (800.0, -0.8, 0.0),
+        (1000.0, 0.0, 0.0), (1250.0, 0.6, 0.0), (1600.0, 1.0, -0.1), (2000.0, 1.2, -0.2),
+        (2500.0, 1.3, -0.3), (3150.0, 1.2, -0.5), (4000.0, 1.0, -0.8), (5000.0, 0.5, -1.3),
+        (6300.0, -0.1, -2.0), (8000.0, -1.1, -3.0), (10000.0, -2.5, -4.4), (12500.0, -4.3, -6.2),
+        (16000.0, -6.6, -8.5), (20000.0, -9.3, -11.2),
+    ];
+
+    fn exact_frequency(n: usize) -> Frequency {
+        1000.0 * 10.0f32.powf((n as f32 - 20.0) / 10.0)
+    }
+
+    #[test]
+    fn test_gain() {
+        for (n, &(nominal, a, c)) in TABLE.iter().enumerate() {
+            let frequency = exact_frequency(n);
+            println!("{} ({}): {}, {}", nominal, frequency, Weighting::A.gain_db(frequency), Weighting::C.gain_db(frequency));
+
+            assert_approx_eq!(a, Weighting::A.gain_db(frequency), 0.05);
+            assert_approx_eq!(c, Weighting::C.gain_db(frequency), 0.05);
+            assert_eq!(0.0, Weighting::Z.gain_db(frequency));
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        for &sampling_rate in &[44100, 48000, 96000] {
+            let filters = [
+                WeightingFilter::new(Weighting::A, sampling_rate).unwrap(),
+                WeightingFilter::new(Weighting::C, sampling_rate).unwrap(),
+                WeightingFilter::new(Weighting::Z, sampling_rate).unwrap(),
+            ];
+
+            // The filters follow the table closely at low frequencies, and to well within the class 1 tolerances
+            // of IEC 61672-1 up to 10 kHz. Above that they fall away towards the Nyquist frequency,
+            // but stay within the class 1 tolerances, which widen to +4 dB and no lower limit at 20 kHz.
+            for (n, &(nominal, a, c)) in TABLE.iter().enumerate() {
+                let frequency = exact_frequency(n);
+                let (above, below) = match nominal {
+                    f if f <= 1000.0 => (0.1, 0.1),
+                    f if f <= 10000.0 => (1.0, 1.0),
+                    f if f <= 12500.0 => (3.0, 6.0),
+                    f if f <= 16000.0 => (3.5, 17.0),
+                    _ => (4.0, SignalStrength::INFINITY),
+                };
+
+                for (filter, expected) in filters.iter().zip(&[a, c, 0.0]) {
+                    let produced = 20.0 * filter.gain(frequency, sampling_rate).log10();
+                    println!("{} {:?} {}: {}", sampling_rate, filter.weighting(), nominal, produced);
+                    assert!(produced <= expected + above && produced >= expected - below);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_process() {
+        const SAMPLE_RATE: usize = 48000;
+
+        // A 100 Hz tone is cut by 19.1 dB by A-weighting, once the filter has settled.
+        let tone: Vec<Sample> = WaveGen::new(WaveFunction::Sine, SAMPLE_RATE, 100.0).take(SAMPLE_RATE).collect();
+
+        let inputs_and_expected = vec![
+            (Weighting::A, -19.1),
+            (Weighting::C, -0.3),
+            (Weighting::Z, 0.0),
+        ];
+
+        for (weighting, expected) in inputs_and_expected {
+            let mut filter = WeightingFilter::new(weighting, SAMPLE_RATE).unwrap();
+            let filtered = filter.process_samples(&tone);
+
+            let rms = |s: &[Sample]| (s.iter().map(|x| x * x).sum::<Sample>() / s.len() as Sample).sqrt();
+            let produced = 20.0 * (rms(&filtered[SAMPLE_RATE / 2..]) / rms(&tone[SAMPLE_RATE / 2..])).log10();
+            println!("{:?}: {}", weighting, produced);
+            assert_approx_eq!(expected, produced, 0.1);
+
+            filter.reset();
+            assert_eq!(filtered[..10], filter.process_samples(&tone[..10])[..]);
+        }
+    }
+
+    #[test]
+    fn test_weigh_spectrum() {
+        // Eight bins of 1 kHz each, with the top three mirroring bins 1 to 3.
+        let spectrum = vec![1.0; 8];
+        let produced = Weighting::A.weigh_spectrum(&spectrum, 8000).unwrap();
+
+        assert_eq!(0.0, produced[0]);
+        assert_approx_eq!(1.0, produced[1]);
+        assert_approx_eq!(Weighting::A.gain(2000.0).powi(2), produced[2]);
+        for i in 1..4 { assert_eq!(produced[i], produced[8 - i]) }
+
+        assert_eq!(spectrum, Weighting::Z.weigh_spectrum(&spectrum, 8000).unwrap());
+        assert_eq!(Some(Error::SamplingRate(0)), Weighting::A.weigh_spectrum(&spectrum, 0).err());
+    }
+
+    #[test]
+    fn test_weigh_bands() {
+        let buckets = Buckets::new(20.0, 20000.0, 3).unwrap();
+        let produced = Weighting::C.weigh_bands(&[1.0, 2.0, 3.0], &buckets).unwrap();
+
+        // The bands are centred on 63.2 Hz, 632 Hz and 6.32 kHz.
+        let expected = [-0.8, 0.0, -2.0];
+        for ((e, p), s) in expected.iter().zip(&produced).zip(&[1.0, 2.0, 3.0]) {
+            assert_approx_eq!(e, 10.0 * (p / s).log10(), 0.1);
+        }
+
+        assert_eq!(Some(Error::InputBuffer(3, 2)), Weighting::C.weigh_bands(&[1.0, 2.0], &buckets).err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(Error::SamplingRate(0)), WeightingFilter::new(Weighting::Z, 0).err());
+        assert_eq!(Some(Error::SamplingRate(16000)), WeightingFilter::new(Weighting::A, 16000).err());
+        assert!(WeightingFilter::new(Weighting::Z, 16000).is_ok());
+    }
+}
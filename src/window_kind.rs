@@ -1,24 +1,179 @@
 use std::f64::consts::PI;
+use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug)]
+use crate::Error;
+use crate::window_metrics::WindowMetrics;
+
+// Coefficients of Blackman's original window, from Blackman and Tukey (1958).
+const CLASSIC_BLACKMAN: [f64; 3] = [0.42, 0.5, 0.08];
+
+// Coefficients of the flat-top window, as used by MATLAB and SciPy.
+const FLAT_TOP: [f64; 5] = [0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368];
+
+// Parameters used when a parameterised window is named without one.
+const DEFAULT_KAISER_BETA: f64 = 8.6;
+const DEFAULT_TUKEY_ALPHA: f64 = 0.5;
+const DEFAULT_GAUSSIAN_SIGMA: f64 = 0.4;
+
+// Zeroth order modified Bessel function of the first kind, summed as a power series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+
+    while term > sum * 1e-17 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+
+    sum
+}
+
+// Sum of cosines with alternating signs, which covers most of the classic windows.
+fn cosine_sum(coefficients: &[f64], len: usize) -> Vec<f64> {
+    let m = (len - 1) as f64;
+
+    (0..len).map(|i| {
+        coefficients
+        .iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * a * (2.0 * PI * k as f64 * i as f64 / m).cos()
+        })
+        .sum()
+    }).collect()
+}
+
+/// Whether a window is symmetric about its centre, as used for filter design,
+/// or periodic, as used for spectral analysis where overlapping windows should add up to a constant.
+/// A periodic window of length N is the first N points of a symmetric window of length N + 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Symmetry {
+    #[default]
+    Symmetric,
+    Periodic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum WindowKind {
+    /// Note that this uses the four-term Blackman-Harris coefficients, and so is the same as `BlackmanHarris`.
     Blackman,
+    BlackmanHarris,
+    /// Blackman window with the three-term coefficients 0.42, 0.5 and 0.08, as used by MATLAB and SciPy.
+    ClassicBlackman,
+    /// Flat-top window, for accurate amplitude readings of tones that fall between bins.
+    FlatTop,
+    /// Gaussian window, with a standard deviation given as a fraction of half the window length.
+    Gaussian(f64),
     Hamming,
     Hanning,
+    /// Kaiser window with the given beta, trading main lobe width for sidelobe level as it increases.
+    Kaiser(f64),
+    Nuttall,
     Rectangular,
     Sine,
     Triangular,
+    /// Tukey window, tapering the given fraction of the window with a cosine and leaving the rest flat.
+    Tukey(f64),
 }
 
 impl WindowKind {
+    /// Generates a symmetric window.
     pub fn generate(&self, len: usize) -> Vec<f64> {
+        self.generate_with_symmetry(len, Symmetry::Symmetric)
+    }
+
+    pub fn generate_with_symmetry(&self, len: usize, symmetry: Symmetry) -> Vec<f64> {
+        // A window of a single point has nothing to taper.
+        if len <= 1 { return vec![1.0; len] }
+
+        match symmetry {
+            Symmetry::Symmetric => self.generate_symmetric(len),
+            Symmetry::Periodic => {
+                let mut window = self.generate_symmetric(len + 1);
+                window.pop();
+                window
+            },
+        }
+    }
+
+    fn generate_symmetric(&self, len: usize) -> Vec<f64> {
         match self {
             &WindowKind::Blackman => apodize::blackman_iter(len).collect(),
+            &WindowKind::BlackmanHarris => apodize::cosine_iter(0.35875, 0.48829, 0.14128, 0.01168, len).collect(),
+            &WindowKind::ClassicBlackman => cosine_sum(&CLASSIC_BLACKMAN, len),
+            &WindowKind::FlatTop => cosine_sum(&FLAT_TOP, len),
+            &WindowKind::Gaussian(sigma) => {
+                let half = (len - 1) as f64 / 2.0;
+                (0..len).map(|i| (-0.5 * ((i as f64 - half) / (sigma * half)).powi(2)).exp()).collect()
+            },
             &WindowKind::Hamming => apodize::hamming_iter(len).collect(),
             &WindowKind::Hanning => apodize::hanning_iter(len).collect(),
+            &WindowKind::Kaiser(beta) => {
+                let m = (len - 1) as f64;
+                (0..len).map(|i| {
+                    let x = 2.0 * i as f64 / m - 1.0;
+                    bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta)
+                }).collect()
+            },
+            &WindowKind::Nuttall => apodize::nuttall_iter(len).collect(),
             &WindowKind::Rectangular => vec![1.0; len],
             &WindowKind::Sine => (0..len).map(|i| (i as f64 / (len - 1) as f64 * PI).sin()).collect(),
             &WindowKind::Triangular => apodize::triangular_iter(len).collect(),
+            &WindowKind::Tukey(alpha) => {
+                let alpha = alpha.clamp(0.0, 1.0);
+                let m = (len - 1) as f64;
+                (0..len).map(|i| {
+                    let x = i as f64 / m;
+                    if x < alpha / 2.0 { 0.5 * (1.0 + (PI * (2.0 * x / alpha - 1.0)).cos()) }
+                    else if x <= 1.0 - alpha / 2.0 { 1.0 }
+                    else { 0.5 * (1.0 + (PI * (2.0 * x / alpha - 2.0 / alpha + 1.0)).cos()) }
+                }).collect()
+            },
+        }
+    }
+
+    /// Measures the metrics of the periodic form of this window at the given length.
+    pub fn metrics(&self, len: usize) -> Result<WindowMetrics, Error> {
+        WindowMetrics::from_window(&self.generate_with_symmetry(len, Symmetry::Periodic))
+    }
+}
+
+impl FromStr for WindowKind {
+    type Err = Error;
+
+    /// Parameterised windows may be followed by a colon and their parameter, such as `kaiser:5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+
+        let (name, param) = match s.find(':') {
+            Some(i) => {
+                let param = s[i + 1..].parse::<f64>().map_err(|_| Error::WindowKind)?;
+                if !(param.is_finite() && param >= 0.0) { Err(Error::WindowKind)? }
+                (&s[..i], Some(param))
+            },
+            None => (s.as_str(), None),
+        };
+
+        match (name, param) {
+            ("blackman", None) => Ok(WindowKind::Blackman),
+            ("blackman-harris", None) | ("blackmanharris", None) => Ok(WindowKind::BlackmanHarris),
+            ("classic-blackman", None) | ("classicblackman", None) => Ok(WindowKind::ClassicBlackman),
+            ("flat-top", None) | ("flattop", None) => Ok(WindowKind::FlatTop),
+            // Zero is rectangular for Kaiser and Tukey windows, but leaves a Gaussian window with no width.
+            ("gaussian", Some(sigma)) if !(sigma > 0.0) => Err(Error::WindowKind),
+            ("gaussian", p) => Ok(WindowKind::Gaussian(p.unwrap_or(DEFAULT_GAUSSIAN_SIGMA))),
+            ("hamming", None) => Ok(WindowKind::Hamming),
+            ("hanning", None) | ("hann", None) => Ok(WindowKind::Hanning),
+            ("kaiser", p) => Ok(WindowKind::Kaiser(p.unwrap_or(DEFAULT_KAISER_BETA))),
+            ("nuttall", None) => Ok(WindowKind::Nuttall),
+            ("rectangular", None) => Ok(WindowKind::Rectangular),
+            ("sine", None) => Ok(WindowKind::Sine),
+            ("triangular", None) => Ok(WindowKind::Triangular),
+            ("tukey", p) => Ok(WindowKind::Tukey(p.unwrap_or(DEFAULT_TUKEY_ALPHA))),
+            _ => Err(Error::WindowKind),
         }
     }
 }
@@ -39,11 +194,21 @@ mod tests {
 
         let inputs_and_expected = vec![
             (WindowKind::Blackman, vec![0.000060000000000004494, 0.03339172347815117, 0.332833504298565, 0.8893697722232837, 0.889369772223284, 0.3328335042985651, 0.03339172347815122, 0.000060000000000004494]),
+            (WindowKind::BlackmanHarris, vec![0.00006000000000000102, 0.03339172347815117, 0.332833504298565, 0.8893697722232837, 0.8893697722232838, 0.3328335042985651, 0.03339172347815122, 0.00006000000000000102]),
+            (WindowKind::ClassicBlackman, vec![-0.000000000000000013877787807814457, 0.09045342435412804, 0.45918295754596355, 0.9203636180999081, 0.9203636180999083, 0.45918295754596383, 0.09045342435412812, -0.000000000000000013877787807814457]),
+            (WindowKind::FlatTop, vec![-0.0004210510000000013, -0.03684078115492348, 0.010703716716153423, 0.7808739149387698, 0.7808739149387701, 0.010703716716153416, -0.03684078115492347, -0.0004210510000000013]),
+            (WindowKind::Gaussian(0.4), vec![0.043936933623407455, 0.20303279625113796, 0.5632793505016535, 0.9382155957191078, 0.9382155957191078, 0.5632793505016535, 0.20303279625113796, 0.043936933623407455]),
             (WindowKind::Hamming, vec![0.08000000000000002, 0.25319469114498255, 0.6423596296199047, 0.9544456792351128, 0.9544456792351128, 0.6423596296199048, 0.25319469114498266, 0.08000000000000002]),
             (WindowKind::Hanning, vec![0.0, 0.1882550990706332, 0.6112604669781572, 0.9504844339512095, 0.9504844339512095, 0.6112604669781573, 0.1882550990706333, 0.0]),
+            (WindowKind::Kaiser(5.0), vec![0.03671089227128667, 0.2706944178894164, 0.6517382352453625, 0.9552473164564361, 0.9552473164564361, 0.6517382352453625, 0.2706944178894164, 0.03671089227128667]),
+            (WindowKind::Kaiser(0.0), vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]),
+            (WindowKind::Nuttall, vec![0.0, 0.031142736797915613, 0.3264168059086425, 0.8876284572934416, 0.8876284572934416, 0.32641680590864275, 0.031142736797915654, 0.0]),
             (WindowKind::Rectangular, vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]),
             (WindowKind::Sine, vec![0.0, 0.4338837391175581, 0.7818314824680298, 0.9749279121818236, 0.9749279121818236, 0.7818314824680299, 0.43388373911755823, 0.00000000000000012246467991473532]),
             (WindowKind::Triangular, vec![0.125, 0.375, 0.625, 0.875, 0.875, 0.625, 0.375, 0.125]),
+            (WindowKind::Tukey(0.5), vec![0.0, 0.6112604669781572, 1.0, 1.0, 1.0, 1.0, 0.6112604669781576, 0.0]),
+            (WindowKind::Tukey(0.0), vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]),
+            (WindowKind::Tukey(1.0), vec![0.0, 0.1882550990706332, 0.6112604669781572, 0.9504844339512095, 0.9504844339512095, 0.6112604669781573, 0.1882550990706333, 0.0]),
         ];
 
         for (input, expected) in inputs_and_expected {
@@ -54,4 +219,119 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_generate_periodic() {
+        const LEN: usize = 8;
+
+        let inputs_and_expected = vec![
+            (WindowKind::Hanning, vec![0.0, 0.1464466094067262, 0.49999999999999994, 0.8535533905932737, 1.0, 0.8535533905932738, 0.5000000000000001, 0.14644660940672632]),
+            (WindowKind::Sine, vec![0.0, 0.3826834323650898, 0.7071067811865475, 0.9238795325112867, 1.0, 0.9238795325112867, 0.7071067811865475, 0.3826834323650899]),
+            (WindowKind::Rectangular, vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            let produced = input.generate_with_symmetry(LEN, Symmetry::Periodic);
+            assert_eq!(LEN, produced.len());
+
+            for (e, p) in expected.into_iter().zip(produced) {
+                assert_approx_eq!(e, p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_short() {
+        let inputs = vec![
+            WindowKind::Blackman,
+            WindowKind::BlackmanHarris,
+            WindowKind::ClassicBlackman,
+            WindowKind::FlatTop,
+            WindowKind::Gaussian(0.4),
+            WindowKind::Hamming,
+            WindowKind::Hanning,
+            WindowKind::Kaiser(5.0),
+            WindowKind::Nuttall,
+            WindowKind::Rectangular,
+            WindowKind::Sine,
+            WindowKind::Triangular,
+            WindowKind::Tukey(0.5),
+        ];
+
+        for input in inputs {
+            for &symmetry in &[Symmetry::Symmetric, Symmetry::Periodic] {
+                assert_eq!(Vec::<f64>::new(), input.generate_with_symmetry(0, symmetry));
+                assert_eq!(vec![1.0], input.generate_with_symmetry(1, symmetry));
+
+                // Periodic windows of two points are generated from symmetric windows of three.
+                assert!(input.generate_with_symmetry(2, symmetry).iter().all(|w| w.is_finite()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_constant_overlap_add() {
+        const LEN: usize = 384;
+
+        // Window kind, hop size and the constant the overlapping windows add up to.
+        let inputs_and_expected = vec![
+            ((WindowKind::Hanning, LEN / 2), 1.0),
+            ((WindowKind::ClassicBlackman, LEN / 3), 3.0 * 0.42),
+            ((WindowKind::BlackmanHarris, LEN / 4), 4.0 * 0.35875),
+        ];
+
+        for ((window_kind, hop), expected) in inputs_and_expected {
+            let window = window_kind.generate_with_symmetry(LEN, Symmetry::Periodic);
+
+            // Every sample of a window is covered by exactly LEN / hop overlapping windows.
+            for i in 0..hop {
+                let produced: f64 = window.iter().skip(i).step_by(hop).sum();
+                assert_approx_eq!(expected, produced);
+            }
+
+            // The symmetric window has one point too many, so it doesn't quite add up.
+            let window = window_kind.generate(LEN);
+            let sums: Vec<f64> = (0..hop).map(|i| window.iter().skip(i).step_by(hop).sum()).collect();
+            assert!(sums.iter().any(|s| (s - expected).abs() > 1e-4));
+        }
+    }
+
+    #[test]
+    fn test_from_str() {
+        let inputs_and_expected = vec![
+            ("blackman", Ok(WindowKind::Blackman)),
+            ("Hamming", Ok(WindowKind::Hamming)),
+            ("hann", Ok(WindowKind::Hanning)),
+            ("hanning", Ok(WindowKind::Hanning)),
+            ("RECTANGULAR", Ok(WindowKind::Rectangular)),
+            ("sine", Ok(WindowKind::Sine)),
+            ("triangular", Ok(WindowKind::Triangular)),
+            ("blackman-harris", Ok(WindowKind::BlackmanHarris)),
+            ("Classic-Blackman", Ok(WindowKind::ClassicBlackman)),
+            ("flattop", Ok(WindowKind::FlatTop)),
+            ("Nuttall", Ok(WindowKind::Nuttall)),
+            ("kaiser", Ok(WindowKind::Kaiser(8.6))),
+            ("kaiser:5", Ok(WindowKind::Kaiser(5.0))),
+            ("tukey:0.25", Ok(WindowKind::Tukey(0.25))),
+            ("kaiser:0", Ok(WindowKind::Kaiser(0.0))),
+            ("tukey:0", Ok(WindowKind::Tukey(0.0))),
+            ("gaussian:0", Err(Error::WindowKind)),
+            ("kaiser:-1", Err(Error::WindowKind)),
+            ("gaussian", Ok(WindowKind::Gaussian(0.4))),
+            ("gaussian-ish", Err(Error::WindowKind)),
+            ("gaussian:", Err(Error::WindowKind)),
+            ("gaussian:-1", Err(Error::WindowKind)),
+            ("hamming:2", Err(Error::WindowKind)),
+        ];
+
+        for (input, expected) in inputs_and_expected {
+            assert_eq!(expected, input.parse::<WindowKind>());
+        }
+
+        // Both are rectangular with a parameter of zero.
+        for input in &["kaiser:0", "tukey:0"] {
+            let produced = input.parse::<WindowKind>().unwrap().generate(8);
+            assert!(produced.iter().all(|&w| (w - 1.0).abs() < 1e-12));
+        }
+    }
 }
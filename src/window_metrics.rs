@@ -0,0 +1,150 @@
+//! Figures of merit for window functions, as described in Harris, "On the Use of Windows for
+//! Harmonic Analysis with the Discrete Fourier Transform" (1978).
+
+use rustfft::FFTplanner;
+use rustfft::num_complex::Complex;
+use rustfft::num_traits::Zero;
+
+use crate::Error;
+
+// How many points the window's spectrum is sampled at per bin.
+const OVERSAMPLE: usize = 64;
+
+/// Describes how a window affects the spectrum it is applied to.
+/// Bandwidths are measured in bins, and losses and levels in decibels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowMetrics {
+    /// The average value of the window, which scales the amplitude of a tone.
+    pub coherent_gain: f64,
+
+    /// The width of a rectangular filter that would let through the same amount of white noise.
+    pub enbw: f64,
+
+    /// The ratio of the output to input signal-to-noise ratios for a tone in white noise,
+    /// which is the length of the window divided by the ENBW.
+    pub processing_gain: f64,
+
+    /// How much quieter a tone halfway between two bins reads than one centred on a bin.
+    pub scalloping_loss: f64,
+
+    /// The level of the highest sidelobe, relative to the peak of the main lobe.
+    pub highest_sidelobe: f64,
+
+    /// The width of the main lobe at half power.
+    pub bandwidth_3db: f64,
+}
+
+impl WindowMetrics {
+    /// Measures the metrics of a window, given its values.
+    pub fn from_window(window: &[f64]) -> Result<Self, Error> {
+        let len = window.len();
+        if !(len > 0) { Err(Error::WindowLength)? }
+
+        let sum: f64 = window.iter().sum();
+        let sum_sq: f64 = window.iter().map(|w| w * w).sum();
+
+        let coherent_gain = sum / len as f64;
+        let enbw = len as f64 * sum_sq / (sum * sum);
+        let processing_gain = sum * sum / sum_sq;
+
+        // Sample the spectrum of the window finely, by zero padding it.
+        let padded_len = len * OVERSAMPLE;
+        let mut input: Vec<_> = window.iter().map(|&w| Complex::new(w, 0.0)).collect();
+        input.resize(padded_len, Complex::zero());
+        let mut output = vec![Complex::zero(); padded_len];
+
+        FFTplanner::new(false).plan_fft(padded_len).process(&mut input, &mut output);
+
+        let magnitudes: Vec<f64> = output.iter().take(padded_len / 2 + 1).map(|c| c.norm()).collect();
+        let peak = magnitudes[0];
+        let to_db = |m: f64| 20.0 * (m / peak).log10();
+
+        let scalloping_loss = -to_db(magnitudes[OVERSAMPLE / 2]);
+
+        // Find where the main lobe drops below half power, interpolating between samples.
+        let half_power = peak / 2.0f64.sqrt();
+        let cutoff = magnitudes.iter().position(|&m| m < half_power).unwrap_or(magnitudes.len() - 1);
+        let bandwidth_3db = match cutoff {
+            0 => 0.0,
+            k => {
+                let (a, b) = (magnitudes[k - 1], magnitudes[k]);
+                let t = if a > b { (a - half_power) / (a - b) } else { 0.0 };
+                2.0 * ((k - 1) as f64 + t) / OVERSAMPLE as f64
+            },
+        };
+
+        // The main lobe ends at the first minimum past the half power point,
+        // which skips over any ripple on top of a flat main lobe.
+        let lobe_end = (cutoff..magnitudes.len().saturating_sub(1)).find(|&k| magnitudes[k] <= magnitudes[k + 1]);
+        let highest_sidelobe = match lobe_end {
+            Some(k) => to_db(magnitudes[k..].iter().cloned().fold(0.0, f64::max)),
+            None => f64::NEG_INFINITY,
+        };
+
+        Ok(Self {
+            coherent_gain,
+            enbw,
+            processing_gain,
+            scalloping_loss,
+            highest_sidelobe,
+            bandwidth_3db,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::window_kind::WindowKind;
+
+    const LEN: usize = 1024;
+
+    #[test]
+    fn test_from_window() {
+        // Coherent gain, ENBW, 3 dB bandwidth, scalloping loss and highest sidelobe, from table 1 of Harris (1978).
+        // The Kaiser-Bessel window with alpha = 3 in that table has a beta of 3 pi.
+        // The table lists a scalloping loss of 1.78 dB for Hamming, but working it out directly gives 1.75 dB,
+        // and likewise a 3 dB bandwidth of 1.68 bins for Blackman, which works out to 1.64 bins.
+        let inputs_and_expected = vec![
+            (WindowKind::Rectangular, (1.00, 1.00, 0.89, 3.92, -13.0)),
+            (WindowKind::Triangular, (0.50, 1.33, 1.28, 1.82, -27.0)),
+            (WindowKind::Hanning, (0.50, 1.50, 1.44, 1.42, -32.0)),
+            (WindowKind::Hamming, (0.54, 1.36, 1.30, 1.75, -43.0)),
+            (WindowKind::ClassicBlackman, (0.42, 1.73, 1.64, 1.10, -58.0)),
+            (WindowKind::BlackmanHarris, (0.36, 2.00, 1.90, 0.83, -92.0)),
+            (WindowKind::Kaiser(3.0 * std::f64::consts::PI), (0.40, 1.80, 1.71, 1.02, -69.0)),
+        ];
+
+        for (input, (cg, enbw, bw, scallop, sidelobe)) in inputs_and_expected {
+            let produced = input.metrics(LEN).unwrap();
+            println!("{:?}: {:?}", input, produced);
+
+            // The table is rounded to two decimal places, and to the nearest decibel for sidelobes.
+            assert!((cg - produced.coherent_gain).abs() < 0.01);
+            assert!((enbw - produced.enbw).abs() < 0.01);
+            assert!((bw - produced.bandwidth_3db).abs() < 0.01);
+            assert!((scallop - produced.scalloping_loss).abs() < 0.01);
+            assert!((sidelobe - produced.highest_sidelobe).abs() < 1.0);
+            assert_approx_eq!(LEN as f64 / produced.enbw, produced.processing_gain);
+        }
+    }
+
+    #[test]
+    fn test_flat_top() {
+        // The flat-top window gives up resolution in exchange for almost no scalloping loss.
+        let produced = WindowKind::FlatTop.metrics(LEN).unwrap();
+        println!("{:?}", produced);
+
+        assert!((0.2156 - produced.coherent_gain).abs() < 0.001);
+        assert!((3.77 - produced.enbw).abs() < 0.01);
+        assert!(produced.scalloping_loss.abs() < 0.01);
+        assert!(produced.highest_sidelobe < -90.0);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(Some(Error::WindowLength), WindowMetrics::from_window(&[]).err());
+        assert_eq!(Some(Error::WindowLength), WindowKind::Hanning.metrics(0).err());
+    }
+}
@@ -0,0 +1,373 @@
+#!/usr/bin/env python3
+"""Generates tiny FLAC, MP3 and Ogg Vorbis tone fixtures without any external encoder.
+
+The FLAC file stores verbatim 16-bit samples. The MP3 and Ogg Vorbis files are built by placing a single
+nonzero coefficient directly into the decoder's transform domain, which yields a tone at that bin's
+centre frequency without needing an actual encoder.
+"""
+import math
+import os
+import struct
+import sys
+
+
+class MsbWriter:
+    def __init__(self):
+        self.bits = []
+
+    def write(self, value, n):
+        for i in reversed(range(n)):
+            self.bits.append((value >> i) & 1)
+
+    def align(self):
+        while len(self.bits) % 8:
+            self.bits.append(0)
+
+    def bytes(self):
+        self.align()
+        out = bytearray()
+        for i in range(0, len(self.bits), 8):
+            b = 0
+            for bit in self.bits[i:i + 8]:
+                b = (b << 1) | bit
+            out.append(b)
+        return bytes(out)
+
+
+class LsbWriter:
+    def __init__(self):
+        self.bits = []
+
+    def write(self, value, n):
+        for i in range(n):
+            self.bits.append((value >> i) & 1)
+
+    def bytes(self):
+        bits = self.bits + [0] * (-len(self.bits) % 8)
+        out = bytearray()
+        for i in range(0, len(bits), 8):
+            b = 0
+            for j, bit in enumerate(bits[i:i + 8]):
+                b |= bit << j
+            out.append(b)
+        return bytes(out)
+
+
+# ---------------------------------------------------------------- FLAC
+
+def crc8(data):
+    crc = 0
+    for b in data:
+        crc ^= b
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x07) & 0xff if crc & 0x80 else (crc << 1) & 0xff
+    return crc
+
+
+def crc16(data):
+    crc = 0
+    for b in data:
+        crc ^= b << 8
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x8005) & 0xffff if crc & 0x8000 else (crc << 1) & 0xffff
+    return crc
+
+
+def flac(path, rate, freqs, num_blocks, block_size=4096):
+    total = num_blocks * block_size
+    channels = [[int(round(0.25 * 32767 * math.sin(2 * math.pi * f * n / rate))) for n in range(total)] for f in freqs]
+
+    out = bytearray(b"fLaC")
+    w = MsbWriter()
+    w.write(1, 1)
+    w.write(0, 7)
+    w.write(34, 24)
+    w.write(block_size, 16)
+    w.write(block_size, 16)
+    w.write(0, 24)
+    w.write(0, 24)
+    w.write(rate, 20)
+    w.write(len(channels) - 1, 3)
+    w.write(15, 5)
+    w.write(total, 36)
+    w.write(0, 128)
+    out += w.bytes()
+
+    rate_codes = {88200: 1, 176400: 2, 192000: 3, 8000: 4, 16000: 5, 22050: 6, 24000: 7, 32000: 8, 44100: 9, 48000: 10, 96000: 11}
+    for frame in range(num_blocks):
+        w = MsbWriter()
+        w.write(0b11111111111110, 14)
+        w.write(0, 1)
+        w.write(0, 1)
+        w.write(12, 4)
+        w.write(rate_codes[rate], 4)
+        w.write(len(channels) - 1, 4)
+        w.write(4, 3)
+        w.write(0, 1)
+        assert frame < 128
+        w.write(frame, 8)
+        header = w.bytes()
+        header += bytes([crc8(header)])
+
+        w = MsbWriter()
+        for ch in channels:
+            w.write(0, 1)
+            w.write(1, 6)
+            w.write(0, 1)
+            for s in ch[frame * block_size:(frame + 1) * block_size]:
+                w.write(s & 0xffff, 16)
+        body = header + w.bytes()
+        out += body + struct.pack(">H", crc16(body))
+
+    open(path, "wb").write(out)
+
+
+# ---------------------------------------------------------------- MP3
+
+def mp3(path, line, num_frames, global_gain, signs):
+    # MPEG-1 Layer III, 48 kHz, 64 kbps, mono: 192 byte frames with no padding.
+    out = bytearray()
+    frame_len = 192
+    for frame in range(num_frames):
+        w = MsbWriter()
+        w.write(0x7ff, 11)
+        w.write(0b11, 2)
+        w.write(0b01, 2)
+        w.write(1, 1)
+        w.write(5, 4)
+        w.write(0b01, 2)
+        w.write(0, 1)
+        w.write(0, 1)
+        w.write(0b11, 2)
+        w.write(0, 2)
+        w.write(0, 1)
+        w.write(1, 1)
+        w.write(0, 2)
+
+        quads = line // 4 + 1
+        part23 = quads * 4 + 1
+
+        # Side information.
+        w.write(0, 9)
+        w.write(0, 5)
+        w.write(0, 4)
+        for gr in range(2):
+            w.write(part23, 12)
+            w.write(0, 9)
+            w.write(global_gain, 8)
+            w.write(0, 4)
+            w.write(0, 1)
+            for _ in range(3):
+                w.write(0, 5)
+            w.write(0, 4)
+            w.write(0, 3)
+            w.write(0, 1)
+            w.write(0, 1)
+            w.write(1, 1)
+
+        # Main data, using count1 table B, which is just the inverted nonzero flags.
+        for gr in range(2):
+            granule = frame * 2 + gr
+            for q in range(quads):
+                nz = [4 * q + i == line for i in range(4)]
+                value = sum(1 << (3 - i) for i in range(4) if nz[i])
+                w.write(15 - value, 4)
+                if any(nz):
+                    w.write(1 if signs(granule) < 0 else 0, 1)
+
+        data = w.bytes()
+        assert len(data) <= frame_len
+        out += data + bytes(frame_len - len(data))
+
+    open(path, "wb").write(out)
+
+
+# ---------------------------------------------------------------- Ogg Vorbis
+
+def ogg_crc(data):
+    crc = 0
+    for b in data:
+        crc ^= b << 24
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x04c11db7) & 0xffffffff if crc & 0x80000000 else (crc << 1) & 0xffffffff
+    return crc
+
+
+def ogg_page(packets, granule, seq, flags, serial=0x766f78):
+    segments = []
+    for p in packets:
+        n = len(p)
+        while n >= 255:
+            segments.append(255)
+            n -= 255
+        segments.append(n)
+    assert len(segments) <= 255
+    header = b"OggS" + struct.pack("<BBqIIIB", 0, flags, granule, serial, seq, 0, len(segments)) + bytes(segments)
+    page = bytearray(header + b"".join(packets))
+    struct.pack_into("<I", page, 22, ogg_crc(page))
+    return bytes(page)
+
+
+def float32_pack(v):
+    if v == 0:
+        return 0
+    sign = 0x80000000 if v < 0 else 0
+    mant, exp = abs(v), 0
+    while mant != int(mant):
+        mant *= 2
+        exp -= 1
+    return sign | (int(mant) & 0x1fffff) | ((exp + 788) << 21)
+
+
+def codebook(w, lengths, lookup=None):
+    w.write(0x564342, 24)
+    w.write(1, 16)
+    w.write(len(lengths), 24)
+    w.write(0, 1)
+    w.write(0, 1)
+    for l in lengths:
+        w.write(l - 1, 5)
+    if lookup is None:
+        w.write(0, 4)
+    else:
+        minimum, delta, value_bits, multiplicands = lookup
+        w.write(1, 4)
+        w.write(float32_pack(minimum), 32)
+        w.write(float32_pack(delta), 32)
+        w.write(value_bits - 1, 4)
+        w.write(0, 1)
+        for m in multiplicands:
+            w.write(m, value_bits)
+
+
+def vorbis(path, rate, bin_index, num_packets, signs, blocksize_exp=8, partition_size=8):
+    n = 1 << blocksize_exp
+    half = n // 2
+
+    ident = LsbWriter()
+    ident.write(1, 8)
+    for c in b"vorbis":
+        ident.write(c, 8)
+    ident.write(0, 32)
+    ident.write(1, 8)
+    ident.write(rate, 32)
+    ident.write(0, 32)
+    ident.write(0, 32)
+    ident.write(0, 32)
+    ident.write(blocksize_exp, 4)
+    ident.write(blocksize_exp, 4)
+    ident.write(1, 1)
+
+    comment = LsbWriter()
+    comment.write(3, 8)
+    for c in b"vorbis":
+        comment.write(c, 8)
+    vendor = b"voxane"
+    comment.write(len(vendor), 32)
+    for c in vendor:
+        comment.write(c, 8)
+    comment.write(0, 32)
+    comment.write(1, 1)
+
+    setup = LsbWriter()
+    setup.write(5, 8)
+    for c in b"vorbis":
+        setup.write(c, 8)
+    # Codebooks: the residue classbook, and a VQ book holding the values -1, 0 and 1.
+    setup.write(2 - 1, 8)
+    codebook(setup, [1, 1])
+    codebook(setup, [2, 1, 2], (-1, 1, 2, [0, 1, 2]))
+    # Time domain transforms (placeholders).
+    setup.write(0, 6)
+    setup.write(0, 16)
+    # A type 1 floor with no partitions, so just the two endpoints.
+    setup.write(0, 6)
+    setup.write(1, 16)
+    setup.write(0, 5)
+    setup.write(1 - 1, 2)
+    setup.write(blocksize_exp - 1, 4)
+    # A type 1 residue with two classifications: silent, and coded with the VQ book.
+    setup.write(0, 6)
+    setup.write(1, 16)
+    setup.write(0, 24)
+    setup.write(half, 24)
+    setup.write(partition_size - 1, 24)
+    setup.write(2 - 1, 6)
+    setup.write(0, 8)
+    setup.write(0, 3)
+    setup.write(0, 1)
+    setup.write(1, 3)
+    setup.write(0, 1)
+    setup.write(1, 8)
+    # A single mapping with no coupling.
+    setup.write(0, 6)
+    setup.write(0, 16)
+    setup.write(0, 1)
+    setup.write(0, 1)
+    setup.write(0, 2)
+    setup.write(0, 8)
+    setup.write(0, 8)
+    setup.write(0, 8)
+    # A single short-block mode.
+    setup.write(0, 6)
+    setup.write(0, 1)
+    setup.write(0, 16)
+    setup.write(0, 16)
+    setup.write(0, 8)
+    setup.write(1, 1)
+
+    packets = []
+    partvals = half // partition_size
+    target = bin_index // partition_size
+    for p in range(num_packets):
+        a = LsbWriter()
+        a.write(0, 1)
+        # Floor: nonzero, flat at the loudest level.
+        a.write(1, 1)
+        a.write(255, 8)
+        a.write(255, 8)
+        # Residue: one classword per partition, then the partition's values.
+        for part in range(partvals):
+            a.write(1 if part == target else 0, 1)
+            if part == target:
+                for i in range(partition_size):
+                    idx = part * partition_size + i
+                    if idx == bin_index:
+                        # Entry 0 (-1) is "00", entry 2 (+1) is "01" when read MSB-first.
+                        if signs(p) < 0:
+                            a.write(0, 1)
+                            a.write(0, 1)
+                        else:
+                            a.write(0, 1)
+                            a.write(1, 1)
+                    else:
+                        # Entry 1 (0) is "1".
+                        a.write(1, 1)
+        packets.append(a.bytes())
+
+    out = bytearray()
+    out += ogg_page([ident.bytes()], 0, 0, 0x02)
+    out += ogg_page([comment.bytes(), setup.bytes()], 0, 1, 0)
+    seq = 2
+    per_page = 32
+    for start in range(0, num_packets, per_page):
+        chunk = packets[start:start + per_page]
+        done = start + len(chunk)
+        granule = (done - 1) * (n // 2)
+        flags = 0x04 if done == num_packets else 0
+        out += ogg_page(chunk, granule, seq, flags)
+        seq += 1
+
+    open(path, "wb").write(out)
+
+
+if __name__ == "__main__":
+    here = os.path.dirname(os.path.abspath(__file__))
+
+    # Flipping the sign every other block keeps the synthesized coefficient phase-continuous,
+    # so that the decoded signal is a steady tone at the centre of its transform bin.
+    signs = lambda i: -1 if (i // 2) % 2 else 1
+
+    flac(os.path.join(here, "sin_440hz_1000hz_44100hz_stereo.flac"), 44100, [440.0, 1000.0], 2)
+    mp3(os.path.join(here, "sin_1896hz_48000hz_mono.mp3"), 45, 40, 190, signs)
+    vorbis(os.path.join(here, "sin_2562hz_32000hz_mono.ogg"), 32000, 20, 250, signs)
@@ -0,0 +1,79 @@
+#!/usr/bin/env python3
+"""Generates reference mel spectrogram and MFCC values for a chirp with librosa.
+
+Requires librosa 0.10.2.post1 and its dependencies:
+
+    pip install librosa==0.10.2.post1
+
+The Slaney fixture uses `htk=False, norm='slaney'`, which are librosa's defaults,
+and the HTK fixture uses `htk=True, norm=None`, matching HTK and Kaldi.
+Frames are not centred, so the first frame starts at the first sample, as with `Analyzer`.
+`power_to_db` limits the whole spectrogram to 80 dB below its loudest band, rather than each frame on its own.
+Deltas use `mode='nearest'`, which repeats the first and last frames at the edges,
+and delta-deltas are the deltas of the deltas.
+"""
+import math
+import os
+
+import librosa
+import numpy as np
+
+LIBROSA_VERSION = "0.10.2.post1"
+
+SAMPLE_RATE = 16000
+FFT_LEN = 1024
+HOP = 512
+NUM_FRAMES = 15
+NUM_MELS = 40
+NUM_MFCC = 13
+DELTA_WIDTH = 2
+
+# A linear chirp from 200 to 4000 Hz, with an amplitude of 0.25, summed the same way as `WaveGen`.
+CHIRP_START = 200.0
+CHIRP_END = 4000.0
+CHIRP_LEN = FFT_LEN + HOP * (NUM_FRAMES - 1)
+
+
+def chirp():
+    out = np.zeros(CHIRP_LEN)
+    phase = 0.0
+    for n in range(CHIRP_LEN):
+        out[n] = 0.25 * math.sin(2 * math.pi * phase)
+        frequency = CHIRP_START + (CHIRP_END - CHIRP_START) * n / CHIRP_LEN
+        phase = (phase + frequency / SAMPLE_RATE) % 1.0
+    return out
+
+
+def write(path, sections):
+    with open(path, "w") as f:
+        for name, rows in sections:
+            f.write("# {}\n".format(name))
+            for row in rows:
+                f.write(" ".join("{:.6f}".format(v) for v in row) + "\n")
+
+
+if __name__ == "__main__":
+    assert librosa.__version__ == LIBROSA_VERSION, "expected librosa {}, found {}".format(LIBROSA_VERSION, librosa.__version__)
+    here = os.path.dirname(os.path.abspath(__file__))
+
+    signal = chirp()
+
+    for name, htk, norm in [("slaney", False, "slaney"), ("htk", True, None)]:
+        mel = librosa.feature.melspectrogram(
+            y=signal, sr=SAMPLE_RATE, n_fft=FFT_LEN, hop_length=HOP, window="hann", center=False, power=2.0,
+            n_mels=NUM_MELS, fmin=0.0, fmax=SAMPLE_RATE / 2, htk=htk, norm=norm,
+        )
+        assert mel.shape == (NUM_MELS, NUM_FRAMES)
+
+        mel_db = librosa.power_to_db(mel, ref=1.0, amin=1e-10, top_db=80.0)
+        mfcc = librosa.feature.mfcc(S=mel_db, n_mfcc=NUM_MFCC, dct_type=2, norm="ortho")
+        delta = librosa.feature.delta(mfcc, width=2 * DELTA_WIDTH + 1, order=1, mode="nearest")
+        delta_delta = librosa.feature.delta(delta, width=2 * DELTA_WIDTH + 1, order=1, mode="nearest")
+
+        # librosa puts frames in columns, and the fixtures put them in rows.
+        write(os.path.join(here, "fixtures", "mfcc_{}.txt".format(name)), [
+            ("mel_db", mel_db.T),
+            ("mfcc", mfcc.T),
+            ("delta", delta.T),
+            ("delta_delta", delta_delta.T),
+        ])